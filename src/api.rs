@@ -1,267 +1,5655 @@
-use rocket::fs::TempFile;
-use rocket::http::Status;
-use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::data::{self, Data, DataStream, FromData, IoHandler, IoStream, Limits, ToByteUnit};
+use rocket::http::{ContentType, Header, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::stream::{ByteStream, Event, EventStream};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::{json::json, json::Json, json::Value, Deserialize, Serialize};
 use rocket::State;
 
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::stream::{BoxStream, StreamExt};
+use futures::SinkExt;
+use rand::RngCore;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::Message;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::api_error::{docker_status, ApiError};
+use crate::api_keys::{ApiKey, ApiKeys};
+use crate::audit::{AuditEntry, AuditLog};
+use crate::authorization::{authorize, is_visible, Action, Grant, Writable};
+use crate::capabilities::Capabilities;
+use crate::checksum::{verify_file, Checksum, VerifyingReader};
+use crate::config_file::{self, check_deployment, save_deployments_file, Config};
+use crate::docker_client::with_timeout;
+use crate::docker_error::DockerError;
+use crate::docker_structs::{
+    CreateNetwork, CreateVolume, ExecResult, LogSource, ProgressMessage, UpdateContainer,
+};
+use crate::engine::EngineReport;
+use crate::history::{History, HistoryEntry};
+use crate::identity::ClientIdentity;
+use crate::jobs::{Job, Jobs};
+use crate::manager::{Change, ImageMetadata, Manager, DEPLOYMENT_LABEL, MANAGED_LABEL};
+use crate::metrics::{self, Exposition};
+use crate::openapi::ApiDoc;
+use crate::run_args;
+use crate::runtime::{Attached, ContainerRuntime, Progress, SharedRuntime};
+use crate::self_stats::SelfStats;
+use crate::uploads::{Upload, UploadError, Uploads};
+use crate::websocket::{self, WebSocketKey};
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = Deployment)]
+#[serde(crate = "rocket::serde")]
+pub struct Deployments {
+    pub name: String,
+    pub state: String,
+    pub image: String,
+    pub health: String,
+    pub disabled: bool,
+    pub digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// What the deployment image declares, once it is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_config: Option<ImageConfig>,
+}
+
+impl Deployments {
+    fn new(config: &Config, deployment: &crate::manager::Deployment) -> Self {
+        Deployments {
+            name: deployment.name.to_owned(),
+            state: deployment.state.to_string(),
+            image: deployment.image.to_string(),
+            health: deployment.health.to_owned(),
+            disabled: config.is_disabled(&deployment.name),
+            digest: deployment.digest.to_owned(),
+            group: config.group(&deployment.name),
+            image_config: deployment.metadata.as_ref().map(|m| m.into()),
+        }
+    }
+}
+
+/// A deployment with what docker reports about its container, for troubleshooting
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DeploymentDetail {
+    #[serde(flatten)]
+    pub deployment: Deployments,
+    /// None when the deployment has no container, the rest are then None or empty too
+    pub container_id: Option<String>,
+    pub image_id: Option<String>,
+    /// registry/repo@digest for each registry the image was pulled from
+    pub image_digests: Vec<String>,
+    pub ports: Vec<ContainerPort>,
+    /// RFC 3339 timestamps
+    pub created: Option<String>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    /// Seconds since the container started, while it is running
+    pub uptime: Option<u64>,
+    pub restart_count: i64,
+    /// Exit code of the last run, once the container has stopped at least once
+    pub exit_code: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ContainerPort {
+    /// i.e. 80/tcp
+    pub port: String,
+    /// host_ip:host_port the port is published on, empty if it is only exposed
+    pub published: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImageConfig {
+    pub exposed_ports: Vec<String>,
+    pub healthcheck: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl From<&ImageMetadata> for ImageConfig {
+    fn from(metadata: &ImageMetadata) -> Self {
+        ImageConfig {
+            exposed_ports: metadata.exposed_ports.to_owned(),
+            healthcheck: metadata.healthcheck.to_owned(),
+            entrypoint: metadata.entrypoint.to_owned(),
+            labels: metadata.labels.to_owned(),
+        }
+    }
+}
+
+/// Items returned by a list endpoint when the client does not give a limit, and the most it
+/// may ask for
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// One page of a list, the X-Total-Count header has the length of the whole list
+pub struct Page<T> {
+    items: Vec<T>,
+    total: usize,
+}
+
+impl<T> Page<T> {
+    /// The `limit` items starting at `offset`
+    pub fn new(items: Vec<T>, offset: Option<usize>, limit: Option<usize>) -> Self {
+        let total = items.len();
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let items = items
+            .into_iter()
+            .skip(offset.unwrap_or(0))
+            .take(limit)
+            .collect();
+
+        Page { items, total }
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Page<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(Json(self.items).respond_to(request)?)
+            .raw_header("X-Total-Count", self.total.to_string())
+            .ok()
+    }
+}
+
+/// Which deployments are listed, and in what order
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeploymentsQuery {
+    /// Only deployments in this state
+    state: Option<String>,
+    /// Only deployments with this health, i.e. unhealthy
+    health: Option<String>,
+    /// Only disabled, or only enabled, deployments
+    disabled: Option<bool>,
+    /// Sort order, prefixed with - for descending
+    sort: Option<String>,
+    /// Items to skip
+    offset: Option<usize>,
+    /// Items to return
+    limit: Option<usize>,
+}
+
+/// Deployments the client has an authorization rule for, or every deployment if there are no
+/// rules
+///
+/// `state`, `health` and `disabled` only return the deployments which match, `sort` orders
+/// them by `name`, `state`, `health` or `image`, prefixed with `-` for descending order.
+/// `offset` and `limit` select a page of the deployments
+#[utoipa::path(
+    tag = "deployments",
+    params(
+        DeploymentsQuery,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Deployments",
+            body = [Deployments],
+            headers(("X-Total-Count" = usize, description = "Number of items in the whole list"))
+        ),
+        (status = 400, description = "Unknown sort field", body = ApiError),
+    )
+)]
+#[get("/deployments?<query..>")]
+pub async fn get_deployments(
+    query: DeploymentsQuery,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Page<Deployments>, ApiError> {
+    let DeploymentsQuery {
+        state,
+        health,
+        disabled,
+        sort,
+        offset,
+        limit,
+    } = query;
+    let (sort, descending) = match sort.as_deref() {
+        Some(sort) => match sort.strip_prefix('-') {
+            Some(sort) => (Some(sort), true),
+            None => (Some(sort), false),
+        },
+        None => (None, false),
+    };
+    if let Some(sort) = sort {
+        if ["name", "state", "health", "image"].contains(&sort) == false {
+            return Err(ApiError::new(
+                Status::BadRequest,
+                format!(
+                    "Cannot sort by '{}', use name, state, health or image",
+                    sort
+                ),
+            ));
+        }
+    }
+
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let mut result = manager
+        .deployments()
+        .iter()
+        .filter(|d| is_visible(config, &identity, &d.name))
+        .map(|d| Deployments::new(config, d))
+        .filter(|d| state.as_ref().map(|s| s == &d.state) != Some(false))
+        .filter(|d| health.as_ref().map(|h| h == &d.health) != Some(false))
+        .filter(|d| disabled.map(|x| x == d.disabled) != Some(false))
+        .collect::<Vec<Deployments>>();
+
+    match sort {
+        Some("state") => result.sort_by(|a, b| a.state.cmp(&b.state).then(a.name.cmp(&b.name))),
+        Some("health") => result.sort_by(|a, b| a.health.cmp(&b.health).then(a.name.cmp(&b.name))),
+        Some("image") => result.sort_by(|a, b| a.image.cmp(&b.image).then(a.name.cmp(&b.name))),
+        Some(_) => result.sort_by(|a, b| a.name.cmp(&b.name)),
+        None => {}
+    }
+    if descending {
+        result.reverse();
+    }
+
+    Ok(Page::new(result, offset, limit))
+}
+
+#[utoipa::path(
+    tag = "deployments",
+    summary = "Get a deployment",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Deployment", body = DeploymentDetail),
+        (
+            status = 404,
+            description = "No such deployment, or the client has no authorization rule for it",
+            body = ApiError
+        ),
+    )
+)]
+#[get("/deployments/<name>")]
+pub async fn get_deployment(
+    name: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<DeploymentDetail>), ApiError> {
+    if is_visible(config, &identity, &name) == false {
+        return Err(Status::NotFound.into());
+    }
+
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    // The image is inspected the first time it is asked for if it has not been started since
+    // the daemon started
+    let unknown_image = manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == name && d.metadata.is_none() && d.image.is_empty() == false)
+        .map(|d| d.image.to_owned());
+    if let Some(image) = unknown_image {
+        let metadata = image_metadata(docker.as_ref(), &image).await;
+        manager.set_metadata(&name, metadata);
+    }
+
+    let deployment = match manager.deployments().iter().find(|d| d.name == name) {
+        Some(deployment) => deployment.clone(),
+        None => return Err(Status::NotFound.into()),
+    };
+    drop(manager);
+
+    let mut detail = DeploymentDetail {
+        deployment: Deployments::new(config, &deployment),
+        container_id: None,
+        image_id: None,
+        image_digests: vec![],
+        ports: vec![],
+        created: None,
+        started_at: None,
+        finished_at: None,
+        uptime: None,
+        restart_count: 0,
+        exit_code: None,
+    };
+    if deployment.id.is_empty() {
+        return Ok((Status::Ok, Json(detail)));
+    }
+
+    // The container may be removed between the update and the inspect, the deployment is
+    // then reported without a container
+    let container = match docker.inspect_running_container(&deployment.id).await {
+        Ok(container) => container,
+        Err(DockerError::NotFound(_)) => return Ok((Status::Ok, Json(detail))),
+        Err(e) => return Err(e.into()),
+    };
+    let image = docker.inspect_image(&container.image).await?;
+
+    let started = unix_seconds(&container.state.started_at);
+    let finished = unix_seconds(&container.state.finished_at);
+    detail.container_id = Some(container.id);
+    detail.image_id = Some(container.image);
+    detail.image_digests = image.and_then(|i| i.repo_digests).unwrap_or_default();
+    detail.ports = container
+        .network_settings
+        .and_then(|n| n.ports)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(port, bindings)| ContainerPort {
+            port,
+            published: bindings
+                .unwrap_or_default()
+                .iter()
+                .map(|b| format!("{}:{}", b.host_ip, b.host_port))
+                .collect(),
+        })
+        .collect();
+    detail.created = Some(container.created);
+    detail.started_at = started.map(|_| container.state.started_at.to_owned());
+    detail.finished_at = finished.map(|_| container.state.finished_at.to_owned());
+    detail.uptime = match container.state.running {
+        true => started.map(|s| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|n| n.as_secs())
+                .unwrap_or_default()
+                .saturating_sub(s)
+        }),
+        false => None,
+    };
+    detail.restart_count = container.restart_count;
+    detail.exit_code = match container.state.running == false && finished.is_some() {
+        true => Some(container.state.exit_code),
+        false => None,
+    };
+
+    Ok((Status::Ok, Json(detail)))
+}
+
+/// Seconds since the unix epoch of a docker RFC 3339 timestamp, i.e.
+/// "2024-01-31T10:12:45.123456789Z". None for docker's zero time, "0001-01-01T00:00:00Z"
+fn unix_seconds(timestamp: &str) -> Option<u64> {
+    let number =
+        |range: std::ops::Range<usize>| -> Option<i64> { timestamp.get(range)?.parse().ok() };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if year < 1970 {
+        return None;
+    }
+
+    // Days from the epoch to the civil date, see Howard Hinnant's days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    // Offsets other than Z are applied, docker always reports UTC but may not in future
+    let offset = match timestamp[19..].trim_start_matches(|c: char| c == '.' || c.is_ascii_digit())
+    {
+        "Z" | "" => 0,
+        zone => {
+            let sign = if zone.starts_with('-') { -1 } else { 1 };
+            let hours: i64 = zone.get(1..3)?.parse().ok()?;
+            let minutes: i64 = zone.get(4..6)?.parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second - offset).ok()
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImageInfo {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub digests: Vec<String>,
+    pub created: String,
+    pub size: i64,
+    pub labels: BTreeMap<String, String>,
+    pub layers: Vec<String>,
+    pub history: Vec<ImageHistoryEntry>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImageHistoryEntry {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub created: i64,
+    pub created_by: String,
+    pub size: i64,
+    pub comment: String,
+}
+
+/// The images loaded, pulled, built or rolled back to for the deployment and who by, newest
+/// first. Failed attempts are included
+#[utoipa::path(
+    tag = "deployments",
+    params(
+        ("name", Path, description = "Deployment name"),
+        ("offset", Query, description = "Items to skip"),
+        ("limit", Query, description = "Items to return"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "History",
+            body = [HistoryEntry],
+            headers(("X-Total-Count" = usize, description = "Number of items in the whole list"))
+        ),
+        (status = 404, description = "No such deployment", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/history?<offset>&<limit>")]
+pub async fn get_history(
+    name: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    history: &State<Arc<History>>,
+) -> Result<Page<HistoryEntry>, ApiError> {
+    if is_visible(config, &identity, &name) == false
+        || config.deployments.get().iter().any(|d| d.name == name) == false
+    {
+        return Err(Status::NotFound.into());
+    }
+
+    Ok(Page::new(history.get(&name), offset, limit))
+}
+
+/// Details of the image the deployment would run, so the exact version deployed can be
+/// checked. 404 if the deployment does not exist or has no image
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Image", body = ImageInfo),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/image/info")]
+pub async fn get_image_info(
+    name: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+) -> Result<(Status, Json<ImageInfo>), ApiError> {
+    if is_visible(config, &identity, &name) == false
+        || config.deployments.get().iter().any(|d| d.name == name) == false
+    {
+        return Err(Status::NotFound.into());
+    }
+
+    let image = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        name
+    );
+
+    let inspection = docker
+        .inspect_image(&image)
+        .await?
+        .ok_or(Status::NotFound)?;
+    let history = docker.image_history(&image).await?.unwrap_or_default();
+
+    return Ok((
+        Status::Ok,
+        Json(ImageInfo {
+            id: inspection.id,
+            tags: inspection.repo_tags.unwrap_or_default(),
+            digests: inspection.repo_digests.unwrap_or_default(),
+            created: inspection.created,
+            size: inspection.size,
+            labels: inspection.config.and_then(|c| c.labels).unwrap_or_default(),
+            layers: inspection.root_fs.map(|r| r.layers).unwrap_or_default(),
+            history: history
+                .into_iter()
+                .map(|h| ImageHistoryEntry {
+                    id: h.id,
+                    tags: h.tags.unwrap_or_default(),
+                    created: h.created,
+                    created_by: h.created_by,
+                    size: h.size,
+                    comment: h.comment,
+                })
+                .collect(),
+        }),
+    ));
+}
+
+/// An image edd loaded, pulled or built for a deployment, or which a deployment container runs
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ManagedImage {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub digests: Vec<String>,
+    /// Bytes, including layers shared with other images
+    pub size: i64,
+    /// Unix timestamp
+    pub created: i64,
+    /// Deployments the image is tagged for, i.e. as their latest or previous image
+    pub deployments: Vec<String>,
+    /// Deployments whose container runs the image
+    pub used_by: Vec<String>,
+}
+
+/// Every image tagged with the container prefix or run by a deployment container, newest first
+async fn managed_images(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    manager: &Manager,
+) -> Result<Vec<ManagedImage>, DockerError> {
+    let prefix = config.container_prefix.trim_start_matches("/");
+    let containers = docker.get_containers(&[]).await?;
+    let running: Vec<(&str, &str)> = manager
+        .deployments()
+        .iter()
+        .filter_map(|d| {
+            containers
+                .iter()
+                .find(|c| c.id == d.id)
+                .map(|c| (d.name.as_str(), c.image_id.as_str()))
+        })
+        .collect();
+
+    let mut images: Vec<ManagedImage> = docker
+        .get_images()
+        .await?
+        .into_iter()
+        .filter_map(|image| {
+            let deployments: Vec<String> = image
+                .repo_tags
+                .iter()
+                .filter_map(|t| t.strip_prefix(prefix))
+                .filter_map(|t| t.rsplit_once(':'))
+                .map(|(name, _)| name.to_owned())
+                .collect();
+            let used_by: Vec<String> = running
+                .iter()
+                .filter(|(_, id)| *id == image.id)
+                .map(|(name, _)| name.to_string())
+                .collect();
+            if deployments.is_empty() && used_by.is_empty() {
+                return None;
+            }
+
+            Some(ManagedImage {
+                id: image.id,
+                tags: image.repo_tags,
+                digests: image.repo_digests.unwrap_or_default(),
+                size: image.size,
+                created: image.created,
+                deployments,
+                used_by,
+            })
+        })
+        .collect();
+    images.sort_by_key(|i| std::cmp::Reverse(i.created));
+
+    Ok(images)
+}
+
+/// The images edd manages, so what is using the disk can be seen. Images are listed when the
+/// client can see one of their deployments, images which no longer belong to a deployment are
+/// only listed for clients which can see every deployment
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("offset", Query, description = "Items to skip"),
+        ("limit", Query, description = "Items to return"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Images",
+            body = [ManagedImage],
+            headers(("X-Total-Count" = usize, description = "Number of items in the whole list"))
+        ),
+    )
+)]
+#[get("/images?<offset>&<limit>")]
+pub async fn get_images(
+    offset: Option<usize>,
+    limit: Option<usize>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Page<ManagedImage>, ApiError> {
+    let mut manager = manager.lock().await;
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let images = managed_images(config, docker.as_ref(), &manager).await?;
+    drop(manager);
+
+    let configured = config.deployments.get();
+    let images = images
+        .into_iter()
+        .filter(|image| {
+            let owned = image
+                .deployments
+                .iter()
+                .chain(image.used_by.iter())
+                .filter(|name| configured.iter().any(|d| &&d.name == name))
+                .any(|name| is_visible(config, &identity, name));
+            owned || is_visible(config, &identity, "*")
+        })
+        .collect();
+
+    Ok(Page::new(images, offset, limit))
+}
+
+/// Removes an image edd manages, by its id or at least the first 12 characters of it. An image
+/// a deployment container runs is only removed when forced, docker also needs `force` to
+/// remove an image which has more than one tag or is used by a stopped container
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("id", Path, description = "Image id, or at least its first 12 characters"),
+        ("force", Query, description = "Remove the image even if it is in use or has other tags"),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such image, or it is not managed by edd", body = ApiError),
+        (
+            status = 409,
+            description = "The image is in use or has other tags, and force was not set",
+            body = ApiError
+        ),
+    )
+)]
+#[delete("/images/<id>?<force>")]
+pub async fn delete_image(
+    id: String,
+    force: Option<bool>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Delete of image {} requested by {}", id, identity);
+    let force = force.unwrap_or(false);
+
+    let mut manager = manager.lock().await;
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let hex = id.strip_prefix("sha256:").unwrap_or(&id);
+    let image = managed_images(config, docker.as_ref(), &manager)
+        .await?
+        .into_iter()
+        .find(|i| {
+            let image_hex = i.id.strip_prefix("sha256:").unwrap_or(&i.id);
+            image_hex == hex || (hex.len() >= 12 && image_hex.starts_with(hex))
+        })
+        .ok_or(Status::NotFound)?;
+
+    // Deleting an image is permitted to clients which may delete every deployment it belongs
+    // to, images no longer belonging to a deployment need permission for every deployment
+    let configured = config.deployments.get();
+    let owners: Vec<&String> = image
+        .deployments
+        .iter()
+        .chain(image.used_by.iter())
+        .filter(|name| configured.iter().any(|d| &&d.name == name))
+        .collect();
+    if owners.is_empty() {
+        authorize(config, &identity, "*", Action::Delete)?;
+    }
+    for owner in owners {
+        authorize(config, &identity, owner, Action::Delete)?;
+    }
+
+    if image.used_by.is_empty() == false && force == false {
+        return Err(ApiError::new(
+            Status::Conflict,
+            format!(
+                "image is used by deployment '{}', add ?force=true to remove it",
+                image.used_by.join("', '")
+            ),
+        ));
+    }
+
+    docker.remove_image(&image.id, force).await.map_err(|e| {
+        println!("Unable to remove image {}: {}", image.id, e);
+        ApiError::from(e)
+    })?;
+    info!("Removed image {} ({})", image.id, image.tags.join(", "));
+
+    Ok((Status::Ok, "{}".into()))
+}
+
+/// Exports the deployment image as a tarball which can be uploaded to another edd with
+/// `load`, for moving images between devices without a registry
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Image tarball",
+            body = Vec<u8>,
+            content_type = "application/x-tar"
+        ),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/image")]
+pub async fn export_image(
+    name: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+) -> Result<(ContentType, tokio::fs::File), ApiError> {
+    info!(
+        "Export of deployment '{}' image requested by {}",
+        name, identity
+    );
+    authorize(config, &identity, &name, Action::Export)?;
+
+    if config.deployments.get().iter().any(|d| d.name == name) == false {
+        return Err(Status::NotFound.into());
+    }
+
+    let filename = temporary_file("export", &name, "tar");
+
+    let result = docker
+        .save_image(
+            &format!(
+                "{}{}:latest",
+                config.container_prefix.trim_start_matches("/"),
+                name
+            ),
+            filename.to_str().unwrap(),
+        )
+        .await;
+
+    let file = open_temporary_file(&filename, result).await.map_err(|e| {
+        println!("Unable to export image for deployment '{}': {}", name, e);
+        ApiError::from(e)
+    })?;
+    Ok((ContentType::new("application", "x-tar"), file))
+}
+
+/// Id of the deployment's container, NotFound if there is no container
+async fn container_id(
+    name: &str,
+    config: &Config,
+    docker: &SharedRuntime,
+    manager: &Arc<Mutex<Manager>>,
+) -> Result<String, ApiError> {
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(config, docker.as_ref()).await?;
+
+    manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == name && d.id.is_empty() == false)
+        .map(|d| d.id.to_owned())
+        .ok_or(Status::NotFound.into())
+}
+
+/// The full docker inspect output for the deployment's container, for when the field needed is
+/// not in the deployment view. Values which may be secrets are redacted, see
+/// sanitise_inspection
+#[utoipa::path(
+    tag = "diagnostics",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "The inspect response as docker returns it", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/inspect")]
+pub async fn inspect_deployment(
+    name: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Json<Value>, ApiError> {
+    info!("Inspect of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Logs)?;
+
+    let id = container_id(&name, config, docker, manager).await?;
+    let mut inspection = docker.inspect_container_json(&id).await?;
+    sanitise_inspection(&mut inspection);
+
+    Ok(Json(inspection))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DeploymentStats {
+    /// When docker took the sample
+    pub read: String,
+    /// Percentage of one CPU, a container using two CPUs fully is at 200
+    pub cpu_percent: f64,
+    pub memory_usage: u64,
+    /// The memory limit, or the host memory if the container has no limit
+    pub memory_limit: u64,
+    pub memory_percent: f64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    pub block_read_bytes: u64,
+    pub block_write_bytes: u64,
+}
+
+/// Resource usage of the deployment's container from a single docker stats sample, with the
+/// same CPU and memory calculations as `docker stats`. Network and block I/O are totals
+/// since the container started
+#[utoipa::path(
+    tag = "diagnostics",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Stats", body = DeploymentStats),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/stats")]
+pub async fn get_stats(
+    name: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Json<DeploymentStats>, ApiError> {
+    authorize(config, &identity, &name, Action::Logs)?;
+
+    let id = container_id(&name, config, docker, manager).await?;
+    let stats = docker.stats(&id).await?;
+
+    let memory_usage = stats.memory_used();
+    let memory_limit = stats.memory_stats.limit.unwrap_or(0);
+    let (network_rx_bytes, network_tx_bytes) = stats.network_bytes();
+    let (block_read_bytes, block_write_bytes) = stats.block_bytes();
+
+    Ok(Json(DeploymentStats {
+        read: stats.read.to_owned(),
+        cpu_percent: stats.cpu_percent(),
+        memory_usage,
+        memory_limit,
+        memory_percent: match memory_limit {
+            0 => 0.0,
+            limit => memory_usage as f64 / limit as f64 * 100.0,
+        },
+        network_rx_bytes,
+        network_tx_bytes,
+        block_read_bytes,
+        block_write_bytes,
+    }))
+}
+
+/// Copies a file or directory out of the deployment's container as a tarball, i.e. to
+/// collect logs or artifacts. `path` must be absolute
+#[utoipa::path(
+    tag = "files",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Tarball", body = Vec<u8>, content_type = "application/x-tar"),
+        (status = 400, description = "path is not absolute", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/archive?<path>")]
+pub async fn get_archive(
+    name: String,
+    path: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(ContentType, tokio::fs::File), ApiError> {
+    info!(
+        "Copy of {} from deployment '{}' requested by {}",
+        path, name, identity
+    );
+    authorize(config, &identity, &name, Action::Files)?;
+    if path.starts_with('/') == false {
+        return Err(Status::BadRequest.into());
+    }
+
+    let id = container_id(&name, config, docker, manager).await?;
+
+    let filename = temporary_file("archive", &name, "tar");
+
+    let result = docker
+        .get_archive(&id, &path, filename.to_str().unwrap())
+        .await;
+
+    let file = open_temporary_file(&filename, result).await.map_err(|e| {
+        println!("Unable to copy {} from deployment '{}': {}", path, name, e);
+        ApiError::from(e)
+    })?;
+    Ok((ContentType::new("application", "x-tar"), file))
+}
+
+/// Container logs, as plain text or, when following, as server sent events
+pub enum Logs {
+    Text(ByteStream<BoxStream<'static, Vec<u8>>>),
+    Events(EventStream<BoxStream<'static, Event>>),
+}
+
+impl<'r> Responder<'r, 'r> for Logs {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            Logs::Text(stream) => (ContentType::Plain, stream).respond_to(request),
+            Logs::Events(stream) => stream.respond_to(request),
+        }
+    }
+}
+
+/// Which of the logs are returned
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogsQuery {
+    /// Only the last lines
+    tail: Option<usize>,
+    /// Only lines after this unix timestamp
+    since: Option<i64>,
+    /// Keep sending output as it is written
+    follow: Option<bool>,
+}
+
+/// The stdout and stderr output of the deployment's container, `tail` limits it to the last
+/// lines and `since` to lines after a unix timestamp
+///
+/// With `follow=true` the response stays open and new output is sent as it is written, until
+/// the container stops. Each chunk of output is sent as a `stdout` or `stderr` event, an
+/// `error` event is sent if docker fails part way through
+#[utoipa::path(
+    tag = "diagnostics",
+    params(
+        ("name", Path, description = "Deployment name"),
+        LogsQuery,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Plain text, or with follow=true a text/event-stream of stdout, stderr and error events",
+            body = String,
+            content_type = "text/plain"
+        ),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/logs?<query..>")]
+pub async fn get_logs(
+    name: String,
+    query: LogsQuery,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Logs, ApiError> {
+    info!("Logs of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Logs)?;
+
+    let id = container_id(&name, config, docker, manager).await?;
+    let follow = query.follow.unwrap_or(false);
+
+    let chunks = docker
+        .get_logs(&id, query.tail, query.since, follow)
+        .await?;
+
+    if follow == false {
+        let output = chunks
+            .take_while(move |chunk| {
+                if let Err(e) = chunk {
+                    println!("Unable to read logs of deployment '{}': {}", name, e);
+                }
+                futures::future::ready(chunk.is_ok())
+            })
+            .filter_map(|chunk| futures::future::ready(chunk.ok().map(|c| c.data)));
+        return Ok(Logs::Text(ByteStream(output.boxed())));
+    }
+
+    let events = chunks.map(|chunk| match chunk {
+        Ok(chunk) => Event::data(String::from_utf8_lossy(&chunk.data).into_owned()).event(
+            match chunk.source {
+                LogSource::Stdout => "stdout",
+                LogSource::Stderr => "stderr",
+            },
+        ),
+        Err(e) => Event::data(e.to_string()).event("error"),
+    });
+    Ok(Logs::Events(EventStream::from(events.boxed())))
+}
+
+/// A gzipped tarball of a deployment's logs, sent as a file download
+#[derive(Responder)]
+#[response(content_type = "application/gzip")]
+pub struct LogBundle {
+    file: tokio::fs::File,
+    disposition: Header<'static>,
+}
+
+/// What goes in a log bundle
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogBundleQuery {
+    /// Only the last lines
+    tail: Option<usize>,
+    /// Only lines after this unix timestamp
+    since: Option<i64>,
+    /// Include the container's docker inspect output, sanitised as for /inspect
+    inspect: Option<bool>,
+}
+
+/// The deployment's logs as a gzipped tarball, so support can attach them to a ticket in one
+/// request. `tail` and `since` are as for the logs. With `inspect=true` the tarball also holds
+/// the container's docker inspect output, sanitised as for `/inspect`
+#[utoipa::path(
+    tag = "diagnostics",
+    params(
+        ("name", Path, description = "Deployment name"),
+        LogBundleQuery,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "A tarball of <name>/logs.txt and, with inspect=true, <name>/inspect.json",
+            body = Vec<u8>,
+            content_type = "application/gzip"
+        ),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/logs/download?<query..>")]
+pub async fn download_logs(
+    name: String,
+    query: LogBundleQuery,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<LogBundle, ApiError> {
+    info!(
+        "Log download of deployment '{}' requested by {}",
+        name, identity
+    );
+    authorize(config, &identity, &name, Action::Logs)?;
+
+    let id = container_id(&name, config, docker, manager).await?;
+    let inspection = match query.inspect.unwrap_or(false) {
+        true => {
+            let mut inspection = docker.inspect_container_json(&id).await?;
+            sanitise_inspection(&mut inspection);
+            Some(inspection)
+        }
+        false => None,
+    };
+
+    let logs = temporary_file("logs", &name, "log");
+    let bundle = temporary_file("logs", &name, "tar.gz");
+
+    let result = write_log_bundle(
+        &name,
+        &id,
+        &query,
+        inspection,
+        &logs,
+        &bundle,
+        docker.as_ref(),
+    )
+    .await;
+    tokio::fs::remove_file(&logs).await.ok();
+
+    let file = open_temporary_file(&bundle, result).await.map_err(|e| {
+        println!("Unable to bundle logs of deployment '{}': {}", name, e);
+        ApiError::from(e)
+    })?;
+    Ok(LogBundle {
+        file,
+        disposition: Header::new(
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{}-logs-{}.tar.gz\"",
+                name,
+                unix_now()
+            ),
+        ),
+    })
+}
+
+/// Write the logs to a file then add it, and the inspect output, to a gzipped tarball. The
+/// logs are read in full first as each file's size comes before it in a tarball
+async fn write_log_bundle(
+    name: &str,
+    id: &str,
+    query: &LogBundleQuery,
+    inspection: Option<Value>,
+    logs: &Path,
+    bundle: &Path,
+    docker: &dyn ContainerRuntime,
+) -> Result<(), DockerError> {
+    let mut chunks = docker.get_logs(id, query.tail, query.since, false).await?;
+    let mut file = tokio::fs::File::create(logs).await?;
+    while let Some(chunk) = chunks.next().await {
+        file.write_all(&chunk?.data).await?;
+    }
+    file.flush().await?;
+
+    let inspection = match inspection {
+        Some(inspection) => Some(serde_json::to_vec_pretty(&inspection)?),
+        None => None,
+    };
+    let (name, logs, bundle) = (name.to_owned(), logs.to_owned(), bundle.to_owned());
+    tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+        let gzip = GzEncoder::new(std::fs::File::create(&bundle)?, Compression::default());
+        let mut tar = tar::Builder::new(gzip);
+        tar.append_path_with_name(&logs, format!("{}/logs.txt", name))?;
+        if let Some(inspection) = inspection {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(inspection.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(unix_now());
+            tar.append_data(
+                &mut header,
+                format!("{}/inspect.json", name),
+                inspection.as_slice(),
+            )?;
+        }
+        tar.into_inner()?.finish()?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| DockerError::Other(e.to_string()))??;
+
+    Ok(())
+}
+
+/// Replace the values of the container's environment variables and log driver options, which
+/// often hold secrets (i.e. a splunk-token), in docker inspect output. The names are kept
+fn sanitise_inspection(inspection: &mut Value) {
+    if let Some(variables) = inspection
+        .pointer_mut("/Config/Env")
+        .and_then(|e| e.as_array_mut())
+    {
+        for variable in variables {
+            let name = match variable.as_str().and_then(|v| v.split_once('=')) {
+                Some((name, _)) => name.to_owned(),
+                None => continue,
+            };
+            *variable = Value::String(format!("{}=<redacted>", name));
+        }
+    }
+
+    if let Some(options) = inspection
+        .pointer_mut("/HostConfig/LogConfig/Config")
+        .and_then(|c| c.as_object_mut())
+    {
+        for value in options.values_mut() {
+            *value = Value::String("<redacted>".into());
+        }
+    }
+}
+
+/// Deployment changes as server sent events, for dashboards to update as they happen rather
+/// than polling `/v1/deployments`. Each change is sent as an event named after it (`started`,
+/// `stopped`, `unhealthy`, ...) with the deployment's new state as JSON. `deployment` limits
+/// the stream to one deployment, clients only receive changes to deployments visible to them.
+/// A `resync` event is sent if the client falls behind and changes were missed
+#[utoipa::path(
+    tag = "deployments",
+    params(
+        ("deployment", Query, description = "Only changes to this deployment"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Events named after the change (started, stopped, paused, unpaused, unhealthy, healthy, loaded) with a DeploymentChange as data, or resync when changes were missed",
+            body = String,
+            content_type = "text/event-stream"
+        ),
+    )
+)]
+#[get("/events?<deployment>")]
+pub async fn get_events(
+    deployment: Option<String>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> EventStream<BoxStream<'static, Event>> {
+    info!("Deployment events requested by {}", identity);
+    let changes = manager.lock().await.subscribe();
+    let config = config.inner().clone();
+
+    let events = futures::stream::unfold(changes, |mut changes| async move {
+        let change = match changes.recv().await {
+            Ok(change) => Ok(change),
+            Err(RecvError::Lagged(missed)) => Err(missed),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((change, changes))
+    })
+    .filter_map(move |change| {
+        let event = match change {
+            Ok(change) => {
+                let wanted = deployment.as_ref().map(|d| d == &change.deployment) != Some(false)
+                    && is_visible(&config, &identity, &change.deployment);
+                wanted.then(|| Event::json(&change).event(change_name(change.event)))
+            }
+            Err(missed) => Some(Event::data(missed.to_string()).event("resync")),
+        };
+        futures::future::ready(event)
+    });
+
+    EventStream::from(events.boxed())
+}
+
+/// The name a change is serialized with, i.e. `started`
+fn change_name(change: Change) -> String {
+    rocket::serde::json::to_value(change)
+        .ok()
+        .and_then(|c| c.as_str().map(|c| c.to_owned()))
+        .unwrap_or_default()
+}
+
+/// A deployment console, upgraded to a WebSocket once the response is sent
+pub struct Console {
+    name: String,
+    accept: String,
+    attached: Attached,
+}
+
+impl<'r> Responder<'r, 'static> for Console {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", self.accept.clone())
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Console {
+    /// Container output is sent to the client as binary messages, and messages from the
+    /// client are written to the container's stdin
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> std::io::Result<()> {
+        let Console {
+            name, mut attached, ..
+        } = *Pin::into_inner(self);
+        let (mut sender, mut messages) = websocket::accept(io).await.split();
+
+        let result = loop {
+            tokio::select! {
+                chunk = attached.output.next() => match chunk {
+                    Some(Ok(chunk)) => sender
+                        .send(Message::Binary(chunk.data))
+                        .await
+                        .map_err(std::io::Error::other)?,
+                    Some(Err(e)) => {
+                        println!("Console of deployment '{}' failed: {}", name, e);
+                        break Ok(());
+                    }
+                    // The container stopped
+                    None => break Ok(()),
+                },
+                message = messages.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        attached.input.write_all(text.as_bytes()).await?;
+                        attached.input.flush().await?;
+                    }
+                    Some(Ok(Message::Binary(data))) => {
+                        attached.input.write_all(&data).await?;
+                        attached.input.flush().await?;
+                    }
+                    Some(Ok(Message::Close(_))) | None => break Ok(()),
+                    // Pings are answered by tungstenite as messages are read
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => break Err(std::io::Error::other(e)),
+                },
+            }
+        };
+
+        info!("Console of deployment '{}' closed", name);
+        sender.send(Message::Close(None)).await.ok();
+        result
+    }
+}
+
+/// Attaches to the stdin, stdout and stderr of the deployment's container over a WebSocket,
+/// for interactive debugging. The container needs `-t` in its args for a shell prompt.
+/// Detaching leaves the container running. Only available when `console` is enabled
+#[utoipa::path(
+    tag = "diagnostics",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 101, description = "Upgraded to a WebSocket"),
+        (
+            status = 403,
+            description = "The console is disabled, not authorized or the daemon is read only",
+            body = ApiError
+        ),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (status = 426, description = "Not a WebSocket upgrade request", body = ApiError),
+    )
+)]
+#[get("/deployments/<name>/console")]
+pub async fn get_console(
+    name: String,
+    key: WebSocketKey,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Console, ApiError> {
+    info!("Console of deployment '{}' requested by {}", name, identity);
+    if config.console == false {
+        return Err(Status::Forbidden.into());
+    }
+    authorize(config, &identity, &name, Action::Console)?;
+
+    let id = container_id(&name, config, docker, manager).await?;
+    let attached = docker.attach(&id).await?;
+
+    Ok(Console {
+        name,
+        accept: key.accept,
+        attached,
+    })
+}
+
+/// Extracts an uploaded tarball (plain, gzip, bzip2 or xz) into the directory `path` of the
+/// deployment's container, i.e. to push configuration files. The upload is streamed to
+/// docker as it arrives
+#[utoipa::path(
+    tag = "files",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/x-tar"),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 400, description = "Docker refused the upload", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (status = 413, description = "The upload is larger than 2 GiB", body = ApiError),
+    )
+)]
+#[put("/deployments/<name>/archive?<path>", data = "<upload>")]
+pub async fn put_archive(
+    name: String,
+    path: String,
+    mut upload: CappedUpload<'_>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    daemon: Daemon<'_>,
+) -> Result<(Status, String), ApiError> {
+    let Daemon {
+        config,
+        docker,
+        manager,
+        ..
+    } = daemon;
+    info!(
+        "Copy to {} in deployment '{}' requested by {}",
+        path, name, identity
+    );
+    authorize(config, &identity, &name, Action::Files)?;
+    if path.starts_with('/') == false {
+        return Err(ApiError::new(Status::BadRequest, "path must be absolute"));
+    }
+
+    let id = container_id(&name, config, docker, manager).await?;
+
+    docker
+        .put_archive(&id, &path, &mut upload)
+        .await
+        .map_err(|e| {
+            println!("Unable to copy to {} in deployment '{}': {}", path, name, e);
+            match e {
+                _ if upload.exceeded() => too_large(),
+                DockerError::InvalidRequest(_) => ApiError {
+                    status: Status::BadRequest,
+                    ..ApiError::from(e)
+                },
+                e => ApiError::from(e),
+            }
+        })?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Health {
+    pub status: String,
+    pub deployments: usize,
+    pub running: usize,
+    pub unhealthy: usize,
+    pub tracked: usize,
+    pub orphaned: usize,
+    /// Problems found with the docker engine at startup
+    pub engine: Vec<String>,
+}
+
+/// Summary of the state of all deployments
+///
+/// `tracked` is the number of deployments with a container, `orphaned` is the number of
+/// prefixed containers which do not map to a configured deployment, `engine` lists the
+/// problems found with the docker engine when edd started
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Health", body = Health),
+    )
+)]
+#[get("/health")]
+pub async fn get_health(
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+    engine: &State<EngineReport>,
+) -> Result<(Status, Json<Health>), ApiError> {
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let running = manager
+        .deployments()
+        .iter()
+        .filter(|d| d.state == crate::manager::State::Running)
+        .count();
+    let unhealthy = manager
+        .deployments()
+        .iter()
+        .filter(|d| d.health == "unhealthy")
+        .count();
+    let tracked = manager
+        .deployments()
+        .iter()
+        .filter(|d| d.id.is_empty() == false)
+        .count();
+    let orphaned = manager.orphans().len();
+
+    let status = if unhealthy == 0 && orphaned == 0 && engine.problems.is_empty() {
+        "ok"
+    } else {
+        "degraded"
+    };
+
+    Ok((
+        Status::Ok,
+        Json(Health {
+            status: status.into(),
+            deployments: manager.deployments().len(),
+            running,
+            unhealthy,
+            tracked,
+            orphaned,
+            engine: engine.problems.clone(),
+        }),
+    ))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Ready {
+    pub ready: bool,
+    pub healthy: usize,
+    pub min_healthy: usize,
+    /// Deployments marked required_for_ready which are not running and healthy
+    pub not_ready: Vec<String>,
+}
+
+/// Readiness of the fleet, returns 503 unless every deployment marked `required_for_ready` is
+/// running and healthy, and at least `min_healthy` deployments are running and healthy
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Ready", body = Ready),
+        (status = 503, description = "Not ready", body = Ready),
+    )
+)]
+#[get("/ready")]
+pub async fn get_ready(
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<Ready>), ApiError> {
+    let mut manager = manager.lock().await;
+
+    manager
+        .update_deployments(&config, docker.as_ref())
+        .await
+        .map_err(|e| ApiError {
+            status: Status::ServiceUnavailable,
+            ..ApiError::from(e)
+        })?;
+
+    let healthy = manager
+        .deployments()
+        .iter()
+        .filter(|d| d.is_healthy())
+        .count();
+    let not_ready: Vec<String> = config
+        .deployments
+        .get()
+        .iter()
+        .filter(|c| c.required_for_ready)
+        .filter(|c| {
+            manager
+                .deployments()
+                .iter()
+                .any(|d| d.name == c.name && d.is_healthy())
+                == false
+        })
+        .map(|c| c.name.to_owned())
+        .collect();
+
+    let ready = not_ready.is_empty() && healthy >= config.min_healthy;
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    Ok((
+        status,
+        Json(Ready {
+            ready,
+            healthy,
+            min_healthy: config.min_healthy,
+            not_ready,
+        }),
+    ))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Auth {
+    pub mutual_tls: bool,
+    pub mutual_tls_mandatory: bool,
+    pub token_auth: bool,
+    pub authorization_rules: usize,
+    pub client: String,
+    /// Other names authorization rules can match the client by
+    pub alternative_names: Vec<String>,
+}
+
+/// Reports which authentication mechanisms are active, and who the caller was identified as
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Auth", body = Auth),
+    )
+)]
+#[get("/auth")]
+pub async fn get_auth(identity: ClientIdentity, config: &State<Arc<Config>>) -> Json<Auth> {
+    Json(Auth {
+        // The CA file is required to exist at startup, so client certificates are always verified
+        mutual_tls: true,
+        mutual_tls_mandatory: config.mutual_tls_mandatory,
+        token_auth: config.token_auth.enabled(),
+        authorization_rules: config.authorization.len(),
+        client: identity.to_string(),
+        alternative_names: identity.alternative_names.to_owned(),
+    })
+}
+
+/// Entries from the audit log, oldest first. `since` is a unix timestamp and `limit` the
+/// number of most recent entries returned, 100 by default
+#[utoipa::path(
+    tag = "daemon",
+    params(
+        ("deployment", Query, description = "Only entries for this deployment"),
+        ("client", Query, description = "Only entries for this client"),
+        ("since", Query, description = "Unix timestamp of the oldest entry"),
+        ("limit", Query, description = "Number of most recent entries"),
+    ),
+    responses(
+        (status = 200, description = "Audit entries", body = [AuditEntry]),
+        (status = 403, description = "Not authorized", body = ApiError),
+        (status = 404, description = "audit_log is not configured", body = ApiError),
+    )
+)]
+#[get("/audit?<deployment>&<client>&<since>&<limit>")]
+pub async fn get_audit_log(
+    deployment: Option<String>,
+    client: Option<String>,
+    since: Option<u64>,
+    limit: Option<usize>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    audit_log: &State<Option<AuditLog>>,
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    authorize(config, &identity, "*", Action::Admin)?;
+    let audit_log = match audit_log.inner() {
+        Some(audit_log) => audit_log,
+        None => {
+            return Err(ApiError::new(
+                Status::NotFound,
+                "audit_log is not configured",
+            ))
+        }
+    };
+
+    let entries = audit_log
+        .query(
+            deployment.as_deref(),
+            client.as_deref(),
+            since,
+            limit.unwrap_or(100),
+        )
+        .await
+        .map_err(|e| {
+            println!("Unable to read audit log: {}", e);
+            ApiError::internal("Unable to read the audit log", e)
+        })?;
+
+    Ok(Json(entries))
+}
+
+/// Whether docker is rootless and which resource limits it can apply on this host
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Capabilities", body = Capabilities),
+    )
+)]
+#[get("/capabilities")]
+pub async fn get_capabilities(
+    docker: &State<SharedRuntime>,
+) -> Result<Json<Capabilities>, ApiError> {
+    let info = docker.system_info().await?;
+
+    Ok(Json(Capabilities::from_info(&info)))
+}
+
+/// OpenAPI 3 description of the API, for generating client SDKs
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "OpenAPI document", body = Object),
+    )
+)]
+#[get("/openapi.json")]
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Deployment states, health, restart counts and image sizes, with the request durations and
+/// docker errors recorded since the daemon started, in the Prometheus text format
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (
+            status = 200,
+            description = "Prometheus text format",
+            body = String,
+            content_type = "text/plain"
+        ),
+    )
+)]
+#[get("/metrics")]
+pub async fn get_metrics(
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(ContentType, String), ApiError> {
+    // Docker is only asked for the restart counts and image sizes once the lock is released
+    let (deployments, orphaned) = {
+        let mut manager = manager.lock().await;
+        if let Err(e) = manager.update_deployments(&config, docker.as_ref()).await {
+            metrics::record_error(e.as_ref());
+            return Err(Status::ServiceUnavailable.into());
+        }
+
+        let deployments: Vec<_> = manager
+            .deployments()
+            .iter()
+            .map(|d| {
+                (
+                    d.name.to_owned(),
+                    d.id.to_owned(),
+                    d.image.to_owned(),
+                    d.state.to_string(),
+                    d.health.to_owned(),
+                    d.is_healthy(),
+                )
+            })
+            .collect();
+        (deployments, manager.orphans().len())
+    };
+
+    let mut exposition = Exposition::default();
+
+    exposition.metric(
+        "edd_deployment_state",
+        "gauge",
+        "State of each deployment, 1 for the state it is in",
+    );
+    for (name, _, _, state, _, _) in &deployments {
+        exposition.sample(
+            "edd_deployment_state",
+            &[("deployment", name), ("state", state)],
+            1.0,
+        );
+    }
+
+    exposition.metric(
+        "edd_deployment_health",
+        "gauge",
+        "Docker health of each deployment, 1 for the health it has",
+    );
+    for (name, _, _, _, health, _) in &deployments {
+        exposition.sample(
+            "edd_deployment_health",
+            &[("deployment", name), ("health", health)],
+            1.0,
+        );
+    }
+
+    exposition.metric(
+        "edd_deployment_healthy",
+        "gauge",
+        "1 if the deployment is running and healthy, 0 otherwise",
+    );
+    for (name, _, _, _, _, healthy) in &deployments {
+        exposition.sample(
+            "edd_deployment_healthy",
+            &[("deployment", name)],
+            *healthy as u8 as f64,
+        );
+    }
+
+    exposition.metric(
+        "edd_deployment_restarts",
+        "gauge",
+        "Times docker has restarted the deployment's container, reset when it is recreated",
+    );
+    for (name, id, _, _, _, _) in deployments.iter().filter(|d| d.1.is_empty() == false) {
+        match docker.inspect_running_container(id).await {
+            Ok(inspection) => exposition.sample(
+                "edd_deployment_restarts",
+                &[("deployment", name)],
+                inspection.restart_count as f64,
+            ),
+            Err(e) => metrics::record_docker_error(&e),
+        }
+    }
+
+    exposition.metric(
+        "edd_deployment_image_size_bytes",
+        "gauge",
+        "Size of the image each deployment is running",
+    );
+    for (name, _, image, _, _, _) in deployments.iter().filter(|d| d.2.is_empty() == false) {
+        match docker.inspect_image(image).await {
+            Ok(Some(inspection)) => exposition.sample(
+                "edd_deployment_image_size_bytes",
+                &[("deployment", name)],
+                inspection.size as f64,
+            ),
+            Ok(None) => {}
+            Err(e) => metrics::record_docker_error(&e),
+        }
+    }
+
+    exposition.metric(
+        "edd_orphaned_containers",
+        "gauge",
+        "Containers using the container prefix which are not a configured deployment",
+    );
+    exposition.sample("edd_orphaned_containers", &[], orphaned as f64);
+
+    exposition.recorded();
+
+    Ok((
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        exposition.into_string(),
+    ))
+}
+
+/// Memory and CPU usage of the daemon process, only supported on Linux
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Stats", body = SelfStats),
+        (status = 501, description = "Not supported on this platform", body = ApiError),
+    )
+)]
+#[get("/self/stats")]
+pub async fn get_self_stats() -> Result<Json<SelfStats>, ApiError> {
+    if cfg!(target_os = "linux") == false {
+        return Err(ApiError::new(Status::NotImplemented, "unsupported"));
+    }
+
+    crate::self_stats::read()
+        .map(Json)
+        .map_err(|e| ApiError::internal("Unable to read the daemon's statistics", e))
+}
+
+#[utoipa::path(
+    tag = "lifecycle",
+    summary = "Start the deployment's container",
+    params(
+        ("name", Path, description = "Deployment name"),
+        (
+            "dry_run" = Option<bool>,
+            Query,
+            description = "Check the request could be made and report what it would do without making it, returns a DryRun with the status the request would fail with"
+        ),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/start")]
+pub async fn start_deployment(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Start of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Start)?;
+    check_enabled(config, &name)?;
+
+    let mut manager = manager.lock().await;
+
+    // Update the info on deployments in case the container is already running
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    // Look for the deployment
+    let result = manager.deployments().iter().find(|d| d.name == name);
+    if result.is_none() {
+        return Err(Status::NotFound.into());
+    }
+    let deployment = result.unwrap();
+
+    if deployment.state == crate::manager::State::Running {
+        return Ok((Status::Ok, "{}".into()));
+    }
+
+    check_digest(config, docker.as_ref(), deployment).await?;
+
+    docker.start(&deployment.id).await?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+#[utoipa::path(
+    tag = "lifecycle",
+    summary = "Stop the deployment's container",
+    params(
+        ("name", Path, description = "Deployment name"),
+        (
+            "dry_run" = Option<bool>,
+            Query,
+            description = "Check the request could be made and report what it would do without making it, returns a DryRun with the status the request would fail with"
+        ),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/stop")]
+pub async fn stop_deployment(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Stop of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Stop)?;
+
+    let mut manager = manager.lock().await;
+
+    // Update the info on deployments in case the container is already stopped
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    stop(&name, docker.as_ref(), &mut manager, true).await?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// What happened to each deployment when a group was started or stopped
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct GroupOutcome {
+    pub name: String,
+    /// started, stopped, running (already), disabled (so not started) or failed
+    pub outcome: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+/// The state of a group as a whole, stopped when none of its deployments are running, running
+/// when every deployment which is not disabled is running and healthy and partial otherwise
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct GroupStatus {
+    pub name: String,
+    pub state: String,
+    pub deployments: Vec<Deployments>,
+}
+
+/// The deployments in a group, the client must be permitted the action on every one of them
+fn group_members(
+    config: &Config,
+    identity: &ClientIdentity,
+    group: &str,
+    action: Action,
+) -> Result<Vec<String>, ApiError> {
+    let members = config.group_members(group);
+    if members.iter().any(|m| is_visible(config, identity, m)) == false {
+        return Err(Status::NotFound.into());
+    }
+    for member in &members {
+        authorize(config, identity, member, action)?;
+    }
+
+    Ok(members)
+}
+
+/// The status is the first failure's, the response lists what happened to every deployment
+fn group_response(outcomes: Vec<GroupOutcome>) -> (Status, Json<Vec<GroupOutcome>>) {
+    let status = outcomes
+        .iter()
+        .find_map(|o| o.error.as_ref().map(|e| e.status))
+        .unwrap_or(Status::Ok);
+
+    (status, Json(outcomes))
+}
+
+/// Starts the deployments in the group in the order they are configured, disabled deployments
+/// are skipped. A failure does not stop the rest of the group being started
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Group name"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "What happened to each deployment, the status is that of the first failure",
+            body = [GroupOutcome]
+        ),
+        (
+            status = 403,
+            description = "Not permitted for every deployment in the group, or the daemon is read only",
+            body = ApiError
+        ),
+        (status = 404, description = "No such group", body = ApiError),
+    )
+)]
+#[post("/groups/<name>/start")]
+pub async fn start_group(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<Vec<GroupOutcome>>), ApiError> {
+    info!("Start of group '{}' requested by {}", name, identity);
+    let members = group_members(config, &identity, &name, Action::Start)?;
+
+    let mut manager = manager.lock().await;
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let mut outcomes = vec![];
+    for member in members {
+        let deployment = match manager.deployments().iter().find(|d| d.name == member) {
+            Some(deployment) => deployment,
+            None => continue,
+        };
+        let result = match deployment.state {
+            _ if config.is_disabled(&member) => Ok("disabled"),
+            crate::manager::State::Running => Ok("running"),
+            _ if deployment.id.is_empty() => Err(ApiError::new(Status::NotFound, "no container")),
+            _ => match check_digest(config, docker.as_ref(), deployment).await {
+                Ok(()) => docker
+                    .start(&deployment.id)
+                    .await
+                    .map(|_| "started")
+                    .map_err(ApiError::from),
+                Err(e) => Err(e),
+            },
+        };
+        outcomes.push(match result {
+            Ok(outcome) => GroupOutcome {
+                name: member,
+                outcome: outcome.into(),
+                error: None,
+            },
+            Err(e) => {
+                println!("Unable to start deployment '{}': {}", member, e.message);
+                GroupOutcome {
+                    name: member,
+                    outcome: "failed".into(),
+                    error: Some(e),
+                }
+            }
+        });
+    }
+
+    Ok(group_response(outcomes))
+}
+
+/// Stops the deployments in the group in the reverse of the order they are configured, so a
+/// deployment is stopped before the deployments it was started after
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Group name"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "What happened to each deployment, the status is that of the first failure",
+            body = [GroupOutcome]
+        ),
+        (
+            status = 403,
+            description = "Not permitted for every deployment in the group, or the daemon is read only",
+            body = ApiError
+        ),
+        (status = 404, description = "No such group", body = ApiError),
+    )
+)]
+#[post("/groups/<name>/stop")]
+pub async fn stop_group(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<Vec<GroupOutcome>>), ApiError> {
+    info!("Stop of group '{}' requested by {}", name, identity);
+    let members = group_members(config, &identity, &name, Action::Stop)?;
+
+    let mut manager = manager.lock().await;
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let mut outcomes = vec![];
+    for member in members.into_iter().rev() {
+        let running = manager
+            .deployments()
+            .iter()
+            .any(|d| d.name == member && d.state != crate::manager::State::Stopped);
+        if running == false {
+            outcomes.push(GroupOutcome {
+                name: member,
+                outcome: "stopped".into(),
+                error: None,
+            });
+            continue;
+        }
+
+        let outcome = match stop(&member, docker.as_ref(), &mut manager, true).await {
+            Ok(()) => GroupOutcome {
+                name: member,
+                outcome: "stopped".into(),
+                error: None,
+            },
+            Err(e) => {
+                println!("Unable to stop deployment '{}': {}", member, e.message);
+                GroupOutcome {
+                    name: member,
+                    outcome: "failed".into(),
+                    error: Some(e),
+                }
+            }
+        };
+        outcomes.push(outcome);
+    }
+
+    Ok(group_response(outcomes))
+}
+
+#[utoipa::path(
+    tag = "deployments",
+    summary = "The group's deployments and overall state",
+    params(
+        ("name", Path, description = "Group name"),
+    ),
+    responses(
+        (status = 200, description = "Group", body = GroupStatus),
+        (status = 404, description = "No such group", body = ApiError),
+    )
+)]
+#[get("/groups/<name>/status")]
+pub async fn get_group_status(
+    name: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Json<GroupStatus>, ApiError> {
+    let members: Vec<String> = config
+        .group_members(&name)
+        .into_iter()
+        .filter(|m| is_visible(config, &identity, m))
+        .collect();
+    if members.is_empty() {
+        return Err(Status::NotFound.into());
+    }
+
+    let mut manager = manager.lock().await;
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let deployments: Vec<&crate::manager::Deployment> = members
+        .iter()
+        .filter_map(|m| manager.deployments().iter().find(|d| &d.name == m))
+        .collect();
+    let enabled: Vec<&&crate::manager::Deployment> = deployments
+        .iter()
+        .filter(|d| config.is_disabled(&d.name) == false)
+        .collect();
+    let state = if deployments
+        .iter()
+        .all(|d| d.state == crate::manager::State::Stopped)
+    {
+        "stopped"
+    } else if enabled.iter().all(|d| d.is_healthy()) {
+        "running"
+    } else {
+        "partial"
+    };
+
+    Ok(Json(GroupStatus {
+        name,
+        state: state.into(),
+        deployments: deployments
+            .iter()
+            .map(|d| Deployments::new(config, d))
+            .collect(),
+    }))
+}
+
+/// Restarts the deployment's existing container, the configuration is not reapplied - use
+/// recreate for that
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Deployment name"),
+        (
+            "timeout",
+            Query,
+            description = "Seconds to wait for the container to stop before it is killed"
+        ),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/restart?<timeout>")]
+pub async fn restart_deployment(
+    name: String,
+    timeout: Option<u64>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Restart of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Restart)?;
+    check_enabled(config, &name)?;
+
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    // There must be a container to restart
+    let result = manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == name && d.id.is_empty() == false);
+    if result.is_none() {
+        return Err(Status::NotFound.into());
+    }
+    let id = result.unwrap().id.to_owned();
+
+    docker.restart_container(&id, timeout).await?;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// Time allowed for an exec'd command when the request does not give one
+const DEFAULT_EXEC_TIMEOUT: u64 = 60;
+
+/// A command to run in a deployment's container, `command` is the program and its arguments
+/// (no shell is involved) and `timeout` the seconds it may run for
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ExecData {
+    command: Vec<String>,
+    #[serde(default)]
+    timeout: Option<u64>,
+}
+
+/// Runs a command in the deployment's container and returns its stdout, stderr and exit
+/// code, i.e. for diagnostics on a headless device. Only the start of the output is kept
+/// (see EXEC_OUTPUT_LIMIT) so this is meant for short commands, a command still running
+/// after the timeout is left running and 504 is returned
+#[utoipa::path(
+    tag = "diagnostics",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    request_body = ExecData,
+    responses(
+        (status = 200, description = "Command output", body = ExecResult),
+        (status = 400, description = "No command given", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (status = 409, description = "The container is not running", body = ApiError),
+        (
+            status = 504,
+            description = "The command did not finish within the timeout",
+            body = ApiError
+        ),
+    )
+)]
+#[post("/deployments/<name>/exec", data = "<exec>")]
+pub async fn exec_deployment(
+    name: String,
+    exec: Json<ExecData>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<Json<ExecResult>, ApiError> {
+    info!(
+        "Exec of {:?} in deployment '{}' requested by {}",
+        exec.command, name, identity
+    );
+    authorize(config, &identity, &name, Action::Exec)?;
+    if exec.command.is_empty() {
+        return Err(ApiError::new(Status::BadRequest, "No command given"));
+    }
+
+    let id = container_id(&name, config, docker, manager).await?;
+
+    let timeout = Duration::from_secs(exec.timeout.unwrap_or(DEFAULT_EXEC_TIMEOUT));
+    let result = with_timeout("exec", timeout, docker.exec(&id, &exec.command))
+        .await
+        .map_err(|e| match e {
+            DockerError::Conflict(_) => ApiError {
+                message: "Container is not running".into(),
+                ..ApiError::from(e)
+            },
+            e => ApiError::from(e),
+        })?;
+
+    Ok(Json(result))
+}
+
+/// Signals which may be sent with the kill route
+const KILL_SIGNALS: [&str; 8] = [
+    "SIGKILL", "SIGTERM", "SIGINT", "SIGQUIT", "SIGHUP", "SIGUSR1", "SIGUSR2", "SIGWINCH",
+];
+
+/// Sends a signal (SIGKILL by default) to the deployment's container, for containers which
+/// do not respond to a graceful stop. The signal may be given with or without the SIG
+/// prefix, signals outside of KILL_SIGNALS are rejected (400)
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 400, description = "Unsupported signal", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/kill?<signal>")]
+pub async fn kill_deployment(
+    name: String,
+    signal: Option<String>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    let signal = match signal {
+        Some(s) => {
+            let upper = s.to_uppercase();
+            match upper.starts_with("SIG") {
+                true => upper,
+                false => format!("SIG{}", upper),
+            }
+        }
+        None => "SIGKILL".to_owned(),
+    };
+    if KILL_SIGNALS.contains(&signal.as_str()) == false {
+        return Err(Status::BadRequest.into());
+    }
+
+    info!(
+        "Kill ({}) of deployment '{}' requested by {}",
+        signal, name, identity
+    );
+    authorize(config, &identity, &name, Action::Stop)?;
+
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let result = manager.deployments().iter().find(|d| d.name == name);
+    if result.is_none() {
+        return Err(Status::NotFound.into());
+    }
+    let deployment = result.unwrap();
+
+    // Only a running (or paused) container can be signalled
+    if deployment.state == crate::manager::State::Stopped {
+        return Err(Status::Conflict.into());
+    }
+
+    docker.kill_container(&deployment.id, &signal).await?;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// Freezes the deployment's container without stopping it, i.e. during a host backup
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (status = 409, description = "The container is not running", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/pause")]
+pub async fn pause_deployment(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Pause of deployment '{}' requested by {}", name, identity);
+    set_paused(&name, true, &identity, config, docker, manager).await
+}
+
+#[utoipa::path(
+    tag = "lifecycle",
+    summary = "Unpause the deployment's container",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (status = 409, description = "The container is not paused", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/unpause")]
+pub async fn unpause_deployment(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Unpause of deployment '{}' requested by {}", name, identity);
+    set_paused(&name, false, &identity, config, docker, manager).await
+}
+
+/// Pausing requires a running container and unpausing a paused one, otherwise 409
+async fn set_paused(
+    name: &str,
+    paused: bool,
+    identity: &ClientIdentity,
+    config: &Config,
+    docker: &SharedRuntime,
+    manager: &Arc<Mutex<Manager>>,
+) -> Result<(Status, String), ApiError> {
+    authorize(config, identity, name, Action::Pause)?;
+
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(config, docker.as_ref()).await?;
+
+    let result = manager.deployments().iter().find(|d| d.name == name);
+    if result.is_none() {
+        return Err(Status::NotFound.into());
+    }
+    let deployment = result.unwrap();
+
+    let required = match paused {
+        true => crate::manager::State::Running,
+        false => crate::manager::State::Paused,
+    };
+    if deployment.state != required {
+        return Err(Status::Conflict.into());
+    }
+
+    docker.set_paused(&deployment.id, paused).await?;
+    manager.mark_paused(name, paused);
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// New limits for a deployment's container, limits which are not given are left unchanged.
+/// `memory` takes the same sizes as the `--memory` run arg (i.e. `512m`)
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ResourceData {
+    #[serde(default)]
+    memory: Option<String>,
+    #[serde(default)]
+    cpus: Option<f64>,
+}
+
+/// Changes the cpu and memory limits of the deployment's container in place, i.e. to
+/// throttle a workload for a while. The change lasts until the container is recreated, when
+/// the limits from the deployment args apply again
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    request_body = ResourceData,
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 400, description = "Invalid limits", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (status = 422, description = "Docker cannot apply the limit on this host", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/resources", data = "<resources>")]
+pub async fn update_resources(
+    name: String,
+    resources: Json<ResourceData>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!(
+        "Resource update of deployment '{}' requested by {}",
+        name, identity
+    );
+    authorize(config, &identity, &name, Action::Resources)?;
+
+    let memory = match &resources.memory {
+        Some(m) => {
+            Some(run_args::parse_bytes(m).map_err(|e| ApiError::new(Status::BadRequest, e))?)
+        }
+        None => None,
+    };
+    let nano_cpus = match resources.cpus {
+        Some(c) if c > 0.0 => Some((c * 1e9) as i64),
+        Some(c) => {
+            return Err(ApiError::new(
+                Status::BadRequest,
+                format!("Invalid cpus ({})", c),
+            ));
+        }
+        None => None,
+    };
+    if memory.is_none() && nano_cpus.is_none() {
+        return Err(ApiError::new(
+            Status::BadRequest,
+            "No memory or cpus limit given",
+        ));
+    }
+
+    let info = docker.system_info().await?;
+    if let Some(message) =
+        Capabilities::from_info(&info).unsupported_limits(memory.is_some(), nano_cpus.is_some())
+    {
+        return Err(ApiError::new(Status::UnprocessableEntity, message));
+    }
+
+    // As with docker run, swap is allowed up to twice the memory limit. Docker refuses a
+    // memory limit above the current swap limit unless the swap limit is changed with it
+    let update = UpdateContainer {
+        memory,
+        memory_swap: memory.map(|m| m * 2),
+        nano_cpus,
+    };
+
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    // There must be a container to update
+    let result = manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == name && d.id.is_empty() == false);
+    if result.is_none() {
+        return Err(Status::NotFound.into());
+    }
+    let id = result.unwrap().id.to_owned();
+
+    docker
+        .update_resources(&id, &update)
+        .await
+        .map_err(|e| match e {
+            DockerError::InvalidRequest(_) => ApiError {
+                status: Status::BadRequest,
+                ..ApiError::from(e)
+            },
+            e => ApiError::from(e),
+        })?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// Adds a deployment, which is kept in deployments_file so it is still configured after a
+/// restart. It has no container until an image is loaded, pulled or built
+#[utoipa::path(
+    tag = "deployments",
+    request_body = config_file::Deployment,
+    responses(
+        (status = 201, description = "Deployment added", body = Object),
+        (status = 400, description = "The deployment is not valid", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 409, description = "A deployment with the name already exists", body = ApiError),
+    )
+)]
+#[post("/deployments", data = "<deployment>")]
+pub async fn create_deployment(
+    _writable: Writable,
+    identity: ClientIdentity,
+    deployment: Json<config_file::Deployment>,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    let mut deployment = deployment.into_inner();
+    info!(
+        "Creation of deployment '{}' requested by {}",
+        deployment.name, identity
+    );
+    authorize(config, &identity, "*", Action::Admin)?;
+    check_deployment(&deployment).map_err(|e| ApiError::new(Status::BadRequest, e))?;
+    deployment.added = true;
+
+    // Changes to the deployments are made with the manager locked, so they are not lost to
+    // another change made at the same time
+    let mut manager = manager.lock().await;
+    let mut deployments = config.deployments.get().as_ref().clone();
+    if deployments.iter().any(|d| d.name == deployment.name) {
+        return Err(ApiError::new(
+            Status::Conflict,
+            format!("deployment '{}' already exists", deployment.name),
+        ));
+    }
+    deployments.push(deployment);
+    set_deployments(config, deployments)?;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    Ok((Status::Created, "{}".into()))
+}
+
+/// Saves the deployments added through the API and then makes the change, the manager must be
+/// locked
+fn set_deployments(
+    config: &Config,
+    deployments: Vec<config_file::Deployment>,
+) -> Result<(), ApiError> {
+    save_deployments_file(config, &deployments).map_err(|e| {
+        println!(
+            "Unable to save deployments_file ({}): {}",
+            config.deployments_file.display(),
+            e
+        );
+        ApiError::internal("Unable to save the deployments", e)
+    })?;
+    config.deployments.set(deployments);
+
+    Ok(())
+}
+
+/// The deployments a config reload added, removed and changed
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ConfigReload {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Deployments whose definition changed, they keep running as they are until recreated
+    pub changed: Vec<String>,
+}
+
+/// Reads the config file again and applies the changes to the deployments. The containers of
+/// removed deployments are removed, their volumes and images are kept. Other settings only
+/// take effect when the daemon is restarted
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Deployments changed", body = ConfigReload),
+        (status = 400, description = "The config file is not valid", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+    )
+)]
+#[post("/config/reload")]
+pub async fn reload_config(
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<ConfigReload>), ApiError> {
+    info!("Config reload requested by {}", identity);
+    authorize(config, &identity, "*", Action::Admin)?;
+
+    let reloaded =
+        config_file::process_config_file(config.config_file.to_owned()).map_err(|e| {
+            println!("Unable to reload config: {}", e);
+            ApiError::new(Status::BadRequest, e)
+        })?;
+    let deployments = reloaded.deployments.get();
+
+    let mut manager = manager.lock().await;
+    let current = config.deployments.get();
+    let reload = ConfigReload {
+        added: deployments
+            .iter()
+            .filter(|d| current.iter().any(|c| c.name == d.name) == false)
+            .map(|d| d.name.to_owned())
+            .collect(),
+        removed: current
+            .iter()
+            .filter(|c| deployments.iter().any(|d| d.name == c.name) == false)
+            .map(|c| c.name.to_owned())
+            .collect(),
+        changed: deployments
+            .iter()
+            .filter(|d| current.iter().any(|c| c.name == d.name && c != *d))
+            .map(|d| d.name.to_owned())
+            .collect(),
+    };
+
+    for name in &reload.removed {
+        stop(name, docker.as_ref(), &mut manager, false).await.ok();
+        remove(name, docker.as_ref(), &mut manager, false)
+            .await
+            .ok();
+    }
+    config.deployments.set(deployments.as_ref().clone());
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+    manager.adopt_containers(&config, docker.as_ref()).await?;
+    if let Err(e) = manager.stop_disabled(&config, docker.as_ref()).await {
+        println!("Unable to stop disabled deployments: {}", e);
+    }
+
+    info!(
+        "Config reloaded, added {:?}, removed {:?}, changed {:?}",
+        reload.added, reload.removed, reload.changed
+    );
+    Ok((Status::Ok, Json(reload)))
+}
+
+/// What is removed along with the deployment's container
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteQuery {
+    /// Also remove the deployment's named volumes
+    volumes: Option<bool>,
+    /// Also remove a deployment added through the API, requires the admin action
+    definition: Option<bool>,
+}
+
+/// `definition` removes a deployment which was added through the API once its container is
+/// removed, deployments from the config file can only be removed by editing the file
+#[utoipa::path(
+    tag = "deployments",
+    params(
+        ("name", Path, description = "Deployment name"),
+        (
+            "dry_run" = Option<bool>,
+            Query,
+            description = "Check the request could be made and report what it would do without making it, returns a DryRun with the status the request would fail with"
+        ),
+        DeleteQuery,
+    ),
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment, or it has no container", body = ApiError),
+        (
+            status = 409,
+            description = "A volume is in use, or the definition was requested for a deployment from the config file",
+            body = ApiError
+        ),
+    )
+)]
+#[delete("/deployments/<name>?<query..>")]
+pub async fn delete_deployment(
+    name: String,
+    query: DeleteQuery,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Delete of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Delete)?;
+
+    let definition = query.definition.unwrap_or(false);
+    if definition {
+        authorize(config, &identity, "*", Action::Admin)?;
+        match config.deployments.get().iter().find(|d| d.name == name) {
+            Some(deployment) if deployment.added => {}
+            Some(_) => {
+                println!(
+                    "Unable to remove deployment '{}', it is defined in the config file",
+                    name
+                );
+                return Err(Status::Conflict.into());
+            }
+            None => return Err(Status::NotFound.into()),
+        }
+    }
+
+    let mut manager = manager.lock().await;
+
+    // Update the info on deployments in case the container is already stopped
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    // Declared volumes are kept unless removal is requested
+    let volumes: Vec<String> = match query.volumes.unwrap_or(false) {
+        true => config
+            .deployments
+            .get()
+            .iter()
+            .filter(|d| d.name == name)
+            .flat_map(|d| d.volumes.iter().map(|v| v.name.to_owned()))
+            .collect(),
+        false => vec![],
+    };
+
+    // A volume used by another container cannot be removed, refuse before the deployment's
+    // own container is removed rather than after
+    let own = manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == name)
+        .map(|d| d.id.to_owned())
+        .unwrap_or_default();
+    for volume in &volumes {
+        let users = docker.get_containers(&[("volume", volume)]).await?;
+        if users.iter().any(|c| c.id != own) {
+            println!("Unable to remove volume '{}', it is in use", volume);
+            return Err(ApiError::new(
+                Status::Conflict,
+                format!("volume '{}' is used by another container", volume),
+            ));
+        }
+    }
+
+    stop(&name, docker.as_ref(), &mut manager, false).await?;
+    remove(&name, docker.as_ref(), &mut manager, false).await?;
+
+    for volume in &volumes {
+        match docker.remove_volume(volume, false).await {
+            Ok(_) | Err(DockerError::NotFound(_)) => {}
+            Err(e) => {
+                println!("Unable to remove volume '{}': {}", volume, e);
+                return Err(e.into());
+            }
+        }
+    }
+
+    if definition {
+        let deployments = config
+            .deployments
+            .get()
+            .iter()
+            .filter(|d| d.name != name)
+            .cloned()
+            .collect();
+        set_deployments(config, deployments)?;
+        manager.update_deployments(&config, docker.as_ref()).await?;
+    }
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// What a request would do, reported by `?dry_run=true` instead of making the request. The
+/// status is the one the request would fail with, or 200 if it would succeed
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DryRun {
+    pub deployment: String,
+    pub action: Action,
+    /// What the request would do if it was made now
+    pub outcome: Vec<String>,
+    /// Why the request would fail, it would succeed if there are none
+    pub problems: Vec<String>,
+    #[serde(skip)]
+    status: Option<Status>,
+}
+
+impl DryRun {
+    fn new(deployment: &str, action: Action) -> Self {
+        DryRun {
+            deployment: deployment.to_owned(),
+            action,
+            outcome: vec![],
+            problems: vec![],
+            status: None,
+        }
+    }
+
+    fn problem(&mut self, status: Status, reason: impl Into<String>) {
+        self.status = self.status.or(Some(status));
+        self.problems.push(reason.into());
+    }
+
+    fn response(self) -> (Status, Json<DryRun>) {
+        (self.status.unwrap_or(Status::Ok), Json(self))
+    }
+
+    /// Checks shared by every dry run, returns the deployment's config and its container if
+    /// it exists
+    async fn check_deployment(
+        &mut self,
+        writable: Option<Writable>,
+        enabled: bool,
+        config: &Config,
+        docker: &dyn ContainerRuntime,
+        manager: &Mutex<Manager>,
+    ) -> Result<Option<(config_file::Deployment, crate::manager::Deployment)>, ApiError> {
+        if writable.is_none() {
+            self.problem(Status::Forbidden, "the daemon is read only");
+        }
+
+        let deployment = match config
+            .deployments
+            .get()
+            .iter()
+            .find(|d| d.name == self.deployment)
+        {
+            Some(deployment) => deployment.clone(),
+            None => {
+                self.problem(Status::NotFound, "the deployment does not exist");
+                return Ok(None);
+            }
+        };
+        if enabled && deployment.disabled {
+            self.problem(Status::Locked, "the deployment is disabled");
+        }
+
+        let mut manager = manager.lock().await;
+        manager.update_deployments(config, docker).await?;
+        Ok(manager
+            .deployments()
+            .iter()
+            .find(|d| d.name == self.deployment)
+            .map(|current| (deployment, current.clone())))
+    }
+
+    /// Host ports the deployment publishes which another container already publishes, docker
+    /// would refuse to start the container. Ports used by processes outside docker are not
+    /// known
+    async fn check_ports(
+        &mut self,
+        deployment: &config_file::Deployment,
+        container_id: &str,
+        docker: &dyn ContainerRuntime,
+    ) -> Result<(), ApiError> {
+        let args: Vec<&str> = deployment
+            .args
+            .iter()
+            .flatten()
+            .map(|a| a.as_str())
+            .collect();
+        let create = match run_args::parse(&args, "") {
+            Ok(create) => create,
+            Err(_) => return Ok(()),
+        };
+        let containers = docker.get_containers(&[]).await?;
+
+        for (port, bindings) in &create.host_config.port_bindings {
+            let protocol = port.split_once('/').map(|(_, p)| p).unwrap_or("tcp");
+            for binding in bindings {
+                let host_port = match binding.host_port.parse::<u16>() {
+                    Ok(host_port) => host_port,
+                    Err(_) => continue,
+                };
+                let used_by = containers.iter().find(|c| {
+                    c.id != container_id
+                        && c.state == "running"
+                        && c.ports
+                            .iter()
+                            .any(|p| p.public_port == Some(host_port) && p.protocol == protocol)
+                });
+                if let Some(container) = used_by {
+                    self.problem(
+                        Status::Conflict,
+                        format!(
+                            "port {}/{} is already published by {}",
+                            host_port,
+                            protocol,
+                            container
+                                .names
+                                .first()
+                                .map(|n| n.trim_start_matches("/"))
+                                .unwrap_or(&container.id)
+                        ),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[post("/deployments/<name>/start?dry_run=true")]
+pub async fn start_dry_run(
+    name: String,
+    writable: Option<Writable>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<DryRun>), ApiError> {
+    authorize(config, &identity, &name, Action::Start)?;
+
+    let mut dry_run = DryRun::new(&name, Action::Start);
+    let (deployment, current) = match dry_run
+        .check_deployment(writable, true, config, docker.as_ref(), manager)
+        .await?
+    {
+        Some(found) => found,
+        None => return Ok(dry_run.response()),
+    };
+
+    if current.state == crate::manager::State::Running {
+        dry_run
+            .outcome
+            .push("nothing, the deployment is already running".into());
+    } else if current.id.is_empty() {
+        dry_run.problem(
+            Status::NotFound,
+            "the deployment has no container, an image must be loaded, pulled or built",
+        );
+    } else {
+        dry_run
+            .outcome
+            .push(format!("start container {}", current.id));
+        dry_run
+            .check_ports(&deployment, &current.id, docker.as_ref())
+            .await?;
+    }
+
+    Ok(dry_run.response())
+}
+
+#[post("/deployments/<name>/stop?dry_run=true")]
+pub async fn stop_dry_run(
+    name: String,
+    writable: Option<Writable>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<DryRun>), ApiError> {
+    authorize(config, &identity, &name, Action::Stop)?;
+
+    let mut dry_run = DryRun::new(&name, Action::Stop);
+    let (_, current) = match dry_run
+        .check_deployment(writable, false, config, docker.as_ref(), manager)
+        .await?
+    {
+        Some(found) => found,
+        None => return Ok(dry_run.response()),
+    };
+
+    if current.id.is_empty() {
+        dry_run.problem(Status::NotFound, "the deployment has no container");
+    } else if current.state == crate::manager::State::Stopped {
+        dry_run
+            .outcome
+            .push("nothing, the deployment is already stopped".into());
+    } else {
+        dry_run
+            .outcome
+            .push(format!("stop container {}", current.id));
+    }
+
+    Ok(dry_run.response())
+}
+
+#[delete("/deployments/<name>?dry_run=true&<query..>")]
+pub async fn delete_dry_run(
+    name: String,
+    query: DeleteQuery,
+    writable: Option<Writable>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<DryRun>), ApiError> {
+    authorize(config, &identity, &name, Action::Delete)?;
+    let definition = query.definition.unwrap_or(false);
+    if definition {
+        authorize(config, &identity, "*", Action::Admin)?;
+    }
+
+    let mut dry_run = DryRun::new(&name, Action::Delete);
+    let (deployment, current) = match dry_run
+        .check_deployment(writable, false, config, docker.as_ref(), manager)
+        .await?
+    {
+        Some(found) => found,
+        None => return Ok(dry_run.response()),
+    };
+
+    match (current.id.is_empty(), &current.state) {
+        (true, _) => dry_run
+            .outcome
+            .push("nothing, the deployment has no container".into()),
+        (false, crate::manager::State::Stopped) => dry_run
+            .outcome
+            .push(format!("remove container {}", current.id)),
+        (false, _) => dry_run
+            .outcome
+            .push(format!("stop and remove container {}", current.id)),
+    }
+    if query.volumes.unwrap_or(false) {
+        for volume in &deployment.volumes {
+            dry_run
+                .outcome
+                .push(format!("remove volume '{}'", volume.name));
+        }
+    }
+    if definition {
+        match deployment.added {
+            true => dry_run.outcome.push("remove the deployment".into()),
+            false => dry_run.problem(
+                Status::Conflict,
+                "the deployment is defined in the config file",
+            ),
+        }
+    }
+
+    Ok(dry_run.response())
+}
+
+/// The upload is not read, `size` is the size of the tarball in bytes for the disk space to be
+/// checked
+#[post("/deployments/<name>/load?dry_run=true&<size>&<query..>", rank = 1)]
+pub async fn load_dry_run(
+    name: String,
+    size: Option<u64>,
+    query: LoadQuery,
+    writable: Option<Writable>,
+    identity: ClientIdentity,
+    daemon: Daemon<'_>,
+) -> Result<(Status, Json<DryRun>), ApiError> {
+    let Daemon {
+        config,
+        docker,
+        manager,
+        ..
+    } = daemon;
+    authorize(config, &identity, &name, Action::Load)?;
+
+    let mut dry_run = DryRun::new(&name, Action::Load);
+    let (deployment, current) = match dry_run
+        .check_deployment(writable, true, config, docker.as_ref(), manager)
+        .await?
+    {
+        Some(found) => found,
+        None => return Ok(dry_run.response()),
+    };
+
+    if let Err(e) = check_disk_space(config, docker.as_ref(), size).await {
+        dry_run.problem(docker_status(&e), e.to_string());
+    }
+
+    let prefix = config.container_prefix.trim_start_matches("/");
+    let latest = format!("{}{}:latest", prefix, name);
+    dry_run.outcome.push(format!(
+        "load {} from the upload and tag it {}",
+        query.image.as_deref().unwrap_or("the image"),
+        latest
+    ));
+    if config.protect_tags
+        && query.force != Some(true)
+        && image_id(docker.as_ref(), &latest).await.is_some()
+    {
+        dry_run.outcome.push(format!(
+            "refuse the load if the upload is not the image already tagged {}, unless forced",
+            latest
+        ));
+    }
+    if current.id.is_empty() == false {
+        dry_run
+            .outcome
+            .push(format!("stop and remove container {}", current.id));
+    }
+    dry_run
+        .outcome
+        .push("create and start a container from the loaded image".into());
+    dry_run
+        .check_ports(&deployment, &current.id, docker.as_ref())
+        .await?;
+
+    Ok(dry_run.response())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LoadResult {
+    pub outcome: String,
+    pub state: String,
+    pub health: String,
+    /// The internal repo:tag the deployment is now running
+    pub image: String,
+    /// The repo:tag (or id) of the image as it was loaded, pulled or built, before it was
+    /// retagged
+    pub source_image: Option<String>,
+}
+
+/// Response to a request run as a background job
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Accepted {
+    pub job: u64,
+    /// Where to follow the job
+    pub url: String,
+}
+
+impl Accepted {
+    fn new(job: u64) -> Self {
+        Accepted {
+            job,
+            url: format!("/v1/jobs/{}", job),
+        }
+    }
+}
+
+/// Status, latest progress and the result of a background load or pull. Clients may only see
+/// jobs for the deployments and action they are authorized for
+#[utoipa::path(
+    tag = "images",
+    responses(
+        (status = 200, description = "Job", body = Job),
+        (status = 403, description = "Not authorized for the job's deployment", body = ApiError),
+        (status = 404, description = "No such job", body = ApiError),
+    )
+)]
+#[get("/jobs/<id>")]
+pub async fn get_job(
+    id: u64,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    jobs: &State<Arc<Jobs>>,
+) -> Result<Json<Job>, ApiError> {
+    let job = jobs.get(id).ok_or(Status::NotFound)?;
+    authorize(config, &identity, &job.deployment, job.action)?;
+
+    Ok(Json(job))
+}
+
+/// Stops, removes and re-creates the container from the currently tagged image, so changes
+/// to the deployment configuration can be applied without uploading a new image
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Started", body = LoadResult),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 409, description = "No image has been loaded", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/recreate")]
+pub async fn recreate_deployment(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    info!(
+        "Recreate of deployment '{}' requested by {}",
+        name, identity
+    );
+    authorize(config, &identity, &name, Action::Recreate)?;
+    check_enabled(config, &name)?;
+
+    let mut manager = manager.lock().await;
+
+    if config.deployments.get().iter().any(|d| d.name == name) == false {
+        return Err(Status::NotFound.into());
+    }
+
+    // There must already be an image to recreate the container from
+    let image = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        name
+    );
+    let images = docker.get_images().await?;
+    if images.iter().any(|i| i.repo_tags.contains(&image)) == false {
+        return Err(Status::Conflict.into());
+    }
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let config = config.inner();
+    return start_container(&name, config, docker.as_ref(), &mut manager, None).await;
+}
+
+/// Swaps the deployment back to the image it ran before its last load, pull or build and
+/// starts it. The image it is rolled back from becomes the previous image, so a rollback can
+/// be undone with another rollback
+#[utoipa::path(
+    tag = "lifecycle",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 200, description = "Started", body = LoadResult),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment", body = ApiError),
+        (status = 409, description = "The deployment has no previous image", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/rollback")]
+pub async fn rollback_deployment(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+    history: &State<Arc<History>>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    info!(
+        "Rollback of deployment '{}' requested by {}",
+        name, identity
+    );
+    authorize(config, &identity, &name, Action::Rollback)?;
+    check_enabled(config, &name)?;
+    if config.deployments.get().iter().any(|d| d.name == name) == false {
+        return Err(Status::NotFound.into());
+    }
+
+    let prefix = config.container_prefix.trim_start_matches("/");
+    let latest_name = format!("{}{}:latest", prefix, name);
+    let previous_name = format!("{}{}:previous", prefix, name);
+
+    let mut manager = manager.lock().await;
+    let previous = image_id(docker.as_ref(), &previous_name)
+        .await
+        .ok_or(ApiError::new(
+            Status::Conflict,
+            format!("deployment '{}' has no previous image", name),
+        ))?;
+    let latest = image_id(docker.as_ref(), &latest_name).await;
+
+    let tagged = match docker.tag_image(&previous, &latest_name).await {
+        Ok(_) => match &latest {
+            Some(latest) => docker.tag_image(latest, &previous_name).await,
+            None => Ok(()),
+        },
+        Err(e) => Err(e),
+    };
+    tagged.map_err(|e| {
+        println!("Unable to roll back deployment '{}': {}", name, e);
+        ApiError::from(e)
+    })?;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+    manager.set_digest(&name, None);
+
+    let config = config.inner();
+    let result =
+        start_container(&name, config, docker.as_ref(), &mut manager, Some(previous)).await;
+    if result.is_ok() {
+        manager.publish_loaded(&name);
+    }
+    record_history(
+        history,
+        &name,
+        Action::Rollback,
+        &identity.to_string(),
+        &result,
+        docker.as_ref(),
+        &manager,
+    )
+    .await;
+
+    result
+}
+
+/// How an uploaded tarball is loaded as the deployment image
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LoadQuery {
+    /// Overwrite the deployment image even if protect_tags is set
+    force: Option<bool>,
+    /// The image to deploy when the tarball contains more than one
+    image: Option<String>,
+}
+
+/// `image` chooses the image to deploy when the tarball contains more than one
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("name", Path, description = "Deployment name"),
+        (
+            "dry_run" = Option<bool>,
+            Query,
+            description = "Check the request could be made and report what it would do without making it, returns a DryRun with the status the request would fail with. The upload is not read"
+        ),
+        (
+            "size" = Option<u64>,
+            Query,
+            description = "With dry_run, the size of the tarball in bytes for the disk space to be checked"
+        ),
+        LoadQuery,
+        (
+            "background" = Option<bool>,
+            Query,
+            description = "Run the load in the background once the upload is received, give the parameter without a value"
+        ),
+        (
+            "X-Checksum-Sha256" = Option<String>,
+            Header,
+            description = "Hex SHA-256 of the tarball, the load is rejected if it does not match"
+        ),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/x-tar"),
+    responses(
+        (status = 200, description = "Started", body = LoadResult),
+        (status = 202, description = "Started as a background job", body = Accepted),
+        (status = 400, description = "Invalid checksum header", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 409, description = "The deployment image is protected", body = ApiError),
+        (status = 413, description = "The upload is larger than 2 GiB", body = ApiError),
+        (status = 415, description = "Not a supported tarball", body = ApiError),
+        (status = 422, description = "The upload does not match its checksum", body = ApiError),
+        (status = 507, description = "Not enough disk space", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/load?<query..>", data = "<upload>", rank = 3)]
+pub async fn load_file(
+    name: String,
+    query: LoadQuery,
+    _writable: Writable,
+    identity: ClientIdentity,
+    checksum: Checksum,
+    mut upload: CappedUpload<'_>,
+    daemon: Daemon<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let Daemon { config, docker, .. } = daemon;
+    info!("Load of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Load)?;
+    check_enabled(config, &name)?;
+
+    check_disk_space(config, docker.as_ref(), upload.length)
+        .await
+        .map_err(|e| {
+            println!("Unable to load image for deployment '{}': {}", name, e);
+            ApiError::from(e)
+        })?;
+
+    // The upload is streamed straight to docker rather than saved to a temporary file first
+    let expected = match checksum.0 {
+        Some(expected) => expected,
+        None => {
+            let result = load_deployment(
+                &name,
+                &mut upload,
+                &query,
+                &daemon,
+                &identity.to_string(),
+                &mut log_progress(&name),
+            )
+            .await;
+            if upload.exceeded() {
+                return Err(too_large());
+            }
+            return result;
+        }
+    };
+
+    // Verified as it streams, docker does not get the end of the upload if it does not match
+    let mut verified = VerifyingReader::new(&mut upload, expected);
+    let result = load_deployment(
+        &name,
+        &mut verified,
+        &query,
+        &daemon,
+        &identity.to_string(),
+        &mut log_progress(&name),
+    )
+    .await;
+    let mismatch = verified.mismatch().map(|m| m.to_owned());
+    if upload.exceeded() {
+        return Err(too_large());
+    }
+    if let Some(mismatch) = mismatch {
+        println!("Rejected upload for deployment '{}': {}", name, mismatch);
+        return Err(ApiError::new(Status::UnprocessableEntity, mismatch));
+    }
+    result
+}
+
+/// As load, but the response is `202 Accepted` with a job once the upload has been received.
+/// The upload is saved to a temporary file and loaded in the background, follow the job at
+/// `/v1/jobs/<id>` for progress and the result
+#[post(
+    "/deployments/<name>/load?background&<query..>",
+    data = "<upload>",
+    rank = 2
+)]
+pub async fn load_file_in_background(
+    name: String,
+    query: LoadQuery,
+    _writable: Writable,
+    identity: ClientIdentity,
+    checksum: Checksum,
+    mut upload: CappedUpload<'_>,
+    daemon: Daemon<'_>,
+) -> Result<(Status, Json<Accepted>), ApiError> {
+    let Daemon {
+        config,
+        docker,
+        manager,
+        history,
+        jobs,
+    } = daemon;
+    info!(
+        "Background load of deployment '{}' requested by {}",
+        name, identity
+    );
+    authorize(config, &identity, &name, Action::Load)?;
+    check_enabled(config, &name)?;
+
+    check_disk_space(config, docker.as_ref(), upload.length)
+        .await
+        .map_err(|e| {
+            println!("Unable to load image for deployment '{}': {}", name, e);
+            ApiError::from(e)
+        })?;
+
+    let id = jobs.create(Action::Load, &name);
+    let filename = temporary_file("load", &name, "tar");
+    let saved = match tokio::fs::File::create(&filename).await {
+        Ok(mut file) => tokio::io::copy(&mut upload, &mut file).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let saved = match saved {
+        Ok(()) => Ok(()),
+        Err(_) if upload.exceeded() => Err(too_large()),
+        Err(e) => Err(ApiError::internal("Unable to save the upload", e)),
+    };
+    // The saved upload is verified before the job is started, so a mismatch is reported here
+    let saved = match (saved, checksum.0) {
+        (Ok(()), Some(expected)) => match verify_file(&filename, &expected).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(mismatch)) => Err(ApiError::new(Status::UnprocessableEntity, mismatch)),
+            Err(e) => Err(ApiError::internal("Unable to verify the upload", e)),
+        },
+        (saved, _) => saved,
+    };
+    if let Err(e) = saved {
+        println!(
+            "Unable to save upload for deployment '{}': {}",
+            name,
+            e.detail.as_ref().unwrap_or(&e.message)
+        );
+        tokio::fs::remove_file(&filename).await.ok();
+        jobs.finish::<()>(id, Err(&e));
+        return Err(e);
+    }
+
+    let config = config.clone();
+    let docker = docker.clone();
+    let manager = manager.clone();
+    let history = history.clone();
+    let client = identity.to_string();
+    let jobs = jobs.clone();
+    tokio::spawn(async move {
+        let daemon = Daemon {
+            config: &config,
+            docker: &docker,
+            manager: &manager,
+            history: &history,
+            jobs: &jobs,
+        };
+        let result = match tokio::fs::File::open(&filename).await {
+            Ok(mut file) => {
+                load_deployment(
+                    &name,
+                    &mut file,
+                    &query,
+                    &daemon,
+                    &client,
+                    &mut job_progress(&name, &jobs, id),
+                )
+                .await
+            }
+            Err(e) => Err(ApiError::internal("Unable to open the upload", e)),
+        };
+        tokio::fs::remove_file(&filename).await.ok();
+
+        jobs.finish(id, result.as_ref().map(|(_, result)| &result.0));
+    });
+
+    Ok((Status::Accepted, Json(Accepted::new(id))))
+}
+
+/// Loads an image tarball as the deployment image and starts it, shared by the load routes.
+/// The outcome is recorded in the deployment's history against the client
+async fn load_deployment(
+    name: &str,
+    upload: &mut (dyn AsyncRead + Send + Unpin),
+    query: &LoadQuery,
+    daemon: &Daemon<'_>,
+    client: &str,
+    progress: &mut Progress<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let Daemon {
+        docker,
+        manager,
+        history,
+        ..
+    } = *daemon;
+    let result = load_and_start(name, upload, query, daemon, progress).await;
+    record_history(
+        history,
+        name,
+        Action::Load,
+        client,
+        &result,
+        docker.as_ref(),
+        &*manager.lock().await,
+    )
+    .await;
+
+    result
+}
+
+async fn load_and_start(
+    name: &str,
+    upload: &mut (dyn AsyncRead + Send + Unpin),
+    query: &LoadQuery,
+    daemon: &Daemon<'_>,
+    progress: &mut Progress<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let Daemon {
+        config,
+        docker,
+        manager,
+        ..
+    } = *daemon;
+    let image_name = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        name
+    );
+    let previous_image = image_id(docker.as_ref(), &image_name).await;
+
+    let overwrite = config.protect_tags == false || query.force.unwrap_or(false);
+    let source_image = docker
+        .load_container_image_stream(
+            upload,
+            query.image.as_deref(),
+            &image_name,
+            overwrite,
+            progress,
+        )
+        .await
+        .map_err(|e| {
+            println!("Unable to load image for deployment '{}': {}", name, e);
+            ApiError::from(e)
+        })?;
+
+    // The manager is only locked once the image is loaded, so other requests are not held
+    // up for the length of the load
+    let mut manager = manager.lock().await;
+    manager.set_digest(name, None);
+
+    let result = start_container(
+        name,
+        config,
+        docker.as_ref(),
+        &mut manager,
+        Some(source_image),
+    )
+    .await;
+    if result.is_ok() {
+        manager.publish_loaded(name);
+        keep_previous_image(config, docker.as_ref(), name, previous_image).await;
+    }
+
+    return result;
+}
+
+/// Starts a chunked upload of an image tarball for the deployment. Chunks are appended with
+/// `PATCH /v1/uploads/<id>?offset=` and the image is loaded by committing the upload, an
+/// interrupted transfer is resumed from the offset reported by `GET /v1/uploads/<id>`
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("name", Path, description = "Deployment name"),
+    ),
+    responses(
+        (status = 201, description = "Upload started", body = Upload),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such deployment", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/uploads")]
+pub async fn create_upload(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    uploads: &State<Uploads>,
+) -> Result<(Status, Json<Upload>), ApiError> {
+    info!("Upload for deployment '{}' started by {}", name, identity);
+    authorize(config, &identity, &name, Action::Load)?;
+    check_enabled(config, &name)?;
+    if config.deployments.get().iter().any(|d| d.name == name) == false {
+        return Err(Status::NotFound.into());
+    }
+
+    let upload = uploads.create(&name).await.map_err(|e| {
+        println!("Unable to start upload for deployment '{}': {}", name, e);
+        ApiError::internal("Unable to start the upload", e)
+    })?;
+
+    Ok((Status::Created, Json(upload)))
+}
+
+/// The upload with the offset the next chunk must start at
+#[utoipa::path(
+    tag = "images",
+    responses(
+        (status = 200, description = "Upload", body = Upload),
+        (status = 403, description = "Not authorized", body = ApiError),
+        (status = 404, description = "No such upload", body = ApiError),
+    )
+)]
+#[get("/uploads/<id>")]
+pub async fn get_upload(
+    id: String,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    uploads: &State<Uploads>,
+) -> Result<Json<Upload>, ApiError> {
+    let upload = find_upload(&id, &identity, config, uploads).await?;
+
+    Ok(Json(upload))
+}
+
+/// Appends the request body to the upload, `offset` must be the current offset of the upload
+/// or `409 Conflict` is returned. Whatever part of the chunk arrives is kept, so after a
+/// failure the client asks for the offset and continues from there
+#[utoipa::path(
+    tag = "images",
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "Chunk appended", body = Upload),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such upload", body = ApiError),
+        (
+            status = 409,
+            description = "The offset is not the upload's offset, or a chunk is being appended or the upload committed",
+            body = ApiError
+        ),
+        (status = 413, description = "The chunk is larger than 2 GiB", body = ApiError),
+    )
+)]
+#[patch("/uploads/<id>?<offset>", data = "<chunk>")]
+pub async fn append_upload(
+    id: String,
+    offset: u64,
+    _writable: Writable,
+    identity: ClientIdentity,
+    mut chunk: CappedUpload<'_>,
+    config: &State<Arc<Config>>,
+    uploads: &State<Uploads>,
+) -> Result<Json<Upload>, ApiError> {
+    find_upload(&id, &identity, config, uploads).await?;
+
+    let upload = uploads
+        .append(&id, offset, &mut chunk)
+        .await
+        .map_err(|e| match e {
+            UploadError::NotFound => ApiError::new(Status::NotFound, e.to_string()),
+            UploadError::Offset { .. } | UploadError::Busy => {
+                ApiError::new(Status::Conflict, e.to_string())
+            }
+            UploadError::Io(_) if chunk.exceeded() => too_large(),
+            UploadError::Io(_) => ApiError::internal("Unable to save the chunk", e),
+        })?;
+
+    Ok(Json(upload))
+}
+
+/// Loads the completed upload as the deployment image and starts it, as for load. The
+/// upload is removed once the image has been loaded
+#[utoipa::path(
+    tag = "images",
+    params(
+        LoadQuery,
+        (
+            "X-Checksum-Sha256" = Option<String>,
+            Header,
+            description = "Hex SHA-256 of the tarball, the load is rejected if it does not match"
+        ),
+    ),
+    responses(
+        (status = 200, description = "Started", body = LoadResult),
+        (status = 400, description = "Invalid checksum header", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such upload", body = ApiError),
+        (
+            status = 409,
+            description = "The deployment image is protected, or the upload is being appended to or committed",
+            body = ApiError
+        ),
+        (status = 415, description = "Not a supported tarball", body = ApiError),
+        (status = 422, description = "The upload does not match its checksum", body = ApiError),
+        (status = 507, description = "Not enough disk space", body = ApiError),
+    )
+)]
+#[post("/uploads/<id>/commit?<query..>")]
+pub async fn commit_upload(
+    id: String,
+    query: LoadQuery,
+    _writable: Writable,
+    identity: ClientIdentity,
+    checksum: Checksum,
+    daemon: Daemon<'_>,
+    uploads: &State<Uploads>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let Daemon { config, docker, .. } = daemon;
+    let upload = find_upload(&id, &identity, config, uploads).await?;
+    let name = upload.deployment;
+    info!(
+        "Load of deployment '{}' from upload {} requested by {}",
+        name, id, identity
+    );
+    check_enabled(config, &name)?;
+
+    // Held until the load has finished, chunks cannot be appended to the data being loaded
+    let _committing = uploads
+        .begin_commit(&id)
+        .map_err(|e| ApiError::new(Status::Conflict, e.to_string()))?;
+
+    check_disk_space(config, docker.as_ref(), Some(upload.offset))
+        .await
+        .map_err(|e| {
+            println!("Unable to load image for deployment '{}': {}", name, e);
+            ApiError::from(e)
+        })?;
+
+    if let Some(expected) = checksum.0 {
+        match verify_file(&uploads.data_path(&id), &expected).await {
+            Ok(Ok(())) => {}
+            Ok(Err(mismatch)) => {
+                println!(
+                    "Rejected upload {} for deployment '{}': {}",
+                    id, name, mismatch
+                );
+                return Err(ApiError::new(Status::UnprocessableEntity, mismatch));
+            }
+            Err(e) => return Err(ApiError::internal("Unable to verify the upload", e)),
+        }
+    }
+
+    let mut file = tokio::fs::File::open(uploads.data_path(&id))
+        .await
+        .map_err(|e| ApiError::internal("Unable to open the upload", e))?;
+    let result = load_deployment(
+        &name,
+        &mut file,
+        &query,
+        &daemon,
+        &identity.to_string(),
+        &mut log_progress(&name),
+    )
+    .await;
+
+    // A failed load is kept so it can be committed again, i.e. once disk space is freed
+    if result.is_ok() {
+        uploads.remove(&id).await;
+    }
+    result
+}
+
+/// Discards an upload
+#[utoipa::path(
+    tag = "images",
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such upload", body = ApiError),
+    )
+)]
+#[delete("/uploads/<id>")]
+pub async fn abort_upload(
+    id: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    uploads: &State<Uploads>,
+) -> Result<(Status, String), ApiError> {
+    find_upload(&id, &identity, config, uploads).await?;
+    uploads.remove(&id).await;
+
+    Ok((Status::Ok, "{}".into()))
+}
+
+/// The upload if it exists and the client may load images for its deployment
+async fn find_upload(
+    id: &str,
+    identity: &ClientIdentity,
+    config: &Config,
+    uploads: &Uploads,
+) -> Result<Upload, ApiError> {
+    let upload = uploads
+        .get(id)
+        .await
+        .map_err(|e| ApiError::internal("Unable to read the upload", e))?
+        .ok_or(Status::NotFound)?;
+    authorize(config, identity, &upload.deployment, Action::Load)?;
+
+    Ok(upload)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CreateApiKey {
+    pub name: String,
+    /// Deployment names, `*` or prefixes ending in `*`
+    pub deployments: Vec<String>,
+    pub actions: Vec<Action>,
+    /// Seconds until the key expires, keys without one last until they are revoked
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    /// The key itself, only ever returned here
+    pub key: String,
+}
+
+/// Issues an API key permitted the requested actions on the requested deployments, whatever
+/// the authorization rules say. The key is only returned in this response
+#[utoipa::path(
+    tag = "daemon",
+    request_body = CreateApiKey,
+    responses(
+        (status = 201, description = "API key issued", body = CreatedApiKey),
+        (status = 400, description = "Empty name, or admin requested", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+    )
+)]
+#[post("/apikeys", data = "<request>")]
+pub async fn create_api_key(
+    request: Json<CreateApiKey>,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    api_keys: &State<ApiKeys>,
+) -> Result<(Status, Json<CreatedApiKey>), ApiError> {
+    authorize(config, &identity, "*", Action::Admin)?;
+    let request = request.into_inner();
+    if request.name.trim().is_empty() {
+        return Err(ApiError::new(Status::BadRequest, "name must not be empty"));
+    }
+    if request.actions.contains(&Action::Admin) {
+        return Err(ApiError::new(
+            Status::BadRequest,
+            "API keys cannot be granted admin",
+        ));
+    }
+
+    let expires = request.expires_in.map(|e| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            .saturating_add(e)
+    });
+    let grant = Grant {
+        deployments: request.deployments,
+        actions: request.actions,
+    };
+    let (api_key, key) = api_keys
+        .create(request.name.trim(), grant, expires)
+        .map_err(|e| {
+            println!("Unable to save API keys: {}", e);
+            ApiError::internal("Unable to save the API keys", e)
+        })?;
+    info!(
+        "API key {} ({}) issued by {}",
+        api_key.id, api_key.name, identity
+    );
+
+    Ok((Status::Created, Json(CreatedApiKey { api_key, key })))
+}
+
+/// The issued API keys, the keys themselves are never returned
+#[utoipa::path(
+    tag = "daemon",
+    params(
+        ("offset", Query, description = "Items to skip"),
+        ("limit", Query, description = "Items to return"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "API keys",
+            body = [ApiKey],
+            headers(("X-Total-Count" = usize, description = "Number of items in the whole list"))
+        ),
+        (status = 403, description = "Not authorized", body = ApiError),
+    )
+)]
+#[get("/apikeys?<offset>&<limit>")]
+pub async fn get_api_keys(
+    offset: Option<usize>,
+    limit: Option<usize>,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    api_keys: &State<ApiKeys>,
+) -> Result<Page<ApiKey>, ApiError> {
+    authorize(config, &identity, "*", Action::Admin)?;
+
+    Ok(Page::new(api_keys.list(), offset, limit))
+}
+
+/// Revokes an API key, requests using it are rejected with 401 immediately
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such API key", body = ApiError),
+    )
+)]
+#[delete("/apikeys/<id>")]
+pub async fn delete_api_key(
+    id: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    api_keys: &State<ApiKeys>,
+) -> Result<(Status, String), ApiError> {
+    authorize(config, &identity, "*", Action::Admin)?;
+
+    let revoked = api_keys.revoke(&id).map_err(|e| {
+        println!("Unable to save API keys: {}", e);
+        ApiError::internal("Unable to save the API keys", e)
+    })?;
+    if revoked == false {
+        return Err(Status::NotFound.into());
+    }
+    info!("API key {} revoked by {}", id, identity);
+
+    Ok((Status::Ok, "{}".into()))
+}
+
+/// How a build context is built into the deployment image
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BuildQuery {
+    /// Overwrite the deployment image even if protect_tags is set
+    force: Option<bool>,
+    /// Path of the Dockerfile within the context
+    dockerfile: Option<String>,
+}
+
+/// Builds the deployment image on the device from an uploaded tar build context, then starts
+/// it as for load. `dockerfile` is the path of the Dockerfile within the context
+#[utoipa::path(
+    tag = "images",
+    params(
+        ("name", Path, description = "Deployment name"),
+        BuildQuery,
+    ),
+    request_body(content = Vec<u8>, content_type = "application/x-tar"),
+    responses(
+        (status = 200, description = "Started", body = LoadResult),
+        (status = 400, description = "The build failed", body = ApiError),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 409, description = "The deployment image is protected", body = ApiError),
+        (status = 413, description = "The upload is larger than 2 GiB", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/build?<query..>", data = "<upload>")]
+pub async fn build_deployment(
+    name: String,
+    query: BuildQuery,
+    _writable: Writable,
+    identity: ClientIdentity,
+    mut upload: CappedUpload<'_>,
+    daemon: Daemon<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let Daemon {
+        config,
+        docker,
+        manager,
+        history,
+        ..
+    } = daemon;
+    info!("Build of deployment '{}' requested by {}", name, identity);
+    authorize(config, &identity, &name, Action::Build)?;
+    check_enabled(config, &name)?;
+
+    check_disk_space(config, docker.as_ref(), upload.length)
+        .await
+        .map_err(|e| {
+            println!("Unable to build image for deployment '{}': {}", name, e);
+            ApiError::from(e)
+        })?;
+
+    let image_name = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        name
+    );
+    let previous_image = image_id(docker.as_ref(), &image_name).await;
+
+    let overwrite = config.protect_tags == false || query.force.unwrap_or(false);
+    let source_image = docker
+        .build_image(
+            &mut upload,
+            query.dockerfile.as_deref(),
+            &image_name,
+            overwrite,
+            &mut log_progress(&name),
+        )
+        .await
+        .map_err(|e| {
+            println!("Unable to build image for deployment '{}': {}", name, e);
+            match upload.exceeded() {
+                true => too_large(),
+                false => ApiError::from(e),
+            }
+        });
+
+    // As with load, the manager is only locked once the build is done
+    let mut manager = manager.lock().await;
+    let source_image = match source_image {
+        Ok(source_image) => source_image,
+        Err(e) => {
+            let result = Err(e);
+            record_history(
+                history,
+                &name,
+                Action::Build,
+                &identity.to_string(),
+                &result,
+                docker.as_ref(),
+                &manager,
+            )
+            .await;
+            return result;
+        }
+    };
+    manager.set_digest(&name, None);
+
+    let result = start_container(
+        &name,
+        config,
+        docker.as_ref(),
+        &mut manager,
+        Some(source_image),
+    )
+    .await;
+    if result.is_ok() {
+        manager.publish_loaded(&name);
+        keep_previous_image(config, docker.as_ref(), &name, previous_image).await;
+    }
+    record_history(
+        history,
+        &name,
+        Action::Build,
+        &identity.to_string(),
+        &result,
+        docker.as_ref(),
+        &manager,
+    )
+    .await;
+
+    return result;
+}
+
+/// `path` is either a registry image reference (i.e. `registry/app:tag`) which is pulled
+/// by docker, or a `http(s)://` url to an image tarball which is downloaded and loaded
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PullData {
+    path: String,
+    /// Overwrite the deployment image even if protect_tags is set
+    #[serde(default)]
+    force: bool,
+    /// For a url, the image to deploy when the tarball contains more than one
+    #[serde(default)]
+    image: Option<String>,
+}
+
+enum PullSource<'a> {
+    Url(&'a str),
+    Registry(&'a str),
+}
+
+impl PullData {
+    /// Check the path is a well formed url or image reference before it is handed to docker,
+    /// the error is a message suitable to return to the client
+    fn source(&self) -> Result<PullSource<'_>, String> {
+        let path = self.path.trim();
+        if path.is_empty() {
+            return Err("path must not be empty".into());
+        }
+
+        if let Some((scheme, _)) = path.split_once("://") {
+            if scheme != "http" && scheme != "https" {
+                return Err(format!(
+                    "unsupported url scheme '{}', only http and https are supported",
+                    scheme
+                ));
+            }
+            let uri: hyper::Uri = path
+                .parse()
+                .map_err(|e| format!("path is not a valid url: {}", e))?;
+            if uri.host().is_none() {
+                return Err("url must include a host".into());
+            }
+            return Ok(PullSource::Url(path));
+        }
+
+        PullData::check_reference(path)?;
+        Ok(PullSource::Registry(path))
+    }
+
+    /// Loose check of an image reference - `[registry[:port]/]repo[/repo...][:tag][@digest]`
+    fn check_reference(reference: &str) -> Result<(), String> {
+        if reference.len() > 255 {
+            return Err("image reference must be 255 characters or less".into());
+        }
+
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (reference, None),
+        };
+
+        if let Some(digest) = digest {
+            let valid = match digest.split_once(':') {
+                Some((algorithm, hex)) => {
+                    algorithm.is_empty() == false
+                        && hex.is_empty() == false
+                        && hex.chars().all(|c| c.is_ascii_hexdigit())
+                }
+                None => false,
+            };
+            if valid == false {
+                return Err(format!("image digest '{}' is not valid", digest));
+            }
+        }
+
+        // A tag follows the last ':' as long as it is after the last '/' (otherwise it is a port)
+        let (repository, tag) = match name.rsplit_once(':') {
+            Some((repository, tag)) if tag.contains('/') == false => (repository, Some(tag)),
+            _ => (name, None),
+        };
+
+        if let Some(tag) = tag {
+            let valid = tag.len() <= 128
+                && tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+            if valid == false {
+                return Err(format!("image tag '{}' is not valid", tag));
+            }
+        }
+
+        for (index, component) in repository.split('/').enumerate() {
+            // The first component may be a registry host, which can include a port
+            let registry = index == 0 && repository.contains('/');
+            let valid = component.is_empty() == false
+                && component.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && component.ends_with(|c: char| c.is_ascii_alphanumeric())
+                && component.chars().all(|c| {
+                    c.is_ascii_lowercase()
+                        || c.is_ascii_digit()
+                        || c == '.'
+                        || c == '_'
+                        || c == '-'
+                        || (registry && (c.is_ascii_uppercase() || c == ':'))
+                });
+            if valid == false {
+                return Err(format!(
+                    "image reference '{}' is not valid, '{}' is not a valid name component",
+                    reference, component
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[utoipa::path(
+    tag = "images",
+    summary = "Pull an image from a registry or url and start it",
+    params(
+        ("name", Path, description = "Deployment name"),
+        (
+            "progress" = Option<bool>,
+            Query,
+            description = "Stream progress as server sent events (progress, then result or error), give the parameter without a value"
+        ),
+        (
+            "background" = Option<bool>,
+            Query,
+            description = "Run the pull in the background, give the parameter without a value"
+        ),
+    ),
+    request_body = PullData,
+    responses(
+        (status = 200, description = "Started", body = LoadResult),
+        (status = 202, description = "Started as a background job", body = Accepted),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 409, description = "The deployment image is protected", body = ApiError),
+    )
+)]
+#[post("/deployments/<name>/pull", data = "<pull>", rank = 3)]
+pub async fn pull(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    pull: Json<PullData>,
+    daemon: Daemon<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    info!("Pull of deployment '{}' requested by {}", name, identity);
+    check_pull(&name, &identity, &pull, daemon.config)?;
+
+    pull_deployment(
+        &name,
+        &pull,
+        &daemon,
+        &identity.to_string(),
+        &mut log_progress(&name),
+    )
+    .await
+}
+
+/// As pull, but the response is a stream of server sent events. Each message docker reports
+/// while pulling is sent as a `progress` event, followed by a `result` event with the load
+/// result or an `error` event with the status code and reason.
+///
+/// The pull continues if the client disconnects.
+#[post("/deployments/<name>/pull?progress", data = "<pull>", rank = 1)]
+pub async fn pull_with_progress(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    pull: Json<PullData>,
+    daemon: Daemon<'_>,
+) -> Result<EventStream![], ApiError> {
+    info!(
+        "Pull of deployment '{}' with progress requested by {}",
+        name, identity
+    );
+    check_pull(&name, &identity, &pull, daemon.config)?;
+
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let pull = pull.into_inner();
+    let config = daemon.config.clone();
+    let docker = daemon.docker.clone();
+    let manager = daemon.manager.clone();
+    let history = daemon.history.clone();
+    let jobs = daemon.jobs.clone();
+    let client = identity.to_string();
+    tokio::spawn(async move {
+        let daemon = Daemon {
+            config: &config,
+            docker: &docker,
+            manager: &manager,
+            history: &history,
+            jobs: &jobs,
+        };
+        let mut progress = |message: &ProgressMessage| {
+            if let Some(text) = message.text() {
+                info!("{}: {}", name, text);
+            }
+            sender.send(Event::json(message).event("progress")).ok();
+        };
+        let result = pull_deployment(&name, &pull, &daemon, &client, &mut progress).await;
+
+        let event = match result {
+            Ok((_, result)) => Event::json(&result.into_inner()).event("result"),
+            Err(e) => Event::json(&json!({
+                "status": e.status.code,
+                "reason": e.message,
+                "code": e.code,
+                "detail": e.detail,
+            }))
+            .event("error"),
+        };
+        sender.send(event).ok();
+    });
+
+    Ok(EventStream! {
+        while let Some(event) = receiver.recv().await {
+            yield event;
+        }
+    })
+}
+
+/// As pull, but the response is `202 Accepted` with a job straight away and the pull runs in
+/// the background, follow the job at `/v1/jobs/<id>` for progress and the result
+#[post("/deployments/<name>/pull?background", data = "<pull>", rank = 2)]
+pub async fn pull_in_background(
+    name: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    pull: Json<PullData>,
+    daemon: Daemon<'_>,
+) -> Result<(Status, Json<Accepted>), ApiError> {
+    info!(
+        "Background pull of deployment '{}' requested by {}",
+        name, identity
+    );
+    check_pull(&name, &identity, &pull, daemon.config)?;
+
+    let id = daemon.jobs.create(Action::Pull, &name);
+    let pull = pull.into_inner();
+    let config = daemon.config.clone();
+    let docker = daemon.docker.clone();
+    let manager = daemon.manager.clone();
+    let history = daemon.history.clone();
+    let client = identity.to_string();
+    let jobs = daemon.jobs.clone();
+    tokio::spawn(async move {
+        let daemon = Daemon {
+            config: &config,
+            docker: &docker,
+            manager: &manager,
+            history: &history,
+            jobs: &jobs,
+        };
+        let result = pull_deployment(
+            &name,
+            &pull,
+            &daemon,
+            &client,
+            &mut job_progress(&name, &jobs, id),
+        )
+        .await;
+
+        jobs.finish(id, result.as_ref().map(|(_, result)| &result.0));
+    });
+
+    Ok((Status::Accepted, Json(Accepted::new(id))))
+}
+
+/// Checks shared by the pull routes, run before any work is started
+fn check_pull(
+    name: &str,
+    identity: &ClientIdentity,
+    pull: &PullData,
+    config: &Config,
+) -> Result<(), ApiError> {
+    authorize(config, identity, name, Action::Pull)?;
+    check_enabled(config, name)?;
+    pull.source()
+        .map_err(|e| ApiError::new(Status::BadRequest, e))?;
+
+    Ok(())
+}
+
+/// Pulls the deployment image and starts it, shared by the pull routes. The outcome is
+/// recorded in the deployment's history against the client
+async fn pull_deployment(
+    name: &str,
+    pull: &PullData,
+    daemon: &Daemon<'_>,
+    client: &str,
+    progress: &mut Progress<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let Daemon {
+        config,
+        docker,
+        manager,
+        history,
+        ..
+    } = *daemon;
+    let result = pull_and_start(name, pull, config, docker, manager, progress).await;
+    record_history(
+        history,
+        name,
+        Action::Pull,
+        client,
+        &result,
+        docker.as_ref(),
+        &*manager.lock().await,
+    )
+    .await;
+
+    result
+}
+
+async fn pull_and_start(
+    name: &str,
+    pull: &PullData,
+    config: &Config,
+    docker: &SharedRuntime,
+    manager: &Arc<Mutex<Manager>>,
+    progress: &mut Progress<'_>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    let source = pull
+        .source()
+        .map_err(|e| ApiError::new(Status::BadRequest, e))?;
+
+    let image_name = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        name
+    );
+
+    // Pulling by digest pins the deployment to that digest, a tag pull records the digest it
+    // resolved to and loading from a URL clears it
+    let mut digest = match source {
+        PullSource::Registry(reference) => reference.split_once('@').map(|(_, d)| d.to_owned()),
+        PullSource::Url(_) => None,
+    };
+
+    let previous_image = image_id(docker.as_ref(), &image_name).await;
+
+    let overwrite = config.protect_tags == false || pull.force;
+    let source_image = match source {
+        PullSource::Url(url) => {
+            // Download to a temporary file and then treat it like an upload
+            let filename = temporary_file("pull", name, "tar");
+
+            let proxy = config.https_proxy.as_ref();
+            let result = match crate::download::download_to_file(url, &filename, proxy).await {
+                Ok(_) => {
+                    let size = tokio::fs::metadata(&filename).await.ok().map(|m| m.len());
+                    match check_disk_space(config, docker.as_ref(), size).await {
+                        Ok(_) => {
+                            docker
+                                .load_container_image(
+                                    filename.to_str().unwrap(),
+                                    pull.image.as_deref(),
+                                    &image_name,
+                                    overwrite,
+                                    progress,
+                                )
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(DockerError::Other(format!("Download failed: {}", e))),
+            };
+            tokio::fs::remove_file(&filename).await.ok();
+
+            match result {
+                Ok(source_image) => source_image,
+                Err(e) => {
+                    println!("Unable to load image from {}: {}", url, e);
+                    return Err(e.into());
+                }
+            }
+        }
+        PullSource::Registry(reference) => {
+            let source_image = pull_from_registry(
+                config,
+                docker.as_ref(),
+                reference,
+                &image_name,
+                overwrite,
+                progress,
+            )
+            .await?;
+            if digest.is_none() {
+                digest = resolved_digest(docker.as_ref(), &image_name, reference).await;
+            }
+            source_image
+        }
+    };
+
+    // The manager is only locked once the image is in place, so other requests are not held
+    // up for the length of the pull
+    let mut manager = manager.lock().await;
+    manager.set_digest(name, digest);
+
+    let result = start_container(
+        name,
+        config,
+        docker.as_ref(),
+        &mut manager,
+        Some(source_image),
+    )
+    .await;
+    if result.is_ok() {
+        manager.publish_loaded(name);
+        keep_previous_image(config, docker.as_ref(), name, previous_image).await;
+    }
+
+    return result;
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Orphans {
+    pub id: String,
+    pub names: Vec<String>,
+    pub image: String,
+    pub state: String,
+}
+
+/// Containers using the container prefix which do not match a configured deployment. Only
+/// orphans whose names, without the prefix, are visible to the client are listed
+#[utoipa::path(
+    tag = "orphans",
+    responses(
+        (status = 200, description = "Orphans", body = [Orphans]),
+    )
+)]
+#[get("/orphans")]
+pub async fn get_orphans(
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<Vec<Orphans>>), ApiError> {
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let result = manager
+        .orphans()
+        .iter()
+        .map(|o| Orphans {
+            id: o.id.to_owned(),
+            names: o
+                .names
+                .iter()
+                .map(|n| n.trim_start_matches("/").to_owned())
+                .collect(),
+            image: o.image.to_owned(),
+            state: o.state.to_owned(),
+        })
+        .filter(|o| {
+            o.names.iter().all(|name| {
+                let name = name
+                    .strip_prefix(config.container_prefix.trim_start_matches("/"))
+                    .unwrap_or(name);
+                is_visible(config, &identity, name)
+            })
+        })
+        .collect::<Vec<Orphans>>();
+    Ok((Status::Ok, Json(result)))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PruneOrphans {
+    pub dry_run: bool,
+    /// Number of matching orphans, including any beyond the limit
+    pub total: usize,
+    pub orphans: Vec<Orphans>,
+    pub removed: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Which orphans a prune removes
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PruneQuery {
+    /// List what would be removed without removing it
+    dry_run: Option<bool>,
+    /// Only orphans with a name containing this
+    filter: Option<String>,
+    /// Items to return
+    limit: Option<usize>,
+    /// Items to skip
+    offset: Option<usize>,
+}
 
-use crate::config_file::Config;
-use crate::docker_client::DockerClient;
-use crate::manager::Manager;
+/// Stops and removes orphaned containers, or with dry_run lists what would be removed
+///
+/// `filter` only includes orphans with a name containing the given string, `limit` and
+/// `offset` page through the matching orphans so large clean ups can be done in batches
+#[utoipa::path(
+    tag = "orphans",
+    params(
+        PruneQuery,
+    ),
+    responses(
+        (status = 200, description = "Result", body = PruneOrphans),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+    )
+)]
+#[post("/orphans/prune?<query..>")]
+pub async fn prune_orphans(
+    query: PruneQuery,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, Json<PruneOrphans>), ApiError> {
+    let dry_run = query.dry_run.unwrap_or(false);
+    info!(
+        "Prune of orphans (dry run: {}) requested by {}",
+        dry_run, identity
+    );
 
-#[derive(Serialize)]
+    let mut manager = manager.lock().await;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    let matching: Vec<Orphans> = manager
+        .orphans()
+        .iter()
+        .map(|o| Orphans {
+            id: o.id.to_owned(),
+            names: o
+                .names
+                .iter()
+                .map(|n| n.trim_start_matches("/").to_owned())
+                .collect(),
+            image: o.image.to_owned(),
+            state: o.state.to_owned(),
+        })
+        .filter(|o| match &query.filter {
+            Some(filter) => o.names.iter().any(|n| n.contains(filter.as_str())),
+            None => true,
+        })
+        .collect();
+    let total = matching.len();
+    let orphans: Vec<Orphans> = matching
+        .into_iter()
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    // Every orphan must be permitted before anything is removed
+    for orphan in &orphans {
+        for name in &orphan.names {
+            let name = name
+                .strip_prefix(config.container_prefix.trim_start_matches("/"))
+                .unwrap_or(name);
+            authorize(config, &identity, name, Action::Delete)?;
+        }
+    }
+
+    let mut removed = vec![];
+    let mut failed = vec![];
+    if dry_run == false {
+        for orphan in &orphans {
+            let result = match docker.stop_running_container(&orphan.id).await {
+                Ok(_) => docker.remove_stopped_container(&orphan.id).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(_) => removed.push(orphan.id.to_owned()),
+                Err(e) => {
+                    println!("Unable to remove orphan container '{}': {}", orphan.id, e);
+                    failed.push(orphan.id.to_owned());
+                }
+            }
+        }
+
+        manager.update_deployments(&config, docker.as_ref()).await?;
+    }
+
+    Ok((
+        Status::Ok,
+        Json(PruneOrphans {
+            dry_run,
+            total,
+            orphans,
+            removed,
+            failed,
+        }),
+    ))
+}
+
+/// What a system prune removed
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
-pub struct Deployments {
-    pub name: String,
-    pub state: String,
-    pub image: String,
-    pub health: String,
+pub struct SystemPrune {
+    /// Ids of the stopped orphan containers removed
+    pub containers: Vec<String>,
+    /// Ids of the images removed
+    pub images: Vec<String>,
+    pub files: Vec<String>,
+    /// Bytes freed by the images and files removed, image sizes include layers shared with
+    /// images which were kept so this is an upper bound
+    pub space_reclaimed: u64,
+    /// Containers, images and files which could not be removed
+    pub failed: Vec<String>,
 }
 
-#[get("/deployments")]
-pub async fn get_deployments(
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, Json<Vec<Deployments>>), Status> {
-    let mut docker = docker.lock().await;
+/// Housekeeping - removes stopped orphan containers, images which are no longer the latest or
+/// previous image of a deployment and are not used by a container, expired or incomplete
+/// uploads and temporary files left by interrupted requests. Deployment containers are kept
+/// even when stopped, so they can be started again
+#[utoipa::path(
+    tag = "daemon",
+    responses(
+        (status = 200, description = "What was removed", body = SystemPrune),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+    )
+)]
+#[post("/system/prune")]
+pub async fn prune_system(
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+    uploads: &State<Uploads>,
+) -> Result<(Status, Json<SystemPrune>), ApiError> {
+    info!("System prune requested by {}", identity);
+    authorize(config, &identity, "*", Action::Admin)?;
+
     let mut manager = manager.lock().await;
+    manager.update_deployments(&config, docker.as_ref()).await?;
 
-    manager
-        .update_deployments(&config, &mut docker)
-        .await
-        .map_err(|_| Status::InternalServerError)?;
+    let mut prune = SystemPrune {
+        containers: vec![],
+        images: vec![],
+        files: vec![],
+        space_reclaimed: 0,
+        failed: vec![],
+    };
 
-    let result = manager
-        .deployments
+    let stopped: Vec<String> = manager
+        .orphans()
+        .iter()
+        .filter(|o| o.state != "running" && o.state != "paused" && o.state != "restarting")
+        .map(|o| o.id.to_owned())
+        .collect();
+    for id in stopped {
+        match docker.remove_stopped_container(&id).await {
+            Ok(_) => prune.containers.push(id),
+            Err(e) => {
+                println!("Unable to remove orphan container '{}': {}", id, e);
+                prune.failed.push(id);
+            }
+        }
+    }
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    // Images are only removed when no container at all uses them, not only deployment
+    // containers
+    let prefix = config.container_prefix.trim_start_matches("/");
+    let configured = config.deployments.get();
+    let kept: Vec<String> = configured
         .iter()
-        .map(|d| Deployments {
-            name: d.name.to_owned(),
-            state: d.state.to_string(),
-            image: d.image.to_string(),
-            health: d.health.to_owned(),
+        .flat_map(|d| {
+            [
+                format!("{}{}:latest", prefix, d.name),
+                format!("{}{}:previous", prefix, d.name),
+            ]
         })
-        .collect::<Vec<Deployments>>();
-    Ok((Status::Ok, Json(result)))
+        .collect();
+    let containers = docker.get_containers(&[]).await?;
+    let images = managed_images(config, docker.as_ref(), &manager).await?;
+    for image in images {
+        if image.tags.iter().any(|t| kept.contains(t))
+            || containers.iter().any(|c| c.image_id == image.id)
+        {
+            continue;
+        }
+        // Forced so an image with several tags is removed in one go
+        match docker.remove_image(&image.id, true).await {
+            Ok(()) => {
+                prune.space_reclaimed += u64::try_from(image.size).unwrap_or_default();
+                prune.images.push(image.id);
+            }
+            Err(e) => {
+                println!("Unable to remove image {}: {}", image.id, e);
+                prune.failed.push(image.id);
+            }
+        }
+    }
+    drop(manager);
+
+    let (files, freed) = uploads.prune().await;
+    prune.space_reclaimed += freed;
+    prune
+        .files
+        .extend(files.iter().map(|f| f.display().to_string()));
+
+    for file in stale_temporary_files().await {
+        let size = tokio::fs::metadata(&file)
+            .await
+            .map(|m| m.len())
+            .unwrap_or_default();
+        match tokio::fs::remove_file(&file).await {
+            Ok(()) => {
+                prune.space_reclaimed += size;
+                prune.files.push(file.display().to_string());
+            }
+            Err(e) => {
+                println!("Unable to remove {}: {}", file.display(), e);
+                prune.failed.push(file.display().to_string());
+            }
+        }
+    }
+
+    info!(
+        "System prune removed {} containers, {} images and {} files, reclaiming {} bytes",
+        prune.containers.len(),
+        prune.images.len(),
+        prune.files.len(),
+        prune.space_reclaimed
+    );
+    Ok((Status::Ok, Json(prune)))
 }
 
-#[get("/deployments/<name>")]
-pub async fn get_deployment(
-    name: String,
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, Json<Deployments>), Status> {
-    let mut docker = docker.lock().await;
-    let mut manager = manager.lock().await;
+/// Temporary files named since the daemon started, so concurrent requests for the same
+/// deployment use different files
+static TEMPORARY_FILES: AtomicU64 = AtomicU64::new(0);
 
-    manager
-        .update_deployments(&config, &mut docker)
-        .await
-        .map_err(|_| Status::InternalServerError)?;
+/// A path for a temporary file, `edd-<kind>-<name>-<n>-<run>.<extension>`. The run token last
+/// lets the system prune recognise files of requests still running
+fn temporary_file(kind: &str, name: &str, extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "edd-{}-{}-{}-{}.{}",
+        kind,
+        name,
+        TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed),
+        run_token(),
+        extension
+    ))
+}
 
-    let result = manager.deployments.iter().find(|d| d.name == name);
+/// 16 random hex characters chosen when the daemon starts. The pid cannot tell runs apart, in a
+/// container the daemon is pid 1 every time
+fn run_token() -> &'static str {
+    static TOKEN: OnceLock<String> = OnceLock::new();
+    TOKEN.get_or_init(|| {
+        let mut random = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut random);
+        random.iter().map(|b| format!("{:02x}", b)).collect()
+    })
+}
 
-    if let Some(deployment) = result {
-        return Ok((
-            Status::Ok,
-            Json(Deployments {
-                name: deployment.name.to_owned(),
-                state: deployment.state.to_string(),
-                image: deployment.image.to_string(),
-                health: deployment.health.to_owned(),
-            }),
-        ));
+/// Open the temporary file written by result to send as a response, removing it either way. The
+/// file stays readable once opened, so it can be removed before the response is sent
+async fn open_temporary_file(
+    filename: &Path,
+    result: Result<(), DockerError>,
+) -> Result<tokio::fs::File, DockerError> {
+    let file = match result {
+        Ok(()) => tokio::fs::File::open(filename)
+            .await
+            .map_err(DockerError::from),
+        Err(e) => Err(e),
+    };
+    tokio::fs::remove_file(filename).await.ok();
+    file
+}
+
+/// The temporary files exports, archives, log downloads, url pulls and background loads leave
+/// behind when they are interrupted, i.e. by the daemon restarting. Files are named
+/// `edd-...-<run>.tar` (or `.tar.gz` and `.log` for log downloads), so those of requests still
+/// running are recognised and kept
+async fn stale_temporary_files() -> Vec<PathBuf> {
+    let mut stale = vec![];
+    let mut entries = match tokio::fs::read_dir(std::env::temp_dir()).await {
+        Ok(entries) => entries,
+        Err(_) => return stale,
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stem = match name.strip_prefix("edd-").and_then(|n| {
+            [".tar", ".tar.gz", ".log"]
+                .iter()
+                .find_map(|extension| n.strip_suffix(extension))
+        }) {
+            Some(stem) => stem,
+            None => continue,
+        };
+
+        let running = match stem.rsplit_once('-') {
+            Some((_, run)) if run.len() == 16 && run.chars().all(|c| c.is_ascii_hexdigit()) => {
+                run == run_token()
+            }
+            _ => continue,
+        };
+        if running == false {
+            stale.push(entry.path());
+        }
     }
 
-    Err(Status::NotFound)
+    stale
 }
 
-#[post("/deployments/<name>/start")]
-pub async fn start_deployment(
-    name: String,
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, String), Status> {
-    let mut docker = docker.lock().await;
+#[utoipa::path(
+    tag = "orphans",
+    summary = "Stop a prefixed container by id",
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such prefixed container", body = ApiError),
+    )
+)]
+#[post("/containers/<id>/stop")]
+pub async fn stop_container(
+    id: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Stop of container '{}' requested by {}", id, identity);
+
     let mut manager = manager.lock().await;
 
-    // Update the info on deployments in case the container is already running
-    manager
-        .update_deployments(&config, &mut docker)
-        .await
-        .unwrap();
+    let (id, name) = find_prefixed_container(config, docker.as_ref(), &id).await?;
+    authorize(config, &identity, &name, Action::Stop)?;
 
-    // Look for the deployment
-    let result = manager.deployments.iter_mut().find(|d| d.name == name);
+    docker.stop_running_container(&id).await?;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+#[utoipa::path(
+    tag = "orphans",
+    summary = "Remove a prefixed container by id",
+    responses(
+        (status = 200, description = "Done", body = Object),
+        (status = 403, description = "Not authorized, or the daemon is read only", body = ApiError),
+        (status = 404, description = "No such prefixed container", body = ApiError),
+    )
+)]
+#[delete("/containers/<id>")]
+pub async fn delete_container(
+    id: String,
+    _writable: Writable,
+    identity: ClientIdentity,
+    config: &State<Arc<Config>>,
+    docker: &State<SharedRuntime>,
+    manager: &State<Arc<Mutex<Manager>>>,
+) -> Result<(Status, String), ApiError> {
+    info!("Delete of container '{}' requested by {}", id, identity);
+
+    let mut manager = manager.lock().await;
+
+    let (id, name) = find_prefixed_container(config, docker.as_ref(), &id).await?;
+    authorize(config, &identity, &name, Action::Delete)?;
+
+    docker.stop_running_container(&id).await?;
+    docker.remove_stopped_container(&id).await?;
+
+    manager.update_deployments(&config, docker.as_ref()).await?;
+
+    return Ok((Status::Ok, "{}".into()));
+}
+
+/// Find a container by full or short (at least 12 character) id, returning the full id and
+/// the name without the prefix
+///
+/// Only containers labelled by this daemon, or with a name using the container prefix, can
+/// be managed, anything else returns 403 so the daemon cannot be used to stop unrelated
+/// containers
+async fn find_prefixed_container(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    id: &str,
+) -> Result<(String, String), ApiError> {
+    if id.len() < 12 {
+        return Err(Status::BadRequest.into());
+    }
+
+    let containers = docker.get_containers(&[]).await?;
+    let result = containers.iter().find(|c| c.id.starts_with(id));
     if result.is_none() {
-        return Err(Status::NotFound);
+        return Err(Status::NotFound.into());
     }
-    let deployment = result.unwrap();
+    let container = result.unwrap();
 
-    if deployment.state == crate::manager::State::Running {
-        return Ok((Status::Ok, "{}".into()));
+    let name = crate::manager::labelled_deployment(config, container).or(container
+        .names
+        .iter()
+        .find_map(|n| n.strip_prefix(&config.container_prefix)));
+    match name {
+        Some(name) => Ok((container.id.to_owned(), name.to_owned())),
+        None => Err(Status::Forbidden.into()),
+    }
+}
+
+/// What the named image declares, None if there is no such image or docker could not be asked
+async fn image_metadata(docker: &dyn ContainerRuntime, name: &str) -> Option<ImageMetadata> {
+    match docker.inspect_image(name).await {
+        Ok(inspection) => inspection.map(ImageMetadata::from_image),
+        Err(e) => {
+            println!("Unable to inspect image {}: {}", name, e);
+            None
+        }
+    }
+}
+
+/// Pull from each of the configured mirrors in turn, then the registry itself, until one
+/// succeeds. Refusing to retag over a protected image is not retried
+async fn pull_from_registry(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    reference: &str,
+    image_name: &str,
+    overwrite: bool,
+    progress: &mut Progress<'_>,
+) -> Result<String, ApiError> {
+    let mut result = Err(Status::InternalServerError.into());
+    for candidate in config.pull_references(reference) {
+        let auth = config
+            .registry_auth(&candidate)
+            .map_err(|e| ApiError::internal("Unable to read the registry credentials", e))?;
+        result = docker
+            .pull_container_image(&candidate, auth.as_ref(), image_name, overwrite, progress)
+            .await
+            .map_err(|e| {
+                println!("Unable to pull {}: {}", candidate, e);
+                ApiError::from(e)
+            });
+        let retry = match &result {
+            Ok(_) => false,
+            Err(e) => e.status != Status::Conflict,
+        };
+        if retry == false {
+            break;
+        }
     }
 
+    result
+}
+
+/// Id of the named image, None if there is no such image or docker could not be asked
+async fn image_id(docker: &dyn ContainerRuntime, name: &str) -> Option<String> {
     docker
-        .start(&deployment.id)
+        .inspect_image(name)
         .await
-        .map_err(|_| Status::InternalServerError)?;
+        .ok()
+        .flatten()
+        .map(|i| i.id)
+}
 
-    return Ok((Status::Ok, "{}".into()));
+/// Records a load, pull, build or rollback in the deployment's history, with the image the
+/// deployment was started with when it succeeded
+async fn record_history(
+    history: &History,
+    name: &str,
+    action: Action,
+    client: &str,
+    result: &Result<(Status, Json<LoadResult>), ApiError>,
+    docker: &dyn ContainerRuntime,
+    manager: &Manager,
+) {
+    let entry = match result {
+        Ok((_, result)) => HistoryEntry {
+            time: unix_now(),
+            action,
+            client: client.to_owned(),
+            source_image: result.source_image.to_owned(),
+            image_id: image_id(docker, &result.image).await,
+            digest: manager
+                .deployments()
+                .iter()
+                .find(|d| d.name == name)
+                .and_then(|d| d.digest.to_owned()),
+            success: true,
+            error: None,
+        },
+        Err(e) => HistoryEntry {
+            time: unix_now(),
+            action,
+            client: client.to_owned(),
+            source_image: None,
+            image_id: None,
+            digest: None,
+            success: false,
+            error: Some(e.detail.as_ref().unwrap_or(&e.message).to_owned()),
+        },
+    };
+
+    history.record(name, entry);
 }
 
-#[post("/deployments/<name>/stop")]
-pub async fn stop_deployment(
-    name: String,
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, String), Status> {
-    let mut manager = manager.lock().await;
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
 
-    // Update the info on deployments in case the container is already stopped
-    let mut docker = docker.lock().await;
-    manager
-        .update_deployments(&config, &mut docker)
-        .await
-        .unwrap();
+/// Tag the image a deployment used before a load, pull or build as `<prefix><name>:previous`
+/// once the new container is running, so the deployment can be rolled back to it. The image
+/// which was the previous image before is then no longer needed, and is removed
+async fn keep_previous_image(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    name: &str,
+    previous: Option<String>,
+) {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return,
+    };
 
-    stop(&name, &mut docker, &mut manager, true).await?;
+    let prefix = config.container_prefix.trim_start_matches("/");
+    let latest = image_id(docker, &format!("{}{}:latest", prefix, name)).await;
+    if latest.as_ref() == Some(&previous) {
+        return;
+    }
 
-    return Ok((Status::Ok, "{}".into()));
+    let previous_name = format!("{}{}:previous", prefix, name);
+    let older = image_id(docker, &previous_name).await;
+    if let Err(e) = docker.tag_image(&previous, &previous_name).await {
+        println!(
+            "Unable to keep the image replaced in deployment '{}': {}",
+            name, e
+        );
+        return;
+    }
+    if older.as_ref() != Some(&previous) {
+        remove_replaced_image(config, docker, name, older).await;
+    }
 }
 
-#[delete("/deployments/<name>")]
-pub async fn delete_deployment(
-    name: String,
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, String), Status> {
-    let mut manager = manager.lock().await;
+/// Remove an image a deployment no longer uses, rather than pruning every dangling image on
+/// the host. Nothing is removed if the deployment's latest or previous image is still the
+/// same image, or docker refuses because it still has other tags or containers
+async fn remove_replaced_image(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    name: &str,
+    replaced: Option<String>,
+) {
+    let replaced = match replaced {
+        Some(replaced) if config.prune_images => replaced,
+        _ => return,
+    };
 
-    // Update the info on deployments in case the container is already stopped
-    let mut docker = docker.lock().await;
-    manager
-        .update_deployments(&config, &mut docker)
-        .await
-        .unwrap();
+    let prefix = config.container_prefix.trim_start_matches("/");
+    for tag in ["latest", "previous"] {
+        let image_name = format!("{}{}:{}", prefix, name, tag);
+        if image_id(docker, &image_name).await.as_ref() == Some(&replaced) {
+            return;
+        }
+    }
 
-    stop(&name, &mut docker, &mut manager, false).await?;
-    remove(&name, &mut docker, &mut manager, false).await?;
+    match docker.remove_image(&replaced, false).await {
+        Ok(_) => info!(
+            "Removed image {} replaced in deployment '{}'",
+            replaced, name
+        ),
+        Err(DockerError::Conflict(e)) => {
+            info!("Kept image replaced in deployment '{}': {}", name, e)
+        }
+        Err(e) => println!(
+            "Unable to remove image replaced in deployment '{}': {}",
+            name, e
+        ),
+    }
+}
 
-    return Ok((Status::Ok, "{}".into()));
+/// The daemon's shared state, as one request guard for the routes which need most of it
+pub struct Daemon<'r> {
+    pub config: &'r Arc<Config>,
+    pub docker: &'r SharedRuntime,
+    pub manager: &'r Arc<Mutex<Manager>>,
+    pub history: &'r Arc<History>,
+    pub jobs: &'r Arc<Jobs>,
 }
 
-#[derive(Serialize)]
-pub struct LoadResult {
-    pub outcome: String,
-    pub state: String,
-    pub health: String,
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Daemon<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        match (
+            rocket.state(),
+            rocket.state(),
+            rocket.state(),
+            rocket.state(),
+            rocket.state(),
+        ) {
+            (Some(config), Some(docker), Some(manager), Some(history), Some(jobs)) => {
+                Outcome::Success(Daemon {
+                    config,
+                    docker,
+                    manager,
+                    history,
+                    jobs,
+                })
+            }
+            _ => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
 }
 
-#[post("/deployments/<name>/load", data = "<container>")]
-pub async fn load_file(
-    name: String,
-    container: TempFile<'_>,
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, Json<LoadResult>), Status> {
-    // Ensure the deployment name actually exists
-    let mut docker = docker.lock().await;
-    let mut manager = manager.lock().await;
+/// A request body streamed to docker, the read fails once the body goes past the limit so
+/// docker never gets an upload which was cut short. `exceeded` is checked afterwards to
+/// return 413 rather than docker's error
+pub struct CappedUpload<'r> {
+    stream: DataStream<'r>,
+    remaining: u64,
+    exceeded: bool,
+    /// Size of the body from the Content-Length header, if the client sent one
+    pub length: Option<u64>,
+}
 
-    docker
-        .load_container_image(
-            container.path().unwrap().to_str().unwrap(),
-            &format!(
-                "{}{}:latest",
-                config.container_prefix.trim_start_matches("/"),
-                name
-            ),
-        )
-        .await
-        .unwrap();
+impl CappedUpload<'_> {
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
 
-    let config = config.inner();
-    return start_container(&name, config, &mut docker, &mut manager).await;
+#[rocket::async_trait]
+impl<'r> FromData<'r> for CappedUpload<'r> {
+    type Error = std::convert::Infallible;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let length = request
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|l| l.parse().ok());
+
+        let limit = request
+            .limits()
+            .get("file")
+            .unwrap_or(Limits::FILE)
+            .as_u64();
+
+        Outcome::Success(CappedUpload {
+            // One byte over the limit is read to tell a body of exactly the limit from a
+            // larger one
+            stream: data.open((limit + 1).bytes()),
+            remaining: limit,
+            exceeded: false,
+            length,
+        })
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(crate = "rocket::serde")]
-pub struct PullData {
-    path: String,
+impl AsyncRead for CappedUpload<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        futures::ready!(Pin::new(&mut this.stream).poll_read(cx, buf))?;
+
+        let read = (buf.filled().len() - before) as u64;
+        if read > this.remaining {
+            this.exceeded = true;
+            return std::task::Poll::Ready(Err(std::io::Error::other("upload is too large")));
+        }
+        this.remaining -= read;
+        std::task::Poll::Ready(Ok(()))
+    }
 }
 
-#[post("/deployments/<name>/pull", data = "<pull>")]
-pub async fn pull(
-    name: String,
-    pull: Json<PullData>,
-    config: &State<Config>,
-    docker: &State<Mutex<DockerClient>>,
-    manager: &State<Mutex<Manager>>,
-) -> Result<(Status, Json<LoadResult>), Status> {
-    let mut docker = docker.lock().await;
-    let mut manager = manager.lock().await;
+/// 413 for an upload which went past the limit
+fn too_large() -> ApiError {
+    ApiError::new(Status::PayloadTooLarge, "upload is too large")
+}
 
-    docker
-        .pull_container_image(&pull.path, "ed_main:latest")
-        .await
-        .unwrap();
+/// Check docker has room to load an image tarball of the given size, if known, and still
+/// leave the configured headroom free
+///
+/// Docker unpacks the tarball before importing its layers so about twice its size is needed.
+/// The check is skipped if docker's free space cannot be found, i.e. docker is on another host
+async fn check_disk_space(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    size: Option<u64>,
+) -> Result<(), DockerError> {
+    let headroom = match config.disk_headroom {
+        Some(headroom) => headroom,
+        None => return Ok(()),
+    };
+
+    let free = match docker.free_space().await {
+        Ok(Some(free)) => free,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            println!("Unable to check free disk space, continuing: {}", e);
+            return Ok(());
+        }
+    };
+
+    let needed = size.unwrap_or(0).saturating_mul(2).saturating_add(headroom);
+    if free < needed {
+        return Err(DockerError::InsufficientStorage(format!(
+            "Not enough disk space to load the image, {} MiB free and {} MiB needed",
+            free / 1024 / 1024,
+            needed / 1024 / 1024
+        )));
+    }
+
+    Ok(())
+}
+
+/// Log image load and pull progress against the deployment as docker reports it
+fn log_progress(name: &str) -> impl FnMut(&ProgressMessage) + Send + '_ {
+    move |message| {
+        if let Some(text) = message.text() {
+            info!("{}: {}", name, text);
+        }
+    }
+}
+
+/// Logs progress as for log_progress, and keeps the latest message on the job
+fn job_progress<'a>(
+    name: &'a str,
+    jobs: &'a Jobs,
+    id: u64,
+) -> impl FnMut(&ProgressMessage) + Send + 'a {
+    move |message| {
+        if let Some(text) = message.text() {
+            info!("{}: {}", name, text);
+            jobs.progress(id, text);
+        }
+    }
+}
+
+/// A deployment pulled by digest is refused (409) if the deployment image no longer has
+/// the requested digest, i.e. it has been retagged outside of the daemon
+async fn check_digest(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+    deployment: &crate::manager::Deployment,
+) -> Result<(), ApiError> {
+    let digest = match &deployment.digest {
+        Some(digest) => digest,
+        None => return Ok(()),
+    };
+
+    let image = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        deployment.name
+    );
+    let inspection = docker.inspect_image(&image).await?;
+    let matches = inspection
+        .and_then(|i| i.repo_digests)
+        .unwrap_or_default()
+        .iter()
+        .any(|d| d.ends_with(&format!("@{}", digest)));
+
+    if matches == false {
+        warn!(
+            "Image for deployment '{}' no longer matches requested digest {}",
+            deployment.name, digest
+        );
+        return Err(ApiError::new(
+            Status::Conflict,
+            format!("the image no longer matches digest {}", digest),
+        ));
+    }
+
+    Ok(())
+}
 
-    return start_container(&name, config, &mut docker, &mut manager).await;
+/// The digest a tag pulled from a registry resolved to, from the repo digest of the pulled
+/// image for the reference's repository
+async fn resolved_digest(
+    docker: &dyn ContainerRuntime,
+    image: &str,
+    reference: &str,
+) -> Option<String> {
+    // The tag follows the last ':' unless that is part of a registry host:port
+    let repository = match reference.rsplit_once(':') {
+        Some((repository, tag)) if tag.contains('/') == false => repository,
+        _ => reference,
+    };
+
+    let repo_digests = docker.inspect_image(image).await.ok()??.repo_digests?;
+    // Docker Hub repositories are listed without the registry or library/ (and the reference
+    // may include them), so compare only up to a path boundary
+    let same = |a: &str, b: &str| a == b || a.ends_with(&format!("/{}", b));
+    let matching = repo_digests.iter().find(|d| match d.split_once('@') {
+        Some((r, _)) => same(r, repository) || same(repository, r),
+        None => false,
+    });
+    let repo_digest = match matching {
+        Some(d) => d,
+        None if repo_digests.len() == 1 => &repo_digests[0],
+        None => return None,
+    };
+
+    repo_digest.split_once('@').map(|(_, d)| d.to_owned())
+}
+
+/// Disabled deployments cannot be (re)started through the API
+fn check_enabled(config: &Config, name: &str) -> Result<(), ApiError> {
+    if config.is_disabled(name) {
+        return Err(ApiError::new(
+            Status::Locked,
+            format!("deployment '{}' is disabled", name),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Create any of the deployment's declared volumes which do not exist yet
+async fn ensure_volumes(
+    deployment: &crate::config_file::Deployment,
+    docker: &dyn ContainerRuntime,
+) -> Result<(), ApiError> {
+    let existing = docker.list_volumes().await?;
+
+    for volume in &deployment.volumes {
+        if existing.iter().any(|v| v.name == volume.name) {
+            continue;
+        }
+
+        info!(
+            "Creating volume '{}' for deployment '{}'",
+            volume.name, deployment.name
+        );
+        let create = CreateVolume {
+            name: volume.name.to_owned(),
+            driver: volume.driver.to_owned().unwrap_or("local".into()),
+            driver_opts: volume.options.to_owned(),
+            labels: [
+                (MANAGED_LABEL.to_owned(), "true".to_owned()),
+                (DEPLOYMENT_LABEL.to_owned(), deployment.name.to_owned()),
+            ]
+            .into(),
+        };
+        docker.create_volume(&create).await.map_err(|e| {
+            println!("Unable to create volume '{}': {}", volume.name, e);
+            Status::InternalServerError
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Create any of the deployment's networks which do not exist yet, as bridge networks
+async fn ensure_networks(
+    deployment: &crate::config_file::Deployment,
+    docker: &dyn ContainerRuntime,
+) -> Result<(), ApiError> {
+    if deployment.networks.is_empty() {
+        return Ok(());
+    }
+
+    let existing = docker.list_networks().await?;
+
+    for network in &deployment.networks {
+        if existing.iter().any(|n| n.name == *network) {
+            continue;
+        }
+
+        info!(
+            "Creating network '{}' for deployment '{}'",
+            network, deployment.name
+        );
+        let create = CreateNetwork {
+            name: network.to_owned(),
+            driver: "bridge".into(),
+            labels: [(MANAGED_LABEL.to_owned(), "true".to_owned())].into(),
+        };
+        docker.create_network(&create).await.map_err(|e| {
+            println!("Unable to create network '{}': {}", network, e);
+            Status::InternalServerError
+        })?;
+    }
+
+    Ok(())
 }
 
 async fn stop(
     name: &str,
-    docker: &mut DockerClient,
+    docker: &dyn ContainerRuntime,
     manager: &mut Manager,
     fail_hard: bool,
-) -> Result<(), Status> {
-    let result = manager.deployments.iter_mut().find(|d| d.name == name);
+) -> Result<(), ApiError> {
+    let result = manager.deployments().iter().find(|d| d.name == name);
     if result.is_none() {
-        return Err(Status::NotFound);
+        return Err(Status::NotFound.into());
     }
-    let deployment = result.unwrap();
+    let id = result.unwrap().id.to_owned();
 
     let result = docker
-        .stop_running_container(&deployment.id)
+        .stop_running_container(&id)
         .await
-        .map_err(|_| Status::InternalServerError);
+        .map_err(ApiError::from);
     if fail_hard && result.is_err() {
         return Err(result.unwrap_err());
     }
-    deployment.state = crate::manager::State::Stopped;
+
+    // Make sure the container has exited before anything else (i.e. a remove) is done to it
+    if result.is_ok() {
+        match docker.wait_container(&id).await {
+            Ok(code) => info!("Deployment '{}' exited with code {}", name, code),
+            Err(e) => println!("Unable to wait for deployment '{}' to exit: {}", name, e),
+        }
+    }
+    manager.mark_stopped(name);
 
     Ok(())
 }
 
 async fn remove(
     name: &str,
-    docker: &mut DockerClient,
+    docker: &dyn ContainerRuntime,
     manager: &mut Manager,
     fail_hard: bool,
-) -> Result<(), Status> {
+) -> Result<(), ApiError> {
     // Look for the deployment
-    let result = manager.deployments.iter_mut().find(|d| d.name == name);
+    let result = manager.deployments().iter().find(|d| d.name == name);
     if result.is_none() {
-        return Err(Status::NotFound);
+        return Err(Status::NotFound.into());
     }
-    let deployment = result.unwrap();
+    let id = result.unwrap().id.to_owned();
 
     let result = docker
-        .remove_stopped_container(&deployment.id)
+        .remove_stopped_container(&id)
         .await
-        .map_err(|_| Status::InternalServerError);
+        .map_err(ApiError::from);
     if fail_hard && result.is_err() {
         return Err(result.unwrap_err());
     }
-
-    *deployment = crate::manager::Deployment::default();
-    deployment.name = String::from(name.clone());
+    manager.mark_removed(name);
 
     Ok(())
 }
@@ -269,63 +5657,131 @@ async fn remove(
 async fn start_container(
     deployment_name: &str,
     config: &Config,
-    docker: &mut DockerClient,
+    docker: &dyn ContainerRuntime,
     manager: &mut Manager,
-) -> Result<(Status, Json<LoadResult>), Status> {
+    source_image: Option<String>,
+) -> Result<(Status, Json<LoadResult>), ApiError> {
+    if let Some(deployment) = manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == deployment_name)
+    {
+        check_digest(config, docker, deployment).await?;
+    }
+
+    let digest = manager
+        .deployments()
+        .iter()
+        .find(|d| d.name == deployment_name)
+        .and_then(|d| d.digest.to_owned());
+
     // Ensure the container is stopped already
     stop(&deployment_name, docker, manager, false).await?;
     remove(&deployment_name, docker, manager, false).await?;
 
-    let result = config
-        .deployments
-        .iter()
-        .find(|d| d.name == deployment_name);
+    let deployments = config.deployments.get();
+    let result = deployments.iter().find(|d| d.name == deployment_name);
     if result.is_none() {
-        return Err(Status::NotFound);
+        return Err(Status::NotFound.into());
     }
     let deployment_config = result.unwrap();
 
-    let args = if let Some(deployment_config) = &deployment_config.args {
-        deployment_config.iter().map(|a| a.as_str()).collect()
+    ensure_volumes(deployment_config, docker).await?;
+    ensure_networks(deployment_config, docker).await?;
+
+    let mut args: Vec<String> = if let Some(deployment_config) = &deployment_config.args {
+        deployment_config.to_owned()
     } else {
         vec![]
     };
 
+    if let Some(log_driver) = &deployment_config.log_driver {
+        args.push(format!("--log-driver={}", log_driver));
+        for (key, value) in deployment_config.log_options.iter().flatten() {
+            args.push(format!("--log-opt={}={}", key, value));
+        }
+    }
+
+    // Added last so the deployment args cannot override them
+    for label in crate::manager::container_labels(config, deployment_name, digest.as_deref()) {
+        args.push(format!("--label={}", label));
+    }
+
+    let image = format!(
+        "{}{}:latest",
+        config.container_prefix.trim_start_matches("/"),
+        deployment_name,
+    );
+
+    // Docker applies the image's entrypoint, healthcheck and labels itself, the exposed ports
+    // are listed in the create request so it shows everything the container will expose
+    let metadata = image_metadata(docker, &image).await;
+    for port in metadata.iter().flat_map(|m| &m.exposed_ports) {
+        args.push(format!("--expose={}", port));
+    }
+
+    // Docker drops limits it cannot apply (i.e. rootless docker on cgroup v1) with only a
+    // warning, refuse to start the container without them instead
+    if let Ok(create) =
+        run_args::parse(&args.iter().map(|a| a.as_str()).collect::<Vec<_>>(), &image)
+    {
+        let memory = create.host_config.memory.is_some();
+        let cpus = create.host_config.nano_cpus.is_some();
+        if memory || cpus {
+            let info = docker.system_info().await?;
+            if let Some(message) = Capabilities::from_info(&info).unsupported_limits(memory, cpus) {
+                println!(
+                    "Unable to start deployment '{}': {}",
+                    deployment_name, message
+                );
+                return Err(Status::UnprocessableEntity.into());
+            }
+        }
+    }
+
     // Start with name
+    let container_name = format!(
+        "{}{}",
+        config.container_prefix.trim_start_matches("/"),
+        deployment_name
+    );
     docker
-        .start_with_cli(
-            &format!(
-                "{}{}",
-                config.container_prefix.trim_start_matches("/"),
-                deployment_name
-            ),
-            &format!(
-                "{}{}:latest",
-                config.container_prefix.trim_start_matches("/"),
-                deployment_name,
-            ),
-            args,
+        .run_container(
+            &container_name,
+            &image,
+            args.iter().map(|a| a.as_str()).collect(),
         )
-        .map_err(|_| Status::InternalServerError)?;
+        .await?;
 
-    manager
-        .update_deployments(&config, docker)
-        .await
-        .map_err(|_| Status::InternalServerError)?;
+    for network in &deployment_config.networks {
+        docker
+            .connect_network(network, &container_name, &[deployment_name.to_owned()])
+            .await
+            .map_err(|e| {
+                println!(
+                    "Unable to attach '{}' to '{}': {}",
+                    deployment_name, network, e
+                );
+                Status::InternalServerError
+            })?;
+    }
+
+    manager.update_deployments(&config, docker).await?;
+    manager.set_metadata(deployment_name, metadata);
 
     let is_running = manager
-        .deployments
+        .deployments()
         .iter()
         .find(|d| d.name == deployment_name)
         .unwrap()
         .state
         == crate::manager::State::Running;
     if is_running == false {
-        return Err(Status::InternalServerError);
+        return Err(Status::InternalServerError.into());
     }
 
     let result = manager
-        .deployments
+        .deployments()
         .iter()
         .find(|d| d.name == deployment_name);
 
@@ -336,9 +5792,11 @@ async fn start_container(
                 outcome: "success".into(),
                 health: deployment.health.to_owned(),
                 state: deployment.state.to_string(),
+                image,
+                source_image,
             }),
         ));
     }
 
-    Err(Status::InternalServerError)
+    Err(Status::InternalServerError.into())
 }
@@ -0,0 +1,126 @@
+use std::error::Error;
+
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::Request;
+use utoipa::ToSchema;
+
+use crate::docker_error::DockerError;
+use crate::metrics;
+
+/// The JSON body of every failed request
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = Error)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: Status,
+    /// Machine readable, the status reason (i.e. not_found) or the kind of docker error (i.e.
+    /// unreachable)
+    pub code: String,
+    pub message: String,
+    /// The error from docker, or the file system, the failure came from
+    pub detail: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code: code(status),
+            message: message.into(),
+            detail: None,
+        }
+    }
+
+    /// A failure of something other than docker, i.e. writing the deployments file
+    pub fn internal(message: impl Into<String>, detail: impl ToString) -> Self {
+        ApiError {
+            detail: Some(detail.to_string()),
+            ..ApiError::new(Status::InternalServerError, message)
+        }
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        ApiError::new(status, status.reason_lossy())
+    }
+}
+
+/// Docker errors which become responses are counted by kind
+impl From<DockerError> for ApiError {
+    fn from(e: DockerError) -> Self {
+        let message = match &e {
+            DockerError::NotFound(_) => "Docker has no such container, image or volume",
+            DockerError::Conflict(_) => "The request conflicts with the state of docker",
+            DockerError::DaemonUnreachable(_) => "Unable to reach docker",
+            DockerError::Timeout { .. } => "Docker did not respond in time",
+            DockerError::MalformedResponse(_) => "Docker responded with something not understood",
+            DockerError::InvalidRequest(_) => "The request to docker could not be made",
+            DockerError::UnsupportedImage(_) => "The image is not a tarball docker can load",
+            DockerError::ImageSelection(_) => "The image to deploy from the tarball was not chosen",
+            DockerError::InsufficientStorage(_) => "Docker does not have enough free disk space",
+            DockerError::Io(_) => "Unable to read or write a local file",
+            DockerError::Http(_) => "The connection to docker failed",
+            DockerError::Other(_) => "Docker was unable to complete the request",
+        };
+
+        metrics::record_docker_error(&e);
+        ApiError {
+            status: docker_status(&e),
+            code: e.kind().to_owned(),
+            message: message.to_owned(),
+            detail: Some(e.to_string()),
+        }
+    }
+}
+
+/// The manager's errors, which are docker errors when it was docker that failed
+impl From<Box<dyn Error + Send + Sync>> for ApiError {
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        match e.downcast::<DockerError>() {
+            Ok(e) => ApiError::from(*e),
+            Err(e) => ApiError::internal("Unable to update the deployments", e),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status;
+        (status, Json(self)).respond_to(request)
+    }
+}
+
+/// The status to return for a docker error, i.e. a refusal to retag over a protected image
+/// is a conflict and a docker timeout is a gateway timeout
+pub fn docker_status(e: &DockerError) -> Status {
+    match e {
+        DockerError::NotFound(_) => Status::NotFound,
+        DockerError::Conflict(_) => Status::Conflict,
+        DockerError::Timeout { .. } => Status::GatewayTimeout,
+        DockerError::DaemonUnreachable(_) => Status::ServiceUnavailable,
+        DockerError::UnsupportedImage(_) => Status::UnsupportedMediaType,
+        DockerError::ImageSelection(_) => Status::BadRequest,
+        DockerError::InsufficientStorage(_) => Status::InsufficientStorage,
+        _ => Status::InternalServerError,
+    }
+}
+
+/// Errors without a body of their own, i.e. a failed request guard or an unknown route
+#[catch(default)]
+pub fn catcher(status: Status, _request: &Request) -> ApiError {
+    ApiError::from(status)
+}
+
+/// not_found for 404 Not Found
+fn code(status: Status) -> String {
+    status
+        .reason_lossy()
+        .to_lowercase()
+        .replace([' ', '-'], "_")
+        .replace('\'', "")
+}
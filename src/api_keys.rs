@@ -0,0 +1,170 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::authorization::Grant;
+use crate::bearer::constant_time_eq;
+
+/// Prefix of every API key, so they are recognisable in scripts and secret scanners
+const KEY_PREFIX: &str = "edd_";
+
+/// An API key issued to an integrator. Only the SHA-256 of the key is kept, the key itself is
+/// returned once when it is created
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    /// The client the key identifies, used in logs
+    pub name: String,
+    #[serde(flatten)]
+    pub grant: Grant,
+    /// Unix timestamps
+    pub created: u64,
+    pub expires: Option<u64>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    #[schema(ignore)]
+    hash: String,
+}
+
+/// API keys persisted in a JSON file, changes are written to the file before they take effect
+pub struct ApiKeys {
+    path: PathBuf,
+    keys: Mutex<Vec<ApiKey>>,
+}
+
+impl ApiKeys {
+    /// Load the keys from the file, which does not need to exist until a key is created
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let keys = match std::fs::read(path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|e| format!("Unable to read api_keys_file ({}): {}", path.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                return Err(format!(
+                    "Unable to read api_keys_file ({}): {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+
+        Ok(ApiKeys {
+            path: path.to_owned(),
+            keys: Mutex::new(keys),
+        })
+    }
+
+    /// Whether any keys have been issued, bearer tokens are required once they have
+    pub fn is_empty(&self) -> bool {
+        self.keys.lock().unwrap().is_empty()
+    }
+
+    /// Issue a new key, returning it with the key itself
+    pub fn create(
+        &self,
+        name: &str,
+        grant: Grant,
+        expires: Option<u64>,
+    ) -> io::Result<(ApiKey, String)> {
+        let id = random_hex(8);
+        let key = format!("{}{}", KEY_PREFIX, random_hex(32));
+        let api_key = ApiKey {
+            id,
+            name: name.to_owned(),
+            grant,
+            created: now(),
+            expires,
+            hash: hash(&key),
+        };
+
+        let mut keys = self.keys.lock().unwrap();
+        let mut updated = keys.clone();
+        updated.push(api_key.clone());
+        self.save(&updated)?;
+        *keys = updated;
+
+        Ok((api_key, key))
+    }
+
+    /// The issued keys, without their hashes
+    pub fn list(&self) -> Vec<ApiKey> {
+        self.keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|k| ApiKey {
+                hash: String::new(),
+                ..k.clone()
+            })
+            .collect()
+    }
+
+    /// Revoke a key, false if there is no key with the id
+    pub fn revoke(&self, id: &str) -> io::Result<bool> {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.iter().any(|k| k.id == id) == false {
+            return Ok(false);
+        }
+
+        let updated: Vec<ApiKey> = keys.iter().filter(|k| k.id != id).cloned().collect();
+        self.save(&updated)?;
+        *keys = updated;
+        Ok(true)
+    }
+
+    /// The key a bearer token is, None if it is not an unexpired API key
+    pub fn authenticate(&self, token: &str) -> Option<ApiKey> {
+        if token.starts_with(KEY_PREFIX) == false {
+            return None;
+        }
+
+        let hash = hash(token);
+        let now = now();
+        self.keys
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|k| {
+                constant_time_eq(k.hash.as_bytes(), hash.as_bytes())
+                    && k.expires.map(|e| now < e) != Some(false)
+            })
+            .cloned()
+    }
+
+    /// Write the keys to a temporary file and rename it over the key file, so the file is never
+    /// left part written
+    fn save(&self, keys: &[ApiKey]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temporary = self.path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_vec_pretty(keys)?)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+fn hash(key: &str) -> String {
+    digest(&SHA256, key.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut random = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut random);
+    random.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,308 @@
+use std::fs::OpenOptions;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Method, Status};
+use rocket::{Request, Response};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::envelope;
+use crate::identity::ClientIdentity;
+use crate::manager::Manager;
+
+/// A request which could change the state of the daemon or a deployment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    /// Unix timestamp of the response
+    pub time: u64,
+    pub request_id: String,
+    /// The client as it was identified, or unauthenticated if it was rejected with a 401
+    pub client: String,
+    pub method: String,
+    /// The matched route, i.e. /v1/deployments/<name>/start
+    pub route: Option<String>,
+    pub path: String,
+    pub deployment: Option<String>,
+    /// The image the deployment was running after the request, and the digest it was pulled by
+    pub image: Option<String>,
+    pub digest: Option<String>,
+    pub status: u16,
+    pub success: bool,
+}
+
+/// The log is read backwards from the end in blocks of this size, so a query only reads as
+/// much of it as the entries it returns need
+const READ_BLOCK: u64 = 64 * 1024;
+
+/// An append only file with one JSON entry per line
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Unable to create audit_log ({}): {}", path.display(), e))?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|e| format!("Unable to open audit_log ({}): {}", path.display(), e))?;
+
+        Ok(AuditLog {
+            path: path.to_owned(),
+            file: Mutex::new(File::from_std(file)),
+        })
+    }
+
+    pub async fn record(&self, entry: &AuditEntry) {
+        let mut line = match serde_json::to_vec(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("Unable to record audit entry: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // The lock is queued in order, so entries are written in the order they are recorded
+        let mut file = self.file.lock().await;
+        let written = match file.write_all(&line).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            println!("Unable to write audit log ({}): {}", self.path.display(), e);
+        }
+    }
+
+    /// The most recent `limit` entries matching the filters, oldest first
+    pub async fn query(
+        &self,
+        deployment: Option<&str>,
+        client: Option<&str>,
+        since: Option<u64>,
+        limit: usize,
+    ) -> io::Result<Vec<AuditEntry>> {
+        let mut file = File::open(&self.path).await?;
+        let mut end = file.metadata().await?.len();
+        let mut entries = vec![];
+        // The start of a line which began in the block before the one just read
+        let mut partial = vec![];
+
+        while end > 0 && entries.len() < limit {
+            let start = end.saturating_sub(READ_BLOCK);
+            let mut block = vec![0; (end - start) as usize];
+            file.seek(SeekFrom::Start(start)).await?;
+            file.read_exact(&mut block).await?;
+            block.append(&mut partial);
+            end = start;
+
+            let mut lines: Vec<&[u8]> = block.split(|b| *b == b'\n').collect();
+            if start > 0 {
+                partial = lines.remove(0).to_vec();
+            }
+
+            for line in lines.into_iter().rev() {
+                // A line being written as the log is read is incomplete, and skipped
+                let entry = match serde_json::from_slice::<AuditEntry>(line) {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+                // Entries are in time order, nothing before this one can match
+                if since.map(|s| entry.time < s) == Some(true) {
+                    end = 0;
+                    break;
+                }
+                if deployment.is_some() && entry.deployment.as_deref() != deployment {
+                    continue;
+                }
+                if client.is_some() && Some(entry.client.as_str()) != client {
+                    continue;
+                }
+
+                entries.push(entry);
+                if entries.len() == limit {
+                    break;
+                }
+            }
+        }
+
+        entries.reverse();
+        Ok(entries)
+    }
+}
+
+/// Records every request which is not a GET, HEAD or OPTIONS in the audit log, if one is
+/// configured, and console sessions as they can write to the container
+pub struct Audit;
+
+#[rocket::async_trait]
+impl Fairing for Audit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Audit log",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let log = match request.rocket().state::<Option<AuditLog>>() {
+            Some(Some(log)) => log,
+            _ => return,
+        };
+        let console = request.route().and_then(|r| r.name.as_deref()) == Some("get_console");
+        if matches!(
+            request.method(),
+            Method::Get | Method::Head | Method::Options
+        ) && console == false
+        {
+            return;
+        }
+
+        let client = match request.guard::<ClientIdentity>().await.succeeded() {
+            Some(identity) => identity.to_string(),
+            None => "unauthenticated".into(),
+        };
+        let deployment = deployment(request);
+        let status = response.status();
+        let success = status.class().is_success() || status == Status::SwitchingProtocols;
+
+        let (image, digest) = match (&deployment, request.rocket().state::<Arc<Mutex<Manager>>>()) {
+            (Some(name), Some(manager)) if success => manager
+                .lock()
+                .await
+                .deployments()
+                .iter()
+                .find(|d| &d.name == name)
+                .map(|d| (Some(d.image.to_owned()), d.digest.to_owned()))
+                .unwrap_or_default(),
+            _ => (None, None),
+        };
+
+        log.record(&AuditEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            request_id: envelope::id(request),
+            client,
+            method: request.method().as_str().to_owned(),
+            route: request.route().map(|r| r.uri.origin.path().to_string()),
+            path: request.uri().path().to_string(),
+            deployment,
+            image,
+            digest,
+            status: status.code,
+            success,
+        })
+        .await;
+    }
+}
+
+/// The deployment named in a /v1/deployments/<name>/... or /v2/deployments/<name>/... path
+fn deployment(request: &Request<'_>) -> Option<String> {
+    let mut segments = request.uri().path().segments();
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some("v1" | "v2"), Some("deployments"), Some(name)) => Some(name.to_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(i: u64) -> AuditEntry {
+        AuditEntry {
+            time: 1_000 + i / 2,
+            request_id: format!("request-{}", i),
+            client: format!("client-{}", i % 3),
+            method: "POST".into(),
+            route: Some("/v1/deployments/<name>/start".into()),
+            // Long lines so the entries span several blocks, and lines cross their boundaries
+            path: format!(
+                "/v1/deployments/{}/start?{}",
+                i,
+                "x".repeat(i as usize % 300)
+            ),
+            deployment: Some(["a", "b"][i as usize % 2].into()),
+            image: None,
+            digest: None,
+            status: 200,
+            success: true,
+        }
+    }
+
+    fn ids(entries: &[AuditEntry]) -> Vec<String> {
+        entries.iter().map(|e| e.request_id.to_owned()).collect()
+    }
+
+    #[rocket::async_test]
+    async fn query_across_blocks() {
+        let path = std::env::temp_dir().join(format!("edd-audit-test-{}.log", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let log = AuditLog::open(&path).unwrap();
+        let all: Vec<AuditEntry> = (0..2_000).map(entry).collect();
+        for entry in &all {
+            log.record(entry).await;
+        }
+        assert!(std::fs::metadata(&path).unwrap().len() > 4 * READ_BLOCK);
+
+        // The filters applied to every entry, keeping the last limit
+        let expected =
+            |deployment: Option<&str>, client: Option<&str>, since: Option<u64>, limit| {
+                let matching: Vec<AuditEntry> = all
+                    .iter()
+                    .filter(|e| deployment.is_none() || e.deployment.as_deref() == deployment)
+                    .filter(|e| client.is_none() || Some(e.client.as_str()) == client)
+                    .filter(|e| since.is_none() || Some(e.time) >= since)
+                    .cloned()
+                    .collect();
+                ids(&matching[matching.len().saturating_sub(limit)..])
+            };
+
+        let queries = [
+            (None, None, None, 10),
+            (None, None, None, usize::MAX),
+            (Some("a"), None, None, 700),
+            (None, Some("client-1"), None, 500),
+            (Some("b"), Some("client-2"), None, usize::MAX),
+            (None, None, Some(1_900), usize::MAX),
+            (Some("a"), None, Some(1_500), 50),
+            (None, Some("client-0"), Some(1_000), usize::MAX),
+            (Some("c"), None, None, usize::MAX),
+            (None, None, Some(5_000), usize::MAX),
+        ];
+        for (deployment, client, since, limit) in queries {
+            let entries = log.query(deployment, client, since, limit).await.unwrap();
+            assert_eq!(
+                ids(&entries),
+                expected(deployment, client, since, limit),
+                "{:?} {:?} {:?} {}",
+                deployment,
+                client,
+                since,
+                limit
+            );
+            assert!(entries.windows(2).all(|w| w[0].time <= w[1].time));
+        }
+
+        // A line still being written is skipped
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, b"{\"time\": 3000, \"request_").unwrap();
+        let entries = log.query(None, None, None, 3).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(ids(&entries), expected(None, None, None, 3));
+    }
+}
@@ -0,0 +1,302 @@
+use std::sync::Arc;
+
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config_file::Config;
+use crate::identity::ClientIdentity;
+
+/// Actions which can be granted to a client in the authorization config section
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Start,
+    Stop,
+    Delete,
+    Load,
+    Pull,
+    /// Build the deployment image from an uploaded build context
+    Build,
+    Recreate,
+    /// Start the deployment from the image it ran before the last load, pull or build
+    Rollback,
+    Restart,
+    /// Pause and unpause
+    Pause,
+    /// Download the deployment image
+    Export,
+    /// Change the cpu and memory limits of a running deployment
+    Resources,
+    /// Copy files into and out of the deployment container
+    Files,
+    /// Read the deployment container's logs
+    Logs,
+    /// Attach to the deployment container's console, only when console is enabled
+    Console,
+    /// Run commands in the deployment container
+    Exec,
+    /// Issue and revoke API keys, read the audit log, add and remove deployments, reload the
+    /// config and prune the system, only granted by a rule for every deployment (`*`)
+    Admin,
+}
+
+/// The deployments and actions an API key may use
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct Grant {
+    pub deployments: Vec<String>,
+    pub actions: Vec<Action>,
+}
+
+impl Grant {
+    fn permits(&self, deployment: &str, action: Action) -> bool {
+        self.actions.contains(&action) && self.covers(deployment)
+    }
+
+    fn covers(&self, deployment: &str) -> bool {
+        self.deployments
+            .iter()
+            .any(|d| deployment_matches(d, deployment))
+    }
+}
+
+/// Request guard for routes which change container state, requests are rejected with a 403
+/// when the daemon is in read only mode
+pub struct Writable;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Writable {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let read_only = request
+            .rocket()
+            .state::<Arc<Config>>()
+            .map(|c| c.read_only)
+            .unwrap_or(true);
+
+        if read_only {
+            return Outcome::Error((Status::Forbidden, ()));
+        }
+
+        Outcome::Success(Writable)
+    }
+}
+
+/// Checks the client is permitted to perform the action on the named deployment
+///
+/// If no authorization rules are configured every client is permitted to do anything, once
+/// a rule exists clients (including anonymous ones) need a matching rule or get a 403. API
+/// keys are only permitted what they were granted, whatever the rules
+pub fn authorize(
+    config: &Config,
+    identity: &ClientIdentity,
+    deployment: &str,
+    action: Action,
+) -> Result<(), Status> {
+    let permitted = match &identity.grant {
+        Some(grant) => grant.permits(deployment, action),
+        None if config.authorization.is_empty() => return Ok(()),
+        None => config.authorization.iter().any(|rule| {
+            identity.is(&rule.client)
+                && rule.actions.contains(&action)
+                && rule
+                    .deployments
+                    .iter()
+                    .any(|d| deployment_matches(d, deployment))
+        }),
+    };
+
+    if permitted == false {
+        warn!(
+            "Client {} is not permitted to {:?} deployment '{}'",
+            identity, action, deployment
+        );
+        return Err(Status::Forbidden);
+    }
+
+    Ok(())
+}
+
+/// Whether the client may see the deployment, once authorization rules are configured clients
+/// only see the deployments they have a rule for, and API keys the deployments they were
+/// granted
+pub fn is_visible(config: &Config, identity: &ClientIdentity, deployment: &str) -> bool {
+    if let Some(grant) = &identity.grant {
+        return grant.covers(deployment);
+    }
+
+    config.authorization.is_empty()
+        || config.authorization.iter().any(|rule| {
+            identity.is(&rule.client)
+                && rule
+                    .deployments
+                    .iter()
+                    .any(|d| deployment_matches(d, deployment))
+        })
+}
+
+/// Deployments in a rule are a name, `*` for every deployment or a prefix ending in `*`, i.e.
+/// `vendor-a-*`
+fn deployment_matches(pattern: &str, deployment: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => deployment.starts_with(prefix),
+        None => pattern == deployment,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_file::tests::test_config;
+    use serde_json::json;
+
+    fn client(name: &str) -> ClientIdentity {
+        ClientIdentity {
+            name: Some(name.into()),
+            alternative_names: vec![],
+            grant: None,
+        }
+    }
+
+    fn anonymous() -> ClientIdentity {
+        ClientIdentity {
+            name: None,
+            alternative_names: vec![],
+            grant: None,
+        }
+    }
+
+    fn with_rules() -> Config {
+        test_config(json!({
+            "authorization": [
+                {"client": "vendor-a", "deployments": ["vendor-a-*"], "actions": ["start", "logs"]},
+                {"client": "ops.example.com", "deployments": ["website"], "actions": ["stop"]},
+                {"client": "admin", "deployments": ["*"], "actions": ["admin", "delete"]},
+                {"client": "partial-admin", "deployments": ["vendor-*"], "actions": ["admin"]},
+            ]
+        }))
+    }
+
+    #[test]
+    fn deployment_patterns() {
+        assert!(deployment_matches("website", "website"));
+        assert!(deployment_matches("website", "website-2") == false);
+        assert!(deployment_matches("vendor-a-*", "vendor-a-web"));
+        assert!(deployment_matches("vendor-a-*", "vendor-a-"));
+        assert!(deployment_matches("vendor-a-*", "vendor-b-web") == false);
+        assert!(deployment_matches("*", "website"));
+        assert!(deployment_matches("*", "*"));
+    }
+
+    #[test]
+    fn no_rules_allow_everything() {
+        let config = test_config(json!({}));
+        for identity in [client("anyone"), anonymous()] {
+            assert_eq!(
+                authorize(&config, &identity, "website", Action::Delete),
+                Ok(())
+            );
+            assert_eq!(authorize(&config, &identity, "*", Action::Admin), Ok(()));
+            assert!(is_visible(&config, &identity, "website"));
+        }
+    }
+
+    #[test]
+    fn prefix_rules() {
+        let config = with_rules();
+        let vendor = client("vendor-a");
+
+        assert_eq!(
+            authorize(&config, &vendor, "vendor-a-web", Action::Start),
+            Ok(())
+        );
+        assert_eq!(
+            authorize(&config, &vendor, "vendor-a-web", Action::Delete),
+            Err(Status::Forbidden)
+        );
+        assert_eq!(
+            authorize(&config, &vendor, "vendor-b-web", Action::Start),
+            Err(Status::Forbidden)
+        );
+        assert!(is_visible(&config, &vendor, "vendor-a-web"));
+        assert!(is_visible(&config, &vendor, "website") == false);
+
+        // Once there are rules, clients without one are refused
+        for identity in [client("someone-else"), anonymous()] {
+            assert_eq!(
+                authorize(&config, &identity, "vendor-a-web", Action::Start),
+                Err(Status::Forbidden)
+            );
+            assert!(is_visible(&config, &identity, "vendor-a-web") == false);
+        }
+
+        // Rules match the certificate's alternative names too
+        let ops = ClientIdentity {
+            name: Some("ops".into()),
+            alternative_names: vec!["ops.example.com".into()],
+            grant: None,
+        };
+        assert_eq!(authorize(&config, &ops, "website", Action::Stop), Ok(()));
+    }
+
+    #[test]
+    fn admin_needs_every_deployment() {
+        let config = with_rules();
+
+        let admin = client("admin");
+        assert_eq!(authorize(&config, &admin, "*", Action::Admin), Ok(()));
+        assert_eq!(
+            authorize(&config, &admin, "website", Action::Delete),
+            Ok(())
+        );
+        assert!(is_visible(&config, &admin, "anything"));
+
+        let partial = client("partial-admin");
+        assert_eq!(
+            authorize(&config, &partial, "*", Action::Admin),
+            Err(Status::Forbidden)
+        );
+        assert_eq!(
+            authorize(&config, &client("vendor-a"), "*", Action::Admin),
+            Err(Status::Forbidden)
+        );
+    }
+
+    #[test]
+    fn grants_override_rules() {
+        let grant = Grant {
+            deployments: vec!["vendor-a-web".into()],
+            actions: vec![Action::Logs],
+        };
+
+        // The key's name matches the admin rule, but only the grant counts
+        let key = ClientIdentity {
+            name: Some("admin".into()),
+            alternative_names: vec![],
+            grant: Some(grant),
+        };
+        for config in [with_rules(), test_config(json!({}))] {
+            assert_eq!(
+                authorize(&config, &key, "vendor-a-web", Action::Logs),
+                Ok(())
+            );
+            assert_eq!(
+                authorize(&config, &key, "vendor-a-web", Action::Start),
+                Err(Status::Forbidden)
+            );
+            assert_eq!(
+                authorize(&config, &key, "website", Action::Logs),
+                Err(Status::Forbidden)
+            );
+            assert_eq!(
+                authorize(&config, &key, "*", Action::Admin),
+                Err(Status::Forbidden)
+            );
+            assert!(is_visible(&config, &key, "vendor-a-web"));
+            assert!(is_visible(&config, &key, "website") == false);
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, RSA_PKCS1_2048_8192_SHA256};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Seconds of clock difference allowed when checking the exp and nbf claims of a JWT
+const CLOCK_LEEWAY: u64 = 60;
+
+/// A static bearer token from the config, the name is the client used in authorization rules
+#[derive(Debug, Deserialize)]
+pub struct BearerToken {
+    pub name: String,
+    pub token: Option<String>,
+    /// File containing the token, read when the config is loaded
+    pub token_file: Option<String>,
+}
+
+/// JWT validation settings from the config
+#[derive(Debug, Deserialize)]
+pub struct JwtConfig {
+    /// HS256, RS256 or ES256
+    pub algorithm: String,
+    /// Shared secret for HS256
+    pub secret: Option<String>,
+    /// PEM public key for RS256 and ES256
+    pub key_file: Option<String>,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    /// Claim holding the client name used in authorization rules, sub by default
+    pub identity_claim: Option<String>,
+}
+
+#[derive(Debug)]
+enum JwtKey {
+    Hmac(hmac::Key),
+    Rsa(Vec<u8>),
+    Ecdsa(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct Jwt {
+    algorithm: String,
+    key: JwtKey,
+    issuer: Option<String>,
+    audience: Option<String>,
+    identity_claim: String,
+}
+
+/// Bearer token authentication, enabled when static tokens or JWT validation are configured
+#[derive(Debug, Default)]
+pub struct TokenAuth {
+    /// Client names with the SHA-256 of their token
+    tokens: Vec<(String, Vec<u8>)>,
+    jwt: Option<Jwt>,
+}
+
+impl TokenAuth {
+    pub fn new(tokens: Vec<BearerToken>, jwt: Option<JwtConfig>) -> Result<Self, String> {
+        let mut hashed = vec![];
+        for token in tokens {
+            let value = match (&token.token, &token.token_file) {
+                (Some(value), None) => value.to_owned(),
+                (None, Some(file)) => std::fs::read_to_string(file)
+                    .map_err(|e| format!("Unable to read token_file ({}): {}", file, e))?
+                    .trim()
+                    .to_owned(),
+                _ => {
+                    return Err(format!(
+                        "token '{}' must have one of token or token_file",
+                        token.name
+                    ))
+                }
+            };
+            if value.len() < 16 {
+                return Err(format!(
+                    "token '{}' must be at least 16 characters",
+                    token.name
+                ));
+            }
+            hashed.push((
+                token.name,
+                digest(&SHA256, value.as_bytes()).as_ref().to_vec(),
+            ));
+        }
+
+        let jwt = match jwt {
+            Some(jwt) => Some(Jwt::new(jwt)?),
+            None => None,
+        };
+
+        Ok(TokenAuth {
+            tokens: hashed,
+            jwt,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.tokens.is_empty() == false || self.jwt.is_some()
+    }
+
+    /// The client a bearer token identifies, None if it is not valid
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        let hash = digest(&SHA256, token.as_bytes());
+        for (name, expected) in &self.tokens {
+            if constant_time_eq(hash.as_ref(), expected) {
+                return Some(name.to_owned());
+            }
+        }
+
+        match &self.jwt {
+            Some(jwt) if token.split('.').count() == 3 => jwt.validate(token),
+            _ => None,
+        }
+    }
+}
+
+impl Jwt {
+    fn new(config: JwtConfig) -> Result<Self, String> {
+        let key = match (config.algorithm.as_str(), &config.secret, &config.key_file) {
+            ("HS256", Some(secret), None) => {
+                JwtKey::Hmac(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()))
+            }
+            ("RS256", None, Some(file)) => JwtKey::Rsa(read_public_key(file)?),
+            ("ES256", None, Some(file)) => JwtKey::Ecdsa(read_public_key(file)?),
+            ("HS256", _, _) => return Err("jwt HS256 needs a secret and no key_file".into()),
+            ("RS256", _, _) | ("ES256", _, _) => {
+                return Err(format!(
+                    "jwt {} needs a key_file and no secret",
+                    config.algorithm
+                ))
+            }
+            (algorithm, _, _) => {
+                return Err(format!(
+                    "jwt algorithm ({}) is not supported, use HS256, RS256 or ES256",
+                    algorithm
+                ))
+            }
+        };
+
+        Ok(Jwt {
+            algorithm: config.algorithm,
+            key,
+            issuer: config.issuer,
+            audience: config.audience,
+            identity_claim: config.identity_claim.unwrap_or("sub".into()),
+        })
+    }
+
+    /// The identity claim of a JWT with a valid signature and claims
+    fn validate(&self, token: &str) -> Option<String> {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let (signed, signature) = token.rsplit_once('.')?;
+        let (header, claims) = signed.split_once('.')?;
+
+        // The algorithm is fixed by the config, never chosen by the token
+        let header: Value = serde_json::from_slice(&engine.decode(header).ok()?).ok()?;
+        if header.get("alg").and_then(|a| a.as_str()) != Some(self.algorithm.as_str()) {
+            return None;
+        }
+
+        let signature = engine.decode(signature).ok()?;
+        let verified = match &self.key {
+            JwtKey::Hmac(key) => hmac::verify(key, signed.as_bytes(), &signature).is_ok(),
+            JwtKey::Rsa(key) => UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key)
+                .verify(signed.as_bytes(), &signature)
+                .is_ok(),
+            JwtKey::Ecdsa(key) => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key)
+                .verify(signed.as_bytes(), &signature)
+                .is_ok(),
+        };
+        if verified == false {
+            return None;
+        }
+
+        let claims: Value = serde_json::from_slice(&engine.decode(claims).ok()?).ok()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+        // Tokens must expire
+        let expires = claims.get("exp")?.as_u64()?;
+        if now > expires.saturating_add(CLOCK_LEEWAY) {
+            return None;
+        }
+        if let Some(not_before) = claims.get("nbf").and_then(|n| n.as_u64()) {
+            if now + CLOCK_LEEWAY < not_before {
+                return None;
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(|i| i.as_str()) != Some(issuer.as_str()) {
+                return None;
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(audience)),
+                _ => false,
+            };
+            if matches == false {
+                return None;
+            }
+        }
+
+        claims
+            .get(&self.identity_claim)?
+            .as_str()
+            .filter(|i| i.is_empty() == false)
+            .map(|i| i.to_owned())
+    }
+}
+
+/// The key from a PEM `PUBLIC KEY` (SubjectPublicKeyInfo) file, in the form ring expects - the
+/// contents of the subjectPublicKey bit string
+fn read_public_key(file: &str) -> Result<Vec<u8>, String> {
+    let pem = std::fs::read_to_string(file)
+        .map_err(|e| format!("Unable to read jwt key_file ({}): {}", file, e))?;
+    let base64: String = pem
+        .lines()
+        .skip_while(|l| l.starts_with("-----BEGIN PUBLIC KEY-----") == false)
+        .skip(1)
+        .take_while(|l| l.starts_with("-----END") == false)
+        .collect();
+    let der = base64::engine::general_purpose::STANDARD
+        .decode(base64.trim())
+        .map_err(|_| format!("jwt key_file ({}) is not a PEM public key", file))?;
+
+    subject_public_key(&der)
+        .map(|k| k.to_vec())
+        .ok_or(format!("jwt key_file ({}) is not a PEM public key", file))
+}
+
+/// SubjectPublicKeyInfo is SEQUENCE { AlgorithmIdentifier, BIT STRING }, the bit string is
+/// returned without its unused bits byte
+fn subject_public_key(der: &[u8]) -> Option<&[u8]> {
+    let (tag, info, _) = der_element(der)?;
+    if tag != 0x30 {
+        return None;
+    }
+    let (_, _, rest) = der_element(info)?;
+    let (tag, key, _) = der_element(rest)?;
+    if tag != 0x03 || key.first() != Some(&0) {
+        return None;
+    }
+
+    Some(&key[1..])
+}
+
+/// Split the first DER element into its tag, contents and the data after it
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *der.first()?;
+    let first = *der.get(1)? as usize;
+    let (length, start): (usize, usize) = match first {
+        length if length < 0x80 => (length, 2),
+        0x81 => (*der.get(2)? as usize, 3),
+        0x82 => (((*der.get(2)? as usize) << 8) | *der.get(3)? as usize, 4),
+        _ => return None,
+    };
+    let end = start.checked_add(length)?;
+    if end > der.len() {
+        return None;
+    }
+
+    Some((tag, &der[start..end], &der[end..]))
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+
+    const SECRET: &str = "a shared secret for the tests";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn jwt_config(algorithm: &str) -> JwtConfig {
+        JwtConfig {
+            algorithm: algorithm.into(),
+            secret: None,
+            key_file: None,
+            issuer: None,
+            audience: None,
+            identity_claim: None,
+        }
+    }
+
+    fn hs256() -> TokenAuth {
+        let config = JwtConfig {
+            secret: Some(SECRET.into()),
+            ..jwt_config("HS256")
+        };
+        TokenAuth::new(vec![], Some(config)).unwrap()
+    }
+
+    /// A token with the given header and claims, signed by sign
+    fn encode(header: Value, claims: Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let signed = format!(
+            "{}.{}",
+            engine.encode(header.to_string()),
+            engine.encode(claims.to_string())
+        );
+        let signature = engine.encode(sign(signed.as_bytes()));
+        format!("{}.{}", signed, signature)
+    }
+
+    fn hmac_sign(signed: &[u8]) -> Vec<u8> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, SECRET.as_bytes());
+        hmac::sign(&key, signed).as_ref().to_vec()
+    }
+
+    fn hs256_token(claims: Value) -> String {
+        encode(json!({"alg": "HS256", "typ": "JWT"}), claims, hmac_sign)
+    }
+
+    #[test]
+    fn static_tokens() {
+        let tokens = vec![BearerToken {
+            name: "ci".into(),
+            token: Some("0123456789abcdef".into()),
+            token_file: None,
+        }];
+        let auth = TokenAuth::new(tokens, None).unwrap();
+        assert!(auth.enabled());
+        assert_eq!(auth.authenticate("0123456789abcdef"), Some("ci".into()));
+        assert_eq!(auth.authenticate("0123456789abcdeF"), None);
+
+        let short = vec![BearerToken {
+            name: "ci".into(),
+            token: Some("short".into()),
+            token_file: None,
+        }];
+        assert!(TokenAuth::new(short, None).is_err());
+        assert!(TokenAuth::default().enabled() == false);
+    }
+
+    #[test]
+    fn valid_jwt() {
+        let token = hs256_token(json!({"sub": "edge-1", "exp": now() + 300}));
+        assert_eq!(hs256().authenticate(&token), Some("edge-1".into()));
+    }
+
+    #[test]
+    fn expired_or_missing_exp() {
+        let auth = hs256();
+
+        let expired = hs256_token(json!({"sub": "edge-1", "exp": now() - 2 * CLOCK_LEEWAY}));
+        assert_eq!(auth.authenticate(&expired), None);
+
+        // Within the leeway is still accepted
+        let skewed = hs256_token(json!({"sub": "edge-1", "exp": now() - CLOCK_LEEWAY / 2}));
+        assert_eq!(auth.authenticate(&skewed), Some("edge-1".into()));
+
+        let missing = hs256_token(json!({"sub": "edge-1"}));
+        assert_eq!(auth.authenticate(&missing), None);
+
+        let not_number = hs256_token(json!({"sub": "edge-1", "exp": "tomorrow"}));
+        assert_eq!(auth.authenticate(&not_number), None);
+
+        let not_yet = hs256_token(json!({
+            "sub": "edge-1",
+            "exp": now() + 600,
+            "nbf": now() + 300
+        }));
+        assert_eq!(auth.authenticate(&not_yet), None);
+    }
+
+    #[test]
+    fn wrong_alg() {
+        let auth = hs256();
+        let claims = json!({"sub": "edge-1", "exp": now() + 300});
+
+        // The signature is valid, but the algorithm is not the configured one
+        let token = encode(json!({"alg": "HS384"}), claims.clone(), hmac_sign);
+        assert_eq!(auth.authenticate(&token), None);
+
+        let token = encode(json!({"alg": "none"}), claims.clone(), |_| vec![]);
+        assert_eq!(auth.authenticate(&token), None);
+
+        let token = encode(json!({}), claims, hmac_sign);
+        assert_eq!(auth.authenticate(&token), None);
+
+        assert!(TokenAuth::new(vec![], Some(jwt_config("none"))).is_err());
+    }
+
+    #[test]
+    fn bad_signature() {
+        let auth = hs256();
+        let claims = json!({"sub": "edge-1", "exp": now() + 300});
+
+        let other = hmac::Key::new(hmac::HMAC_SHA256, b"some other secret");
+        let token = encode(json!({"alg": "HS256"}), claims.clone(), |signed| {
+            hmac::sign(&other, signed).as_ref().to_vec()
+        });
+        assert_eq!(auth.authenticate(&token), None);
+
+        // Claims swapped after signing
+        let token = hs256_token(claims);
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let forged = engine.encode(json!({"sub": "admin", "exp": now() + 300}).to_string());
+        let token = format!("{}.{}.{}", header, forged, signature);
+        assert_eq!(auth.authenticate(&token), None);
+
+        assert_eq!(auth.authenticate("not.a.jwt"), None);
+        assert_eq!(auth.authenticate("header.claims"), None);
+    }
+
+    #[test]
+    fn issuer_audience_and_identity_claim() {
+        let config = JwtConfig {
+            secret: Some(SECRET.into()),
+            issuer: Some("https://issuer".into()),
+            audience: Some("ed-daemon".into()),
+            identity_claim: Some("device".into()),
+            ..jwt_config("HS256")
+        };
+        let auth = TokenAuth::new(vec![], Some(config)).unwrap();
+        let exp = now() + 300;
+
+        let token = hs256_token(json!({
+            "device": "edge-1",
+            "iss": "https://issuer",
+            "aud": ["other", "ed-daemon"],
+            "exp": exp
+        }));
+        assert_eq!(auth.authenticate(&token), Some("edge-1".into()));
+
+        let token = hs256_token(json!({
+            "device": "edge-1",
+            "iss": "https://elsewhere",
+            "aud": "ed-daemon",
+            "exp": exp
+        }));
+        assert_eq!(auth.authenticate(&token), None);
+
+        let token = hs256_token(json!({
+            "device": "edge-1",
+            "iss": "https://issuer",
+            "aud": "other",
+            "exp": exp
+        }));
+        assert_eq!(auth.authenticate(&token), None);
+
+        let token = hs256_token(json!({
+            "sub": "edge-1",
+            "iss": "https://issuer",
+            "aud": "ed-daemon",
+            "exp": exp
+        }));
+        assert_eq!(auth.authenticate(&token), None);
+    }
+
+    #[test]
+    fn es256_key_file() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+
+        // SubjectPublicKeyInfo for a P-256 key, followed by the uncompressed point
+        let mut der = vec![
+            0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06,
+            0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+        ];
+        der.extend_from_slice(pair.public_key().as_ref());
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::engine::general_purpose::STANDARD.encode(&der)
+        );
+        let file = std::env::temp_dir().join(format!("edd-test-{}.pem", std::process::id()));
+        std::fs::write(&file, pem).unwrap();
+
+        let config = JwtConfig {
+            key_file: Some(file.to_str().unwrap().into()),
+            ..jwt_config("ES256")
+        };
+        let auth = TokenAuth::new(vec![], Some(config));
+        std::fs::remove_file(&file).ok();
+        let auth = auth.unwrap();
+
+        let claims = json!({"sub": "edge-1", "exp": now() + 300});
+        let token = encode(json!({"alg": "ES256"}), claims.clone(), |signed| {
+            pair.sign(&rng, signed).unwrap().as_ref().to_vec()
+        });
+        assert_eq!(auth.authenticate(&token), Some("edge-1".into()));
+
+        // An HS256 token signed with the public key must not be accepted as ES256
+        let key = hmac::Key::new(hmac::HMAC_SHA256, pair.public_key().as_ref());
+        let token = encode(json!({"alg": "HS256"}), claims, |signed| {
+            hmac::sign(&key, signed).as_ref().to_vec()
+        });
+        assert_eq!(auth.authenticate(&token), None);
+    }
+
+    #[test]
+    fn malformed_public_keys() {
+        assert_eq!(subject_public_key(&[]), None);
+        assert_eq!(subject_public_key(&[0x30, 0x05, 0x00]), None);
+        assert_eq!(subject_public_key(&[0x04, 0x00]), None);
+        assert_eq!(der_element(&[0x30, 0x84, 0, 0, 0, 1, 0]), None);
+
+        let config = JwtConfig {
+            key_file: Some("/nonexistent/key.pem".into()),
+            ..jwt_config("RS256")
+        };
+        assert!(TokenAuth::new(vec![], Some(config)).is_err());
+    }
+}
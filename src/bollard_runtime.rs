@@ -0,0 +1,988 @@
+use std::collections::HashMap;
+
+use bollard::container::{
+    AttachContainerOptions, Config, CreateContainerOptions, DownloadFromContainerOptions,
+    KillContainerOptions, ListContainersOptions, LogOutput, LogsOptions, RenameContainerOptions,
+    RestartContainerOptions, StartContainerOptions, StatsOptions, StopContainerOptions,
+    UpdateContainerOptions, UploadToContainerOptions, WaitContainerOptions,
+};
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::image::{
+    BuildImageOptions, CreateImageOptions, ImportImageOptions, ListImagesOptions,
+    RemoveImageOptions, TagImageOptions,
+};
+use bollard::network::{ConnectNetworkOptions, CreateNetworkOptions, ListNetworksOptions};
+use bollard::system::EventsOptions;
+use bollard::volume::{CreateVolumeOptions, ListVolumesOptions, RemoveVolumeOptions};
+use bollard::Docker;
+use futures::{StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::docker_client::{filesystem_free_space, DockerClient, DockerSocket, Timeouts};
+use crate::docker_error::DockerError;
+use crate::docker_structs::*;
+use crate::run_args;
+use crate::runtime::{
+    select_loaded_image, Attached, ContainerEvents, ContainerRuntime, ExecOutput, LogChunks,
+    Progress,
+};
+
+/// Container runtime backed by the bollard docker client, built with `--features bollard`
+///
+/// bollard's models are converted to the structs in ./src/docker_structs.rs through their
+/// json representation, which both sides share with the docker API. Only unix and tcp sockets
+/// are supported, TLS and SSH connections always use DockerClient.
+pub struct BollardRuntime {
+    docker: Docker,
+    timeouts: Timeouts,
+    /// Docker is on this host, reached through a unix socket
+    local: bool,
+}
+
+impl BollardRuntime {
+    pub fn new(socket: &DockerSocket, timeouts: Timeouts) -> Result<Self, DockerError> {
+        let timeout = timeouts.request.as_secs();
+        let docker = match socket {
+            #[cfg(unix)]
+            DockerSocket::Unix(path) => {
+                Docker::connect_with_unix(path, timeout, bollard::API_DEFAULT_VERSION)?
+            }
+            #[cfg(not(unix))]
+            DockerSocket::Unix(_) => {
+                return Err(DockerError::InvalidRequest(
+                    "unix:// docker sockets are not supported on this platform, use npipe://"
+                        .into(),
+                ))
+            }
+            #[cfg(windows)]
+            DockerSocket::NamedPipe(path) => {
+                Docker::connect_with_named_pipe(path, timeout, bollard::API_DEFAULT_VERSION)?
+            }
+            #[cfg(not(windows))]
+            DockerSocket::NamedPipe(_) => {
+                return Err(DockerError::InvalidRequest(
+                    "npipe:// docker sockets are only supported on Windows".into(),
+                ))
+            }
+            DockerSocket::Tcp(address) => Docker::connect_with_http(
+                &format!("tcp://{}", address),
+                timeout,
+                bollard::API_DEFAULT_VERSION,
+            )?,
+            DockerSocket::Ssh { .. } => {
+                return Err(DockerError::InvalidRequest(
+                    "ssh:// docker sockets are not supported by the bollard runtime".into(),
+                ))
+            }
+        };
+
+        Ok(BollardRuntime {
+            docker,
+            timeouts,
+            local: matches!(socket, DockerSocket::Unix(_)),
+        })
+    }
+
+    /// Client for loads, pulls and saves which can take much longer than other requests
+    fn long_client(&self) -> Docker {
+        self.docker.clone().with_timeout(self.timeouts.long)
+    }
+
+    /// Map an error from a request to endpoint, reporting bollard's timeout as a Timeout as
+    /// DockerClient does
+    fn request_error(
+        &self,
+        endpoint: &'static str,
+    ) -> impl Fn(bollard::errors::Error) -> DockerError {
+        BollardRuntime::error(endpoint, self.timeouts.request)
+    }
+
+    /// As request_error, for requests made with long_client
+    fn long_error(&self, endpoint: &'static str) -> impl Fn(bollard::errors::Error) -> DockerError {
+        BollardRuntime::error(endpoint, self.timeouts.long)
+    }
+
+    fn error(
+        endpoint: &'static str,
+        after: std::time::Duration,
+    ) -> impl Fn(bollard::errors::Error) -> DockerError {
+        move |e| match e {
+            bollard::errors::Error::RequestTimeoutError => DockerError::Timeout {
+                operation: endpoint.to_owned(),
+                after,
+            },
+            e => e.into(),
+        }
+    }
+
+    /// bollard handles TTY containers, their output arrives as LogOutput::Console
+    fn log_chunk(output: LogOutput) -> LogChunk {
+        match output {
+            LogOutput::StdErr { message } => LogChunk {
+                source: LogSource::Stderr,
+                data: message.to_vec(),
+            },
+            output => LogChunk {
+                source: LogSource::Stdout,
+                data: output.into_bytes().to_vec(),
+            },
+        }
+    }
+
+    /// Convert between a bollard model and ours using the json both serialize to
+    fn convert<T: Serialize, U: DeserializeOwned>(
+        endpoint: &str,
+        value: T,
+    ) -> Result<U, DockerError> {
+        serde_json::from_value(serde_json::to_value(value)?).map_err(|e| {
+            DockerError::MalformedResponse(format!(
+                "Unable to parse response from {}: {}",
+                endpoint, e
+            ))
+        })
+    }
+
+    /// Send an image tarball to /images/load, then retag
+    async fn load_image(
+        &self,
+        image: Vec<u8>,
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // bollard sends its own content type, but unsupported uploads are still rejected
+        DockerClient::tarball_content_type(&image)?;
+
+        let mut loaded_images = vec![];
+        let client = self.long_client();
+        let mut messages =
+            client.import_image(ImportImageOptions { quiet: false }, image.into(), None);
+        while let Some(message) = messages.next().await {
+            let message = message.map_err(self.long_error("/images/load"))?;
+            let message = ProgressMessage {
+                stream: message.stream,
+                status: message.status,
+                id: message.id,
+                progress: message.progress,
+                error: message.error,
+                aux: None,
+            };
+            loaded_images.extend(message.loaded_images());
+            progress(&message);
+        }
+
+        let loaded_image_name = select_loaded_image(&loaded_images, select)?;
+        self.rename_image(&loaded_image_name, new_name, overwrite)
+            .await?;
+
+        Ok(loaded_image_name)
+    }
+
+    /// Retag an image with the internal naming, see DockerClient::rename_image
+    async fn rename_image(
+        &self,
+        existing_name: &str,
+        new_name: &str,
+        overwrite: bool,
+    ) -> Result<(), DockerError> {
+        let split: Vec<&str> = new_name.split(":").collect();
+        if split.len() != 2 {
+            return Err(DockerError::Other(
+                "Unable to determine repo and tag for provided new_name".into(),
+            ));
+        }
+
+        // Refuse to move the tag if it is already in use by a different image
+        if overwrite == false {
+            if let Some(current) = self.inspect_image(new_name).await? {
+                let existing = self.inspect_image(existing_name).await?;
+                if existing.map(|i| i.id) != Some(current.id) {
+                    return Err(DockerError::Conflict(format!(
+                        "{} already exists and refers to a different image",
+                        new_name
+                    )));
+                }
+            }
+        }
+
+        self.docker
+            .tag_image(
+                existing_name,
+                Some(TagImageOptions {
+                    repo: split[0],
+                    tag: split[1],
+                }),
+            )
+            .await
+            .map_err(self.request_error("/images/{name}/tag"))?;
+
+        Ok(())
+    }
+}
+
+/// Map bollard errors onto ours, docker's status codes pick the variant as in DockerClient
+impl From<bollard::errors::Error> for DockerError {
+    fn from(e: bollard::errors::Error) -> Self {
+        use bollard::errors::Error;
+
+        match e {
+            Error::DockerResponseServerError {
+                status_code: 404,
+                message,
+            } => DockerError::NotFound(message),
+            Error::DockerResponseServerError {
+                status_code: 409,
+                message,
+            } => DockerError::Conflict(message),
+            Error::DockerResponseServerError {
+                status_code: 400,
+                message,
+            } => DockerError::InvalidRequest(message),
+            // Calls map their own timeouts, see BollardRuntime::request_error
+            Error::RequestTimeoutError => DockerError::Other("Docker request timed out".into()),
+            Error::JsonDataError { message, .. } => DockerError::MalformedResponse(message),
+            Error::IOError { err } => DockerError::Io(err),
+            e => DockerError::Other(e.to_string()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl ContainerRuntime for BollardRuntime {
+    async fn get_images(&self) -> Result<Vec<ImageList>, DockerError> {
+        let images = self
+            .docker
+            .list_images(None::<ListImagesOptions<String>>)
+            .await
+            .map_err(self.request_error("/images/json"))?;
+        BollardRuntime::convert("/images/json", images)
+    }
+
+    async fn get_containers(
+        &self,
+        filters: &[(&str, &str)],
+    ) -> Result<Vec<RunningContainer>, DockerError> {
+        let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, value) in filters {
+            grouped
+                .entry(key.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+
+        let containers = self
+            .docker
+            .list_containers(Some(ListContainersOptions::<String> {
+                all: true,
+                filters: grouped,
+                ..Default::default()
+            }))
+            .await
+            .map_err(self.request_error("/containers/json"))?;
+        BollardRuntime::convert("/containers/json", containers)
+    }
+
+    async fn inspect_running_container(&self, id: &str) -> Result<InspectContainer, DockerError> {
+        let inspection = self
+            .docker
+            .inspect_container(id, None)
+            .await
+            .map_err(self.request_error("/containers/{id}/json"))?;
+        BollardRuntime::convert("/containers/{id}/json", inspection)
+    }
+
+    async fn inspect_container_json(&self, id: &str) -> Result<serde_json::Value, DockerError> {
+        let inspection = self
+            .docker
+            .inspect_container(id, None)
+            .await
+            .map_err(self.request_error("/containers/{id}/json"))?;
+        BollardRuntime::convert("/containers/{id}/json", inspection)
+    }
+
+    /// bollard only accepts the tarball as a single buffer so, unlike DockerClient, the whole
+    /// image is read into RAM before it is sent
+    async fn load_container_image(
+        &self,
+        filename: &str,
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        let mut file = tokio::fs::File::open(filename).await?;
+        self.load_container_image_stream(&mut file, select, new_name, overwrite, progress)
+            .await
+    }
+
+    /// As with load_container_image the whole tarball is read into RAM first
+    async fn load_container_image_stream(
+        &self,
+        image: &mut (dyn AsyncRead + Send + Unpin),
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        let first = DockerClient::read_start(image).await?;
+        let mut buffer = vec![];
+        DockerClient::decompress(first, image)?
+            .read_to_end(&mut buffer)
+            .await?;
+        self.load_image(buffer, select, new_name, overwrite, progress)
+            .await
+    }
+
+    async fn pull_container_image(
+        &self,
+        image: &str,
+        auth: Option<&AuthConfig>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        let credentials = match auth {
+            Some(auth) => Some(BollardRuntime::convert("/images/create", auth)?),
+            None => None,
+        };
+
+        // The final status message names the image just pulled
+        let mut last_status = None;
+        let client = self.long_client();
+        let mut messages = client.create_image(
+            Some(CreateImageOptions {
+                from_image: image,
+                ..Default::default()
+            }),
+            None,
+            credentials,
+        );
+        while let Some(message) = messages.next().await {
+            let message = message.map_err(self.long_error("/images/create"))?;
+            if let Some(status) = &message.status {
+                last_status = Some(status.to_owned());
+            }
+            progress(&ProgressMessage {
+                stream: None,
+                status: message.status,
+                id: message.id,
+                progress: message.progress,
+                error: message.error,
+                aux: None,
+            });
+        }
+
+        let image_name = last_status
+            .as_deref()
+            .and_then(|s| s.split(" ").last())
+            .filter(|s| s.contains(":"))
+            .map(|s| s.to_owned())
+            .ok_or(DockerError::Other(format!(
+                "Unable to determine pulled image repo and tag, last status was: {}",
+                last_status.clone().unwrap_or_default()
+            )))?;
+        self.rename_image(&image_name, new_name, overwrite).await?;
+
+        Ok(image_name)
+    }
+
+    async fn build_image(
+        &self,
+        context: &mut (dyn AsyncRead + Send + Unpin),
+        dockerfile: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // As with load_container_image_stream, bollard needs the whole context up front
+        let first = DockerClient::read_start(context).await?;
+        let mut buffer = vec![];
+        DockerClient::decompress(first, context)?
+            .read_to_end(&mut buffer)
+            .await?;
+        DockerClient::tarball_content_type(&buffer)?;
+
+        let mut built = None;
+        let client = self.long_client();
+        let mut messages = client.build_image(
+            BuildImageOptions {
+                dockerfile: dockerfile.unwrap_or("Dockerfile"),
+                rm: true,
+                forcerm: true,
+                ..Default::default()
+            },
+            None,
+            Some(buffer.into()),
+        );
+        while let Some(message) = messages.next().await {
+            let message = message.map_err(self.long_error("/build"))?;
+            if let Some(error) = message.error {
+                return Err(DockerError::Other(format!("/build failed: {}", error)));
+            }
+            let message = ProgressMessage {
+                stream: message.stream,
+                status: message.status,
+                id: message.id,
+                progress: message.progress,
+                error: None,
+                aux: message.aux.map(|a| BuildResult { id: a.id }),
+            };
+            if let Some(id) = message.built_image() {
+                built = Some(id);
+            }
+            progress(&message);
+        }
+
+        let id = built.ok_or(DockerError::MalformedResponse(
+            "/build did not report the id of the built image".into(),
+        ))?;
+        self.rename_image(&id, new_name, overwrite).await?;
+
+        Ok(id)
+    }
+
+    async fn version(&self) -> Result<EngineVersion, DockerError> {
+        let version = self
+            .docker
+            .version()
+            .await
+            .map_err(self.request_error("/version"))?;
+        Ok(EngineVersion {
+            version: version.version.unwrap_or_default(),
+            api_version: version.api_version.unwrap_or_default(),
+        })
+    }
+
+    async fn system_info(&self) -> Result<SystemInfo, DockerError> {
+        let info = self
+            .docker
+            .info()
+            .await
+            .map_err(self.request_error("/info"))?;
+        Ok(SystemInfo {
+            docker_root_dir: info.docker_root_dir.unwrap_or_default(),
+            security_options: info.security_options.unwrap_or_default(),
+            cgroup_version: info.cgroup_version.map(|v| v.to_string()),
+            memory_limit: info.memory_limit.unwrap_or_default(),
+            swap_limit: info.swap_limit.unwrap_or_default(),
+            cpu_cfs_quota: info.cpu_cfs_quota.unwrap_or_default(),
+            pids_limit: info.pids_limit.unwrap_or_default(),
+            live_restore_enabled: info.live_restore_enabled.unwrap_or_default(),
+        })
+    }
+
+    /// See DockerClient::free_space
+    async fn free_space(&self) -> Result<Option<u64>, DockerError> {
+        if self.local == false {
+            return Ok(None);
+        }
+
+        let info = self
+            .docker
+            .info()
+            .await
+            .map_err(self.request_error("/info"))?;
+        Ok(info
+            .docker_root_dir
+            .as_deref()
+            .and_then(filesystem_free_space))
+    }
+
+    async fn remove_image(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        let options = RemoveImageOptions {
+            force,
+            ..Default::default()
+        };
+        self.docker
+            .remove_image(name, Some(options), None)
+            .await
+            .map_err(self.request_error("/images/{name}"))?;
+        Ok(())
+    }
+
+    async fn tag_image(&self, name: &str, new_name: &str) -> Result<(), DockerError> {
+        self.rename_image(name, new_name, true).await
+    }
+
+    async fn inspect_image(&self, name: &str) -> Result<Option<InspectImage>, DockerError> {
+        match self
+            .docker
+            .inspect_image(name)
+            .await
+            .map_err(self.request_error("/images/{name}/json"))
+        {
+            Ok(inspection) => Ok(Some(BollardRuntime::convert(
+                "/images/{name}/json",
+                inspection,
+            )?)),
+            Err(DockerError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn image_history(&self, name: &str) -> Result<Option<Vec<ImageHistory>>, DockerError> {
+        match self
+            .docker
+            .image_history(name)
+            .await
+            .map_err(self.request_error("/images/{name}/history"))
+        {
+            Ok(history) => Ok(Some(BollardRuntime::convert(
+                "/images/{name}/history",
+                history,
+            )?)),
+            Err(DockerError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn save_image(&self, name: &str, filename: &str) -> Result<(), DockerError> {
+        let client = self.long_client();
+        let mut chunks = client.export_image(name);
+
+        let mut file = tokio::fs::File::create(filename).await?;
+        while let Some(chunk) = chunks.next().await {
+            file.write_all(&chunk.map_err(self.long_error("/images/{name}/get"))?)
+                .await?;
+        }
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    async fn events(&self, names: &[String]) -> Result<ContainerEvents, DockerError> {
+        let mut filters = HashMap::new();
+        filters.insert("type".to_owned(), vec!["container".to_owned()]);
+        filters.insert(
+            "event".to_owned(),
+            ["start", "stop", "die", "health_status"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        );
+        if names.is_empty() == false {
+            filters.insert("container".to_owned(), names.to_owned());
+        }
+
+        let events = self
+            .docker
+            .events(Some(EventsOptions {
+                filters,
+                ..Default::default()
+            }))
+            .filter_map(|message| async move {
+                let message = match message {
+                    Ok(message) => BollardRuntime::convert::<_, EventMessage>("/events", message),
+                    Err(e) => Err(e.into()),
+                };
+                match message {
+                    Ok(message) => ContainerEvent::from_message(message).map(Ok),
+                    Err(e) => Some(Err(e)),
+                }
+            });
+
+        Ok(events.boxed())
+    }
+
+    async fn stats(&self, id: &str) -> Result<ContainerStats, DockerError> {
+        let stats = self
+            .docker
+            .stats(
+                id,
+                Some(StatsOptions {
+                    stream: false,
+                    one_shot: false,
+                }),
+            )
+            .next()
+            .await
+            .ok_or(DockerError::MalformedResponse(format!(
+                "No stats returned for {}",
+                id
+            )))?
+            .map_err(self.request_error("/containers/{id}/stats"))?;
+        BollardRuntime::convert("/containers/{id}/stats", stats)
+    }
+
+    async fn list_volumes(&self) -> Result<Vec<Volume>, DockerError> {
+        let list = self
+            .docker
+            .list_volumes(None::<ListVolumesOptions<String>>)
+            .await
+            .map_err(self.request_error("/volumes"))?;
+        BollardRuntime::convert("/volumes", list.volumes.unwrap_or_default())
+    }
+
+    async fn create_volume(&self, volume: &CreateVolume) -> Result<Volume, DockerError> {
+        let created = self
+            .docker
+            .create_volume(CreateVolumeOptions {
+                name: volume.name.as_str(),
+                driver: volume.driver.as_str(),
+                driver_opts: volume
+                    .driver_opts
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect(),
+                labels: volume
+                    .labels
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect(),
+            })
+            .await
+            .map_err(self.request_error("/volumes/create"))?;
+        BollardRuntime::convert("/volumes/create", created)
+    }
+
+    async fn remove_volume(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        self.docker
+            .remove_volume(name, Some(RemoveVolumeOptions { force }))
+            .await
+            .map_err(self.request_error("/volumes/{name}"))?;
+        Ok(())
+    }
+
+    async fn list_networks(&self) -> Result<Vec<Network>, DockerError> {
+        let networks = self
+            .docker
+            .list_networks(None::<ListNetworksOptions<String>>)
+            .await
+            .map_err(self.request_error("/networks"))?;
+        BollardRuntime::convert("/networks", networks)
+    }
+
+    async fn create_network(&self, network: &CreateNetwork) -> Result<String, DockerError> {
+        let created = self
+            .docker
+            .create_network(CreateNetworkOptions {
+                name: network.name.as_str(),
+                driver: network.driver.as_str(),
+                labels: network
+                    .labels
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect(),
+                ..Default::default()
+            })
+            .await
+            .map_err(self.request_error("/networks/create"))?;
+
+        created.id.ok_or(DockerError::MalformedResponse(format!(
+            "No id returned creating network {}",
+            network.name
+        )))
+    }
+
+    async fn connect_network(
+        &self,
+        network: &str,
+        container: &str,
+        aliases: &[String],
+    ) -> Result<(), DockerError> {
+        self.docker
+            .connect_network(
+                network,
+                ConnectNetworkOptions {
+                    container,
+                    endpoint_config: bollard::models::EndpointSettings {
+                        aliases: Some(aliases.to_owned()),
+                        ..Default::default()
+                    },
+                },
+            )
+            .await
+            .map_err(self.request_error("/networks/{id}/connect"))?;
+        Ok(())
+    }
+
+    async fn exec(&self, id: &str, command: &[String]) -> Result<ExecResult, DockerError> {
+        let exec = self
+            .docker
+            .create_exec(
+                id,
+                CreateExecOptions {
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    tty: Some(false),
+                    cmd: Some(command.to_owned()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(self.request_error("/containers/{id}/exec"))?;
+
+        let mut captured = ExecOutput::default();
+        if let StartExecResults::Attached { mut output, .. } = self
+            .docker
+            .start_exec(&exec.id, None)
+            .await
+            .map_err(self.request_error("/exec/{id}/start"))?
+        {
+            while let Some(chunk) = output.next().await {
+                match chunk.map_err(self.request_error("/exec/{id}/start"))? {
+                    LogOutput::StdErr { message } => captured.push(LogSource::Stderr, &message),
+                    chunk => captured.push(LogSource::Stdout, &chunk.into_bytes()),
+                }
+            }
+        }
+
+        let inspection = self
+            .docker
+            .inspect_exec(&exec.id)
+            .await
+            .map_err(self.request_error("/exec/{id}/json"))?;
+
+        Ok(captured.finish(inspection.exit_code))
+    }
+
+    async fn get_logs(
+        &self,
+        id: &str,
+        tail: Option<usize>,
+        since: Option<i64>,
+        follow: bool,
+    ) -> Result<LogChunks, DockerError> {
+        let chunks = self
+            .docker
+            .logs(
+                id,
+                Some(LogsOptions {
+                    follow,
+                    stdout: true,
+                    stderr: true,
+                    since: since.unwrap_or(0),
+                    tail: tail.map(|t| t.to_string()).unwrap_or("all".into()),
+                    ..Default::default()
+                }),
+            )
+            .map_ok(BollardRuntime::log_chunk)
+            .map_err(DockerError::from);
+
+        Ok(chunks.boxed())
+    }
+
+    async fn attach(&self, id: &str) -> Result<Attached, DockerError> {
+        let attached = self
+            .docker
+            .attach_container(
+                id,
+                Some(AttachContainerOptions::<String> {
+                    stdin: Some(true),
+                    stdout: Some(true),
+                    stderr: Some(true),
+                    stream: Some(true),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .map_err(self.request_error("/containers/{id}/attach"))?;
+
+        Ok(Attached {
+            output: attached
+                .output
+                .map_ok(BollardRuntime::log_chunk)
+                .map_err(DockerError::from)
+                .boxed(),
+            input: attached.input,
+        })
+    }
+
+    async fn run_container(
+        &self,
+        name: &str,
+        image: &str,
+        args: Vec<&str>,
+    ) -> Result<(), DockerError> {
+        let create = run_args::parse(&args, image).map_err(DockerError::InvalidRequest)?;
+        let config: Config<String> = serde_json::from_value(serde_json::to_value(&create)?)?;
+
+        let created = self
+            .docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name,
+                    platform: None,
+                }),
+                config,
+            )
+            .await
+            .map_err(self.request_error("/containers/create"))?;
+        for warning in created.warnings.iter() {
+            println!("Warning creating container {}: {}", name, warning);
+        }
+
+        self.start(&created.id).await
+    }
+
+    async fn start(&self, id: &str) -> Result<(), DockerError> {
+        self.docker
+            .start_container(id, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(self.request_error("/containers/{id}/start"))?;
+        Ok(())
+    }
+
+    async fn stop_running_container(&self, id: &str) -> Result<(), DockerError> {
+        let inspection = self.inspect_running_container(id).await?;
+        if inspection.state.running == false {
+            // Already stopped
+            return Ok(());
+        }
+
+        match self
+            .docker
+            .stop_container(id, Some(StopContainerOptions { t: 5 }))
+            .await
+        {
+            // 304 if it stopped between the inspect and the stop
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 304, ..
+            }) => Ok(()),
+            result => Ok(result.map_err(self.request_error("/containers/{id}/stop"))?),
+        }
+    }
+
+    async fn restart_container(&self, id: &str, timeout: Option<u64>) -> Result<(), DockerError> {
+        let options = timeout.map(|t| RestartContainerOptions { t: t as isize });
+        self.docker
+            .restart_container(id, options)
+            .await
+            .map_err(self.request_error("/containers/{id}/restart"))?;
+        Ok(())
+    }
+
+    async fn wait_container(&self, id: &str) -> Result<i64, DockerError> {
+        let result = self
+            .docker
+            .wait_container(
+                id,
+                Some(WaitContainerOptions {
+                    condition: "not-running",
+                }),
+            )
+            .next()
+            .await
+            .ok_or(DockerError::MalformedResponse(format!(
+                "No wait response for {}",
+                id
+            )))?;
+
+        // bollard returns a non zero exit code as an error
+        match result {
+            Ok(result) => Ok(result.status_code),
+            Err(bollard::errors::Error::DockerContainerWaitError { error, code }) => {
+                match error.is_empty() {
+                    true => Ok(code),
+                    false => Err(DockerError::Other(format!(
+                        "Error waiting for {}: {}",
+                        id, error
+                    ))),
+                }
+            }
+            Err(e) => Err(self.request_error("/containers/{id}/wait")(e)),
+        }
+    }
+
+    async fn kill_container(&self, id: &str, signal: &str) -> Result<(), DockerError> {
+        self.docker
+            .kill_container(id, Some(KillContainerOptions { signal }))
+            .await
+            .map_err(self.request_error("/containers/{id}/kill"))?;
+        Ok(())
+    }
+
+    async fn rename_container(&self, id: &str, name: &str) -> Result<(), DockerError> {
+        self.docker
+            .rename_container(id, RenameContainerOptions { name })
+            .await
+            .map_err(self.request_error("/containers/{id}/rename"))?;
+        Ok(())
+    }
+
+    async fn set_paused(&self, id: &str, paused: bool) -> Result<(), DockerError> {
+        match paused {
+            true => self
+                .docker
+                .pause_container(id)
+                .await
+                .map_err(self.request_error("/containers/{id}/pause"))?,
+            false => self
+                .docker
+                .unpause_container(id)
+                .await
+                .map_err(self.request_error("/containers/{id}/unpause"))?,
+        }
+        Ok(())
+    }
+
+    async fn update_resources(
+        &self,
+        id: &str,
+        update: &UpdateContainer,
+    ) -> Result<(), DockerError> {
+        self.docker
+            .update_container(
+                id,
+                UpdateContainerOptions::<String> {
+                    memory: update.memory,
+                    memory_swap: update.memory_swap,
+                    nano_cp_us: update.nano_cpus,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(self.request_error("/containers/{id}/update"))?;
+        Ok(())
+    }
+
+    async fn get_archive(&self, id: &str, path: &str, filename: &str) -> Result<(), DockerError> {
+        let client = self.long_client();
+        let mut chunks =
+            client.download_from_container(id, Some(DownloadFromContainerOptions { path }));
+
+        let mut file = tokio::fs::File::create(filename).await?;
+        while let Some(chunk) = chunks.next().await {
+            file.write_all(&chunk.map_err(self.long_error("/containers/{id}/archive"))?)
+                .await?;
+        }
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    async fn put_archive(
+        &self,
+        id: &str,
+        path: &str,
+        archive: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), DockerError> {
+        // As with load_container_image_stream, bollard needs the whole archive up front
+        let mut buffer = vec![];
+        archive.read_to_end(&mut buffer).await?;
+
+        self.long_client()
+            .upload_to_container(
+                id,
+                Some(UploadToContainerOptions {
+                    path,
+                    no_overwrite_dir_non_dir: "false",
+                }),
+                buffer.into(),
+            )
+            .await
+            .map_err(self.long_error("/containers/{id}/archive"))?;
+        Ok(())
+    }
+
+    async fn remove_stopped_container(&self, id: &str) -> Result<(), DockerError> {
+        self.docker
+            .remove_container(id, None)
+            .await
+            .map_err(self.request_error("/containers/{id}"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::docker_structs::SystemInfo;
+
+/// Docker's guide to rootless mode, including how to enable resource limits
+const ROOTLESS_DOCS: &str = "https://docs.docker.com/engine/security/rootless/";
+
+/// Which resource limits the docker engine can apply on this host
+///
+/// Rootless docker can only apply limits on cgroup v2 hosts, and then only for the cgroup
+/// controllers systemd delegates to the user. Docker silently drops limits it cannot apply,
+/// so they are checked here to report why instead
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Capabilities {
+    pub rootless: bool,
+    /// "1" or "2", None if docker does not report it
+    pub cgroup_version: Option<String>,
+    pub memory_limit: bool,
+    pub swap_limit: bool,
+    pub cpu_limit: bool,
+    pub pids_limit: bool,
+}
+
+impl Capabilities {
+    pub fn from_info(info: &SystemInfo) -> Self {
+        Capabilities {
+            rootless: info.security_options.iter().any(|o| o == "name=rootless"),
+            cgroup_version: info
+                .cgroup_version
+                .clone()
+                .filter(|v| v.is_empty() == false),
+            memory_limit: info.memory_limit,
+            swap_limit: info.swap_limit,
+            cpu_limit: info.cpu_cfs_quota,
+            pids_limit: info.pids_limit,
+        }
+    }
+
+    /// A line for each limit docker cannot apply, reported at startup
+    pub fn warnings(&self) -> Vec<String> {
+        [
+            (self.memory_limit, "memory"),
+            (self.swap_limit, "swap"),
+            (self.cpu_limit, "cpu"),
+            (self.pids_limit, "pids"),
+        ]
+        .iter()
+        .filter(|(available, _)| *available == false)
+        .map(|(_, limit)| format!("docker cannot apply {} limits{}", limit, self.hint()))
+        .collect()
+    }
+
+    /// Why the memory and/or cpu limits asked for cannot be applied, None if they can
+    pub fn unsupported_limits(&self, memory: bool, cpus: bool) -> Option<String> {
+        let missing: Vec<&str> = [
+            (memory && self.memory_limit == false, "memory"),
+            (cpus && self.cpu_limit == false, "cpu"),
+        ]
+        .iter()
+        .filter(|(missing, _)| *missing)
+        .map(|(_, limit)| *limit)
+        .collect();
+
+        if missing.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "docker cannot apply {} limits on this host{}",
+            missing.join(" or "),
+            self.hint()
+        ))
+    }
+
+    /// What rootless docker needs for limits to work
+    fn hint(&self) -> String {
+        match (self.rootless, self.cgroup_version.as_deref()) {
+            (true, Some("2")) => format!(
+                ", rootless docker needs the cgroup controllers delegated to the user (see {})",
+                ROOTLESS_DOCS
+            ),
+            (true, _) => format!(", rootless docker needs cgroup v2 (see {})", ROOTLESS_DOCS),
+            _ => String::new(),
+        }
+    }
+}
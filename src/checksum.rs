@@ -0,0 +1,254 @@
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use ring::digest::{Context as Digest, SHA256};
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+/// Header holding the hex SHA-256 of an uploaded tarball, as printed by sha256sum
+pub const CHECKSUM_HEADER: &str = "X-Checksum-Sha256";
+
+/// Size of the reads from the upload
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Data at the end of the upload held back until it is verified, far more than the end of
+/// archive marker of a tarball so docker cannot finish a load before the check
+const HELD_SIZE: usize = 64 * 1024;
+
+/// The SHA-256 the client expects its upload to have, if it sent one. A header which is not
+/// 64 hex characters is rejected with 400 Bad Request
+pub struct Checksum(pub Option<[u8; 32]>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Checksum {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match request.headers().get_one(CHECKSUM_HEADER) {
+            Some(hex) => match parse_hex(hex.trim()) {
+                Some(digest) => Outcome::Success(Checksum(Some(digest))),
+                None => Outcome::Error((Status::BadRequest, ())),
+            },
+            None => Outcome::Success(Checksum(None)),
+        }
+    }
+}
+
+/// Why an upload does not match its checksum
+pub fn mismatch_message(expected: &[u8; 32], actual: &[u8]) -> String {
+    format!(
+        "SHA-256 of the upload is {} but {} was expected",
+        hex(actual),
+        hex(expected)
+    )
+}
+
+/// Check a file has the expected SHA-256, the error describes the mismatch
+pub async fn verify_file(path: &Path, expected: &[u8; 32]) -> io::Result<Result<(), String>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut digest = Digest::new(&SHA256);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        digest.update(&buffer[..read]);
+    }
+
+    let actual = digest.finish();
+    match actual.as_ref() == expected {
+        true => Ok(Ok(())),
+        false => Ok(Err(mismatch_message(expected, actual.as_ref()))),
+    }
+}
+
+/// Passes an upload through while hashing it. The last HELD_SIZE bytes are held back until
+/// the end of the upload, and are replaced by an error if the checksum does not match, so
+/// docker never sees the end of a corrupt tarball and abandons the load
+pub struct VerifyingReader<'a> {
+    inner: &'a mut (dyn AsyncRead + Send + Unpin),
+    expected: [u8; 32],
+    digest: Digest,
+    buffer: Vec<u8>,
+    /// Data read but not yet verified
+    held: Vec<u8>,
+    /// Data released to the reader, and how much of it has been read
+    released: Vec<u8>,
+    position: usize,
+    finished: bool,
+    /// Set when the upload has been read and did not match
+    mismatch: Option<String>,
+}
+
+impl<'a> VerifyingReader<'a> {
+    pub fn new(inner: &'a mut (dyn AsyncRead + Send + Unpin), expected: [u8; 32]) -> Self {
+        VerifyingReader {
+            inner,
+            expected,
+            digest: Digest::new(&SHA256),
+            buffer: vec![0u8; BUFFER_SIZE],
+            held: vec![],
+            released: vec![],
+            position: 0,
+            finished: false,
+            mismatch: None,
+        }
+    }
+
+    /// Why the upload was rejected, None if it matched or has not been read to the end
+    pub fn mismatch(&self) -> Option<&str> {
+        self.mismatch.as_deref()
+    }
+}
+
+impl AsyncRead for VerifyingReader<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.position < this.released.len() {
+                let count = buf.remaining().min(this.released.len() - this.position);
+                buf.put_slice(&this.released[this.position..this.position + count]);
+                this.position += count;
+                return Poll::Ready(Ok(()));
+            }
+            if this.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut read = ReadBuf::new(&mut this.buffer);
+            match Pin::new(&mut *this.inner).poll_read(cx, &mut read) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Ready(Ok(())) => {}
+            }
+
+            let data = read.filled();
+            this.position = 0;
+            if data.is_empty() {
+                // End of the upload, the held data is only released if the checksum matches
+                this.finished = true;
+                let actual = this.digest.clone().finish();
+                if actual.as_ref() != this.expected {
+                    let message = mismatch_message(&this.expected, actual.as_ref());
+                    this.mismatch = Some(message.clone());
+                    this.released.clear();
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, message)));
+                }
+                this.released = std::mem::take(&mut this.held);
+            } else {
+                this.digest.update(data);
+                this.held.extend_from_slice(data);
+                let release = this.held.len().saturating_sub(HELD_SIZE);
+                this.released = this.held.drain(..release).collect();
+            }
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<[u8; 32]> {
+    // from_str_radix alone would accept a sign, i.e. "+f"
+    if hex.len() != 64 || hex.chars().all(|c| c.is_ascii_hexdigit()) == false {
+        return None;
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::digest::digest;
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        digest(&SHA256, data).as_ref().try_into().unwrap()
+    }
+
+    fn upload(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Everything the reader released, and the error it ended with
+    async fn read_all(data: &[u8], expected: [u8; 32]) -> (Vec<u8>, Option<io::Error>, bool) {
+        let mut inner = data;
+        let mut reader = VerifyingReader::new(&mut inner, expected);
+        let mut output = vec![];
+        let mut buffer = vec![0u8; 4096];
+        let error = loop {
+            match reader.read(&mut buffer).await {
+                Ok(0) => break None,
+                Ok(read) => output.extend_from_slice(&buffer[..read]),
+                Err(e) => break Some(e),
+            }
+        };
+        (output, error, reader.mismatch().is_some())
+    }
+
+    #[rocket::async_test]
+    async fn matching_upload_passes_through() {
+        let data = upload(3 * BUFFER_SIZE + 123);
+        let (output, error, mismatch) = read_all(&data, sha256(&data)).await;
+        assert!(error.is_none());
+        assert!(mismatch == false);
+        assert_eq!(output, data);
+    }
+
+    #[rocket::async_test]
+    async fn mismatch_holds_back_the_end() {
+        let data = upload(3 * BUFFER_SIZE + 123);
+        let (output, error, mismatch) = read_all(&data, sha256(b"something else")).await;
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::InvalidData);
+        assert!(mismatch);
+        assert_eq!(output.len(), data.len() - HELD_SIZE);
+        assert_eq!(output, data[..data.len() - HELD_SIZE]);
+    }
+
+    #[rocket::async_test]
+    async fn short_uploads() {
+        for size in [0, 1, HELD_SIZE - 1, HELD_SIZE] {
+            let data = upload(size);
+            let (output, error, _) = read_all(&data, sha256(&data)).await;
+            assert!(error.is_none());
+            assert_eq!(output, data);
+
+            // Nothing of an upload shorter than the held data is released
+            let (output, error, mismatch) = read_all(&data, sha256(b"something else")).await;
+            assert!(error.is_some());
+            assert!(mismatch);
+            assert!(output.is_empty());
+        }
+    }
+
+    #[test]
+    fn hex_checksums() {
+        let data = b"upload";
+        let hex = hex(&sha256(data));
+        assert_eq!(parse_hex(&hex), Some(sha256(data)));
+        assert_eq!(parse_hex(&hex.to_uppercase()), Some(sha256(data)));
+
+        assert_eq!(parse_hex(""), None);
+        assert_eq!(parse_hex(&hex[..62]), None);
+        assert_eq!(parse_hex(&format!("{}00", hex)), None);
+        assert_eq!(parse_hex(&format!("+f{}", &hex[2..])), None);
+        assert_eq!(parse_hex(&format!("{}-1", &hex[..62])), None);
+        assert_eq!(parse_hex(&format!("{}zz", &hex[..62])), None);
+        // 64 bytes but fewer characters, slicing must not split a character
+        assert_eq!(parse_hex(&format!("{}é", &hex[..62])), None);
+    }
+}
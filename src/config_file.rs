@@ -1,53 +1,524 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::authorization::Action;
+use crate::bearer::{BearerToken, JwtConfig, TokenAuth};
+use crate::docker_client::{DockerSocket, PoolConfig, Retry, Timeouts, TlsFiles};
+use crate::docker_context;
+use crate::docker_structs::AuthConfig;
+use crate::download::Proxy;
+use crate::run_args;
+use crate::webhooks::Webhook;
 
 #[derive(Debug, Deserialize)]
 pub struct EDConfig {
     pub docker_socket: Option<String>,
+    pub docker_tls_cert: Option<String>,
+    pub docker_tls_key: Option<String>,
+    pub docker_tls_ca: Option<String>,
+    /// Seconds an unused connection to docker is kept open, 0 to keep it indefinitely
+    pub docker_pool_idle_timeout: Option<u64>,
+    pub docker_pool_max_idle: Option<usize>,
+    /// Seconds between TCP keepalives on tcp:// docker sockets
+    pub docker_keepalive: Option<u64>,
+    /// Seconds docker may take to respond to a request
+    pub docker_timeout: Option<u64>,
+    /// Seconds allowed for a whole image load, pull or export
+    pub docker_long_timeout: Option<u64>,
+    /// Attempts for docker requests which fail with a transient error, 1 disables retries
+    pub docker_retries: Option<u32>,
+    /// Milliseconds before the first retry, doubled for each further retry
+    pub docker_retry_backoff: Option<u64>,
     pub container_prefix: Option<String>,
     pub deployments: Vec<Deployment>,
     pub tls_certs: Option<String>,
     pub tls_key: Option<String>,
     pub mututal_tls_ca_certs: Option<String>,
+    pub mutual_tls_mandatory: Option<bool>,
+    pub authorization: Option<Vec<AuthorizationRule>>,
+    /// Static bearer tokens, as an alternative to client certificates
+    pub tokens: Option<Vec<BearerToken>>,
+    /// Accept JWTs signed with the configured key as bearer tokens
+    pub jwt: Option<JwtConfig>,
+    pub refresh_interval: Option<u64>,
+    pub refresh_concurrency: Option<usize>,
+    pub protect_tags: Option<bool>,
+    /// Remove the image a deployment was using once a new image replaces it
+    pub prune_images: Option<bool>,
+    /// Check docker has space for an image before it is loaded
+    pub disk_check: Option<bool>,
+    /// MiB of free space which must remain after an image is loaded
+    pub disk_headroom: Option<u64>,
+    pub min_healthy: Option<usize>,
+    pub read_only: Option<bool>,
+    pub registries: Option<BTreeMap<String, RegistryCredentials>>,
+    /// http proxy for url pulls, `http://[user:password@]host[:port]`
+    pub https_proxy: Option<String>,
+    /// Registries (host[:port]) tried in order before docker hub for docker hub images
+    pub registry_mirrors: Option<Vec<String>>,
+    /// Refuse to start when the docker engine does not meet the requirements, false to start
+    /// with warnings instead
+    pub engine_check: Option<bool>,
+    /// Docker must have live restore enabled
+    pub require_live_restore: Option<bool>,
+    /// Allow clients to attach to deployment consoles over a WebSocket
+    pub console: Option<bool>,
+    /// Serve Swagger UI at /v1/docs
+    pub swagger_ui: Option<bool>,
+    /// Directory partial chunked uploads are kept in
+    pub upload_dir: Option<String>,
+    /// Hours an unfinished chunked upload is kept after its last chunk
+    pub upload_expiry: Option<u64>,
+    /// File API keys issued through the API are kept in
+    pub api_keys_file: Option<String>,
+    /// File every request which changes state is recorded in, disabled if not set
+    pub audit_log: Option<String>,
+    /// Urls deployment changes are POSTed to
+    pub webhooks: Option<Vec<Webhook>>,
+    /// File the images loaded for each deployment are recorded in
+    pub history_file: Option<String>,
+    /// File deployments added through the API are kept in
+    pub deployments_file: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+#[schema(as = DeploymentConfig)]
 pub struct Deployment {
     pub name: String,
     pub args: Option<Vec<String>>,
+    /// A disabled deployment is stopped and cannot be started through the API
+    #[serde(default)]
+    pub disabled: bool,
+    /// Docker logging driver, the engine default is used if not set
+    pub log_driver: Option<String>,
+    pub log_options: Option<BTreeMap<String, String>>,
+    /// The deployment must be running and healthy for /v1/ready to succeed
+    #[serde(default)]
+    pub required_for_ready: bool,
+    /// Named volumes which are created, if missing, before the container is started
+    #[serde(default)]
+    pub volumes: Vec<VolumeConfig>,
+    /// User defined bridge networks the container is attached to, created if missing. The
+    /// container can be reached by the deployment name from other containers on the network
+    #[serde(default)]
+    pub networks: Vec<String>,
+    /// Deployments in the same group can be started, stopped and checked together
+    pub group: Option<String>,
+    /// Added through the API rather than the config file, kept in deployments_file
+    #[serde(skip)]
+    pub added: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct VolumeConfig {
+    pub name: String,
+    /// Volume driver, "local" if not set
+    pub driver: Option<String>,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+/// Logging drivers built in to the docker engine
+const LOG_DRIVERS: [&str; 12] = [
+    "none",
+    "local",
+    "json-file",
+    "syslog",
+    "journald",
+    "gelf",
+    "fluentd",
+    "awslogs",
+    "splunk",
+    "etwlogs",
+    "gcplogs",
+    "logentries",
+];
+
+/// Grants a client identity (certificate common name) the listed actions on the listed
+/// deployments, "*" may be used to match all deployments
+#[derive(Debug, Deserialize)]
+pub struct AuthorizationRule {
+    pub client: String,
+    pub deployments: Vec<String>,
+    pub actions: Vec<Action>,
+}
+
+/// Credentials for pulling from a private registry, keyed by registry host in the config
+///
+/// Either a username with a password (or a file containing the password, which is re-read on
+/// every pull so short lived tokens can be rotated by another process) or an identity token
+#[derive(Debug, Deserialize)]
+pub struct RegistryCredentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub password_file: Option<String>,
+    pub identity_token: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub config_file: PathBuf,
     pub docker_socket: String,
+    /// Set when connecting to a TLS protected docker daemon
+    pub docker_tls: Option<TlsFiles>,
+    pub docker_pool: PoolConfig,
+    pub docker_timeouts: Timeouts,
+    pub docker_retry: Retry,
     pub container_prefix: String,
-    pub deployments: Vec<Deployment>,
+    pub deployments: Deployments,
     pub tls_certs: String,
     pub tls_key: String,
     pub mutual_tls_ca_certs: String,
+    pub mutual_tls_mandatory: bool,
+    pub authorization: Vec<AuthorizationRule>,
+    /// Bearer token authentication, disabled unless tokens or jwt are configured
+    pub token_auth: TokenAuth,
+    pub refresh_interval: Option<Duration>,
+    pub refresh_concurrency: usize,
+    /// Refuse to retag over an existing deployment image unless forced
+    pub protect_tags: bool,
+    /// Remove replaced deployment images
+    pub prune_images: bool,
+    /// Bytes of free space which must remain after a load, None if the check is disabled
+    pub disk_headroom: Option<u64>,
+    /// Number of deployments which must be running and healthy for /v1/ready to succeed
+    pub min_healthy: usize,
+    /// Reject every request which would change container state
+    pub read_only: bool,
+    /// Registry credentials keyed by registry host
+    pub registries: BTreeMap<String, RegistryCredentials>,
+    /// Proxy url downloads are sent through, from the config or the HTTPS_PROXY environment
+    pub https_proxy: Option<Proxy>,
+    pub registry_mirrors: Vec<String>,
+    /// Refuse to start when the docker engine does not meet the requirements
+    pub engine_check: bool,
+    pub require_live_restore: bool,
+    /// Clients may attach to deployment consoles
+    pub console: bool,
+    pub swagger_ui: bool,
+    pub upload_dir: PathBuf,
+    pub upload_expiry: Duration,
+    pub api_keys_file: PathBuf,
+    pub audit_log: Option<PathBuf>,
+    pub webhooks: Vec<Webhook>,
+    pub history_file: PathBuf,
+    pub deployments_file: PathBuf,
+}
+
+/// The deployments from the config file followed by those added through the API, which can
+/// change while the daemon runs
+#[derive(Debug)]
+pub struct Deployments(watch::Sender<Arc<Vec<Deployment>>>);
+
+impl Deployments {
+    pub fn new(deployments: Vec<Deployment>) -> Self {
+        let (sender, _) = watch::channel(Arc::new(deployments));
+        Deployments(sender)
+    }
+
+    /// The deployments as they are now, later changes do not affect the returned list
+    pub fn get(&self) -> Arc<Vec<Deployment>> {
+        self.0.borrow().clone()
+    }
+
+    pub fn set(&self, deployments: Vec<Deployment>) {
+        self.0.send_replace(Arc::new(deployments));
+    }
+
+    /// Notified whenever the deployments change
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Vec<Deployment>>> {
+        self.0.subscribe()
+    }
+}
+
+/// Background refreshes closer together than this are not allowed
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+impl Config {
+    pub fn is_disabled(&self, name: &str) -> bool {
+        self.deployments
+            .get()
+            .iter()
+            .any(|d| d.name == name && d.disabled)
+    }
+
+    pub fn group(&self, name: &str) -> Option<String> {
+        self.deployments
+            .get()
+            .iter()
+            .find(|d| d.name == name)
+            .and_then(|d| d.group.to_owned())
+    }
+
+    /// The deployments in a group, in the order they are configured
+    pub fn group_members(&self, group: &str) -> Vec<String> {
+        self.deployments
+            .get()
+            .iter()
+            .filter(|d| d.group.as_deref() == Some(group))
+            .map(|d| d.name.to_owned())
+            .collect()
+    }
+
+    /// Credentials for the registry an image reference will be pulled from, if configured
+    pub fn registry_auth(&self, reference: &str) -> Result<Option<AuthConfig>, String> {
+        let registry = registry_host(reference);
+        let credentials = match self.registries.get(registry) {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let password = match &credentials.password_file {
+            Some(file) => Some(
+                std::fs::read_to_string(file)
+                    .map_err(|e| format!("Unable to read password_file ({}): {}", file, e))?
+                    .trim()
+                    .to_owned(),
+            ),
+            None => credentials.password.to_owned(),
+        };
+
+        Ok(Some(AuthConfig {
+            username: credentials.username.to_owned(),
+            password,
+            identitytoken: credentials.identity_token.to_owned(),
+            serveraddress: registry.to_owned(),
+        }))
+    }
+}
+
+impl Config {
+    /// The references to try, in order, to pull an image. Docker hub images are tried from
+    /// each mirror before docker hub itself, i.e. `nginx:latest` is pulled as
+    /// `mirror:5000/library/nginx:latest`
+    pub fn pull_references(&self, reference: &str) -> Vec<String> {
+        let mut references = vec![];
+        if registry_host(reference) == "docker.io" {
+            let path = reference
+                .strip_prefix("docker.io/")
+                .or(reference.strip_prefix("index.docker.io/"))
+                .unwrap_or(reference);
+            let path = match path.contains('/') {
+                true => path.to_owned(),
+                false => format!("library/{}", path),
+            };
+            for mirror in &self.registry_mirrors {
+                references.push(format!("{}/{}", mirror, path));
+            }
+        }
+        references.push(reference.to_owned());
+
+        references
+    }
+}
+
+/// The registry host of an image reference, references without one come from docker hub
+fn registry_host(reference: &str) -> &str {
+    match reference.split_once('/') {
+        Some((first, _)) if first.contains('.') || first.contains(':') || first == "localhost" => {
+            first
+        }
+        _ => "docker.io",
+    }
+}
+
+/// Socket of a docker engine running as root
+const ROOT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// The docker socket used when none is configured, the system socket or, when there is no
+/// system docker, the socket rootless docker creates in $XDG_RUNTIME_DIR
+fn default_docker_socket() -> String {
+    if PathBuf::from(ROOT_DOCKER_SOCKET).exists() == false {
+        if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+            let socket = PathBuf::from(runtime_dir).join("docker.sock");
+            if socket.exists() {
+                println!("Using rootless docker socket {}", socket.display());
+                return socket.to_string_lossy().into();
+            }
+        }
+    }
+
+    ROOT_DOCKER_SOCKET.into()
 }
 
 pub fn process_config_file(path: PathBuf) -> Result<Config, String> {
-    let config_file = std::fs::read_to_string(&path).unwrap();
-    let config: EDConfig = serde_json::from_str(&config_file).unwrap();
+    let config_file = std::fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "Error processing config file: unable to read {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let config: EDConfig = serde_json::from_str(&config_file)
+        .map_err(|e| format!("Error processing config file: {}", e))?;
 
-    let docker_socket = config
+    let mut docker_socket = config
         .docker_socket
         .to_owned()
-        .unwrap_or("/var/run/docker.socket".into());
+        .unwrap_or_else(default_docker_socket);
+
+    // A docker CLI context supplies the endpoint, and TLS files unless they are set here
+    let mut context_tls = None;
+    if let Some(name) = docker_socket.strip_prefix("context://") {
+        let (host, tls) = docker_context::resolve(name.trim_end_matches("/"))
+            .map_err(|e| format!("Error processing config file: {}", e))?;
+        println!("Using docker context '{}' ({})", name, host);
+        docker_socket = host;
+        context_tls = tls;
+    }
+
+    let docker_tls = match (
+        config.docker_tls_cert,
+        config.docker_tls_key,
+        config.docker_tls_ca,
+    ) {
+        (Some(cert), Some(key), Some(ca)) => Some(TlsFiles { cert, key, ca }),
+        (None, None, None) => context_tls,
+        _ => {
+            return Err(
+                "docker_tls_cert, docker_tls_key and docker_tls_ca must all be set to use TLS"
+                    .into(),
+            )
+        }
+    };
+
+    let defaults = PoolConfig::default();
+    let docker_pool = PoolConfig {
+        idle_timeout: match config.docker_pool_idle_timeout {
+            Some(0) => None,
+            Some(s) => Some(Duration::from_secs(s)),
+            None => defaults.idle_timeout,
+        },
+        max_idle: config.docker_pool_max_idle.unwrap_or(defaults.max_idle),
+        tcp_keepalive: config
+            .docker_keepalive
+            .map(Duration::from_secs)
+            .or(defaults.tcp_keepalive),
+    };
+
+    let defaults = Timeouts::default();
+    let docker_timeouts = Timeouts {
+        request: config
+            .docker_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.request),
+        long: config
+            .docker_long_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.long),
+    };
+
+    let defaults = Retry::default();
+    let docker_retry = Retry {
+        attempts: config.docker_retries.unwrap_or(defaults.attempts).max(1),
+        backoff: config
+            .docker_retry_backoff
+            .map(Duration::from_millis)
+            .unwrap_or(defaults.backoff),
+    };
+
+    let webhooks = config.webhooks.unwrap_or_default();
+    for webhook in &webhooks {
+        webhook
+            .validate()
+            .map_err(|e| format!("Error processing config file: {}", e))?;
+    }
+
+    let token_auth = TokenAuth::new(config.tokens.unwrap_or_default(), config.jwt)
+        .map_err(|e| format!("Error processing config file: {}", e))?;
+
+    let deployments_file = PathBuf::from(
+        config
+            .deployments_file
+            .unwrap_or("/var/lib/edd/deployments.json".into()),
+    );
+    let mut deployments = config.deployments;
+    deployments.extend(
+        read_deployments_file(&deployments_file)
+            .map_err(|e| format!("Error processing config file: {}", e))?,
+    );
 
     let complete = Config {
         config_file: path,
         docker_socket,
+        docker_tls,
+        docker_pool,
+        docker_timeouts,
+        docker_retry,
         container_prefix: format!("/{}", config.container_prefix.unwrap_or("ed_".into())),
-        deployments: config.deployments,
+        deployments: Deployments::new(deployments),
         tls_certs: config.tls_certs.unwrap_or("/etc/edd/server.crt".into()),
         tls_key: config.tls_key.unwrap_or("/etc/edd/server.key".into()),
         mutual_tls_ca_certs: config
             .mututal_tls_ca_certs
             .unwrap_or("/etc/edd/ca.crt".into()),
+        mutual_tls_mandatory: config.mutual_tls_mandatory.unwrap_or(false),
+        authorization: config.authorization.unwrap_or_default(),
+        token_auth,
+        refresh_interval: config
+            .refresh_interval
+            .map(|s| Duration::from_secs(s).max(MIN_REFRESH_INTERVAL)),
+        refresh_concurrency: config.refresh_concurrency.unwrap_or(4).max(1),
+        protect_tags: config.protect_tags.unwrap_or(false),
+        prune_images: config.prune_images.unwrap_or(true),
+        engine_check: config.engine_check.unwrap_or(true),
+        require_live_restore: config.require_live_restore.unwrap_or(false),
+        console: config.console.unwrap_or(false),
+        swagger_ui: config.swagger_ui.unwrap_or(false),
+        upload_dir: config
+            .upload_dir
+            .map(PathBuf::from)
+            .unwrap_or(std::env::temp_dir().join("edd-uploads")),
+        upload_expiry: Duration::from_secs(config.upload_expiry.unwrap_or(24) * 60 * 60),
+        api_keys_file: PathBuf::from(
+            config
+                .api_keys_file
+                .unwrap_or("/etc/edd/apikeys.json".into()),
+        ),
+        audit_log: config.audit_log.map(PathBuf::from),
+        webhooks,
+        history_file: PathBuf::from(
+            config
+                .history_file
+                .unwrap_or("/var/lib/edd/history.json".into()),
+        ),
+        deployments_file,
+        disk_headroom: match config.disk_check.unwrap_or(true) {
+            true => Some(config.disk_headroom.unwrap_or(256) * 1024 * 1024),
+            false => None,
+        },
+        min_healthy: config.min_healthy.unwrap_or(0),
+        read_only: config.read_only.unwrap_or(false),
+        registries: config.registries.unwrap_or_default(),
+        https_proxy: match config
+            .https_proxy
+            .or(std::env::var("HTTPS_PROXY").ok())
+            .or(std::env::var("https_proxy").ok())
+            .filter(|p| p.is_empty() == false)
+        {
+            Some(proxy) => Some(
+                Proxy::parse(&proxy).map_err(|e| format!("Error processing config file: {}", e))?,
+            ),
+            None => None,
+        },
+        registry_mirrors: config
+            .registry_mirrors
+            .unwrap_or_default()
+            .iter()
+            .map(|m| {
+                m.trim_start_matches("https://")
+                    .trim_start_matches("http://")
+                    .trim_end_matches('/')
+                    .to_owned()
+            })
+            .collect(),
     };
 
     check_config(&complete).map_err(|e| format!("Error processing config file: {}", e))?;
@@ -55,7 +526,73 @@ pub fn process_config_file(path: PathBuf) -> Result<Config, String> {
     Ok(complete)
 }
 
+/// The deployments added through the API, the file does not need to exist until one is added
+fn read_deployments_file(path: &Path) -> Result<Vec<Deployment>, String> {
+    let mut deployments: Vec<Deployment> = match std::fs::read(path) {
+        Ok(json) => serde_json::from_slice(&json).map_err(|e| {
+            format!(
+                "Unable to read deployments_file ({}): {}",
+                path.display(),
+                e
+            )
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => {
+            return Err(format!(
+                "Unable to read deployments_file ({}): {}",
+                path.display(),
+                e
+            ))
+        }
+    };
+    for deployment in &mut deployments {
+        deployment.added = true;
+    }
+
+    Ok(deployments)
+}
+
+/// Write the deployments added through the API to a temporary file and rename it over the
+/// deployments file, so the file is never left part written
+pub fn save_deployments_file(config: &Config, deployments: &[Deployment]) -> io::Result<()> {
+    let added: Vec<&Deployment> = deployments.iter().filter(|d| d.added).collect();
+    if let Some(dir) = config.deployments_file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temporary = config.deployments_file.with_extension("json.tmp");
+    std::fs::write(&temporary, serde_json::to_vec_pretty(&added)?)?;
+    std::fs::rename(&temporary, &config.deployments_file)
+}
+
 fn check_config(config: &Config) -> Result<(), String> {
+    // An empty prefix would match (and adopt) every container on the host
+    let prefix = config.container_prefix.trim_start_matches("/");
+    if prefix.is_empty() {
+        return Err("container_prefix must not be empty".into());
+    }
+
+    // Docker container names must start with an alphanumeric and only contain [a-zA-Z0-9_.-]
+    let valid_characters = prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if valid_characters == false || prefix.starts_with(|c: char| c.is_ascii_alphanumeric()) == false
+    {
+        return Err(format!(
+            "container_prefix ({}) is not a valid docker container name prefix",
+            prefix
+        ));
+    }
+
+    // A mirror without a . or : would be taken by docker as a docker hub repository
+    for mirror in &config.registry_mirrors {
+        if mirror.is_empty() || registry_host(&format!("{}/image", mirror)) != mirror {
+            return Err(format!(
+                "registry_mirrors entry ({}) is not a registry host[:port]",
+                mirror
+            ));
+        }
+    }
+
     if PathBuf::from(&config.tls_certs).exists() == false {
         return Err(format!(
             "tls_certs file ({}) does not exist",
@@ -74,5 +611,170 @@ fn check_config(config: &Config) -> Result<(), String> {
         ));
     }
 
+    let socket = DockerSocket::parse(&config.docker_socket)?;
+
+    if let Some(tls) = &config.docker_tls {
+        if matches!(socket, DockerSocket::Tcp(_)) == false {
+            return Err(format!(
+                "docker_tls_cert, docker_tls_key and docker_tls_ca can only be used with a tcp:// docker_socket ({})",
+                config.docker_socket
+            ));
+        }
+        for file in [&tls.cert, &tls.key, &tls.ca] {
+            if PathBuf::from(file).exists() == false {
+                return Err(format!("docker tls file ({}) does not exist", file));
+            }
+        }
+    }
+
+    for (registry, credentials) in &config.registries {
+        let has_password = credentials.password.is_some() || credentials.password_file.is_some();
+        if credentials.identity_token.is_none()
+            && (credentials.username.is_none() || has_password == false)
+        {
+            return Err(format!(
+                "registry {} needs a username and password (or password_file), or an identity_token",
+                registry
+            ));
+        }
+        if credentials.password.is_some() && credentials.password_file.is_some() {
+            return Err(format!(
+                "registry {} has both password and password_file set",
+                registry
+            ));
+        }
+    }
+
+    let deployments = config.deployments.get();
+    for (index, deployment) in deployments.iter().enumerate() {
+        check_deployment(deployment)?;
+        if deployments[..index]
+            .iter()
+            .any(|d| d.name == deployment.name)
+        {
+            return Err(format!(
+                "deployment '{}' is configured more than once",
+                deployment.name
+            ));
+        }
+    }
+
     Ok(())
 }
+
+/// Checks a deployment from the config file, or one being added through the API
+pub fn check_deployment(deployment: &Deployment) -> Result<(), String> {
+    // The name is used in the container and image names
+    let valid_name = deployment
+        .name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if deployment.name.is_empty() || valid_name == false {
+        return Err(format!(
+            "deployment name ({}) may only contain [a-zA-Z0-9_.-]",
+            deployment.name
+        ));
+    }
+
+    let args: Vec<&str> = deployment
+        .args
+        .iter()
+        .flatten()
+        .map(|a| a.as_str())
+        .collect();
+    if let Err(e) = run_args::parse(&args, "") {
+        return Err(format!(
+            "args for deployment '{}' are not supported: {}",
+            deployment.name, e
+        ));
+    }
+    for volume in &deployment.volumes {
+        if valid_object_name(&volume.name) == false {
+            return Err(format!(
+                "volume name ({}) for deployment '{}' is not valid",
+                volume.name, deployment.name
+            ));
+        }
+    }
+    for network in &deployment.networks {
+        if valid_object_name(network) == false {
+            return Err(format!(
+                "network name ({}) for deployment '{}' is not valid",
+                network, deployment.name
+            ));
+        }
+    }
+    if let Some(driver) = &deployment.log_driver {
+        if LOG_DRIVERS.contains(&driver.as_str()) == false {
+            return Err(format!(
+                "log_driver ({}) for deployment '{}' is not a known docker logging driver",
+                driver, deployment.name
+            ));
+        }
+    }
+    if deployment.log_options.is_some() && deployment.log_driver.is_none() {
+        return Err(format!(
+            "log_options for deployment '{}' require a log_driver",
+            deployment.name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Docker volume and network names must be at least 2 characters, start with an
+/// alphanumeric and only contain [a-zA-Z0-9_.-]
+fn valid_object_name(name: &str) -> bool {
+    name.len() > 1
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static CONFIGS: AtomicU64 = AtomicU64::new(0);
+
+    /// A Config processed from a minimal config file with the fields in extra added. The files
+    /// it must refer to only exist while it is processed
+    pub fn test_config(extra: serde_json::Value) -> Config {
+        let dir = std::env::temp_dir().join(format!(
+            "edd-config-test-{}-{}",
+            CONFIGS.fetch_add(1, Ordering::Relaxed),
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let placeholder = dir.join("placeholder");
+        std::fs::write(&placeholder, "").unwrap();
+
+        let mut file = serde_json::json!({
+            "deployments": [],
+            "docker_socket": "unix:///var/run/docker.sock",
+            "tls_certs": placeholder,
+            "tls_key": placeholder,
+            "mututal_tls_ca_certs": placeholder,
+            "deployments_file": dir.join("deployments.json"),
+            "https_proxy": "",
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            file[key] = value.clone();
+        }
+        std::fs::write(dir.join("config.json"), file.to_string()).unwrap();
+
+        let config = process_config_file(dir.join("config.json"));
+        std::fs::remove_dir_all(&dir).ok();
+        config.unwrap()
+    }
+
+    #[test]
+    fn minimal_config() {
+        let config = test_config(serde_json::json!({"container_prefix": "edge_"}));
+        assert_eq!(config.container_prefix, "/edge_");
+        assert!(config.authorization.is_empty());
+        assert!(config.token_auth.enabled() == false);
+    }
+}
@@ -1,174 +1,908 @@
-use core::panic;
-use std::{error::Error, io};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::future::Future;
+use std::io::BufReader;
 
+use base64::Engine;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
 use hyper::{body::Bytes, Body, Client, Request, Response};
-use hyperlocal::{UnixClientExt, UnixConnector, Uri};
+use hyper_rustls::HttpsConnector;
+#[cfg(unix)]
+use hyperlocal::UnixConnector;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
+use crate::docker_error::DockerError;
 use crate::docker_structs::*;
+#[cfg(windows)]
+use crate::named_pipe::NamedPipeConnector;
+use crate::run_args;
+use crate::runtime::{
+    select_loaded_image, Attached, ContainerEvents, ContainerRuntime, ExecOutput, LogChunks,
+    Progress,
+};
+
+/// Size of the chunks an upload is read in while it is streamed to docker
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes read from the start of an image tarball to identify it, a tar header is 512 bytes
+const TARBALL_MAGIC_SIZE: usize = 512;
 
 /// Provides accessors for Docker API and Docker CLI functions
 
 pub struct DockerClient {
+    /// Socket path for unix sockets, pipe path for named pipes, host:port for tcp
     address: String,
-    _client: ClientType, // Future - remote sockets?
+    client: ClientType,
+    /// Keeps the ssh process forwarding the remote docker socket alive for ssh:// addresses
+    tunnel: Option<SshTunnel>,
+    timeouts: Timeouts,
+    retry: Retry,
 }
 
 enum ClientType {
+    #[cfg(unix)]
     Unix(Client<UnixConnector>),
+    #[cfg(windows)]
+    NamedPipe(Client<NamedPipeConnector>),
+    Tcp(Client<HttpConnector>),
+    Tls(Client<HttpsConnector<HttpConnector>>),
+}
+
+/// A parsed docker_socket value
+#[derive(Debug, Clone, PartialEq)]
+pub enum DockerSocket {
+    /// unix:///path/to/docker.sock, or a bare path
+    Unix(String),
+    /// tcp://host[:port] or http://host[:port], held as host:port
+    Tcp(String),
+    /// npipe:////./pipe/docker_engine, held as \\.\pipe\docker_engine
+    NamedPipe(String),
+    /// ssh://[user@]host[:port]
+    Ssh {
+        destination: String,
+        port: Option<u16>,
+    },
+}
+
+/// Port used for tcp:// sockets which do not give one, as with the docker CLI
+const DEFAULT_DOCKER_PORT: u16 = 2375;
+
+impl DockerSocket {
+    /// Parse a docker socket address, malformed values and unsupported schemes are an error
+    /// describing what is wrong so it can be reported at startup
+    pub fn parse(address: &str) -> Result<Self, String> {
+        let (scheme, rest) = match address.split_once("://") {
+            Some((scheme, rest)) => (scheme, rest),
+            None => ("unix", address),
+        };
+
+        match scheme {
+            "unix" => {
+                if rest.is_empty() {
+                    return Err("docker_socket unix socket path is empty".into());
+                }
+                Ok(DockerSocket::Unix(rest.to_owned()))
+            }
+            "npipe" => {
+                let path = rest.trim_start_matches('/');
+                if path.is_empty() {
+                    return Err("docker_socket named pipe path is empty".into());
+                }
+                Ok(DockerSocket::NamedPipe(format!(
+                    "\\\\{}",
+                    path.replace('/', "\\")
+                )))
+            }
+            "tcp" | "http" => {
+                let (host, port) = DockerSocket::host_and_port(rest.trim_end_matches("/"))?;
+                Ok(DockerSocket::Tcp(format!(
+                    "{}:{}",
+                    host,
+                    port.unwrap_or(DEFAULT_DOCKER_PORT)
+                )))
+            }
+            "ssh" => {
+                let (destination, port) =
+                    DockerSocket::host_and_port(rest.trim_end_matches("/"))?;
+                Ok(DockerSocket::Ssh {
+                    destination: destination.to_owned(),
+                    port,
+                })
+            }
+            _ => Err(format!(
+                "docker_socket scheme ({}) is not supported, use unix://, npipe://, tcp://, http:// or ssh://",
+                scheme
+            )),
+        }
+    }
+
+    /// Split host[:port], an IPv6 host must be in [] if a port is given
+    fn host_and_port(value: &str) -> Result<(&str, Option<u16>), String> {
+        if value.contains('/') {
+            return Err(format!("docker_socket ({}) must not contain a path", value));
+        }
+
+        let (host, port) = match value.rsplit_once(':') {
+            Some((host, port)) if host.starts_with('[') || host.contains(':') == false => {
+                let port = port
+                    .parse::<u16>()
+                    .map_err(|_| format!("docker_socket port ({}) is not valid", port))?;
+                (host, Some(port))
+            }
+            _ => (value, None),
+        };
+
+        if host.is_empty() || host.rsplit('@').next() == Some("") {
+            return Err(format!("docker_socket ({}) has no host", value));
+        }
+
+        Ok((host, port))
+    }
+}
+
+/// Client certificate, key and CA used to connect to a TLS protected docker daemon
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: String,
+    pub key: String,
+    pub ca: String,
+}
+
+/// Connection pool settings for the client, one client (and pool) is kept for the life of
+/// the DockerClient so connections to the daemon are reused between requests
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// How long an unused connection is kept open, None to keep it indefinitely
+    pub idle_timeout: Option<std::time::Duration>,
+    /// Maximum number of unused connections kept open
+    pub max_idle: usize,
+    /// TCP keepalive interval for tcp:// sockets, None to leave it disabled
+    pub tcp_keepalive: Option<std::time::Duration>,
+}
+
+impl Default for PoolConfig {
+    /// The hyper defaults
+    fn default() -> Self {
+        Self {
+            idle_timeout: Some(std::time::Duration::from_secs(90)),
+            max_idle: usize::MAX,
+            tcp_keepalive: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    fn builder(&self) -> hyper::client::Builder {
+        let mut builder = Client::builder();
+        builder
+            .pool_idle_timeout(self.idle_timeout)
+            .pool_max_idle_per_host(self.max_idle);
+        builder
+    }
+
+    fn http_connector(&self) -> HttpConnector {
+        let mut connector = HttpConnector::new();
+        connector.set_keepalive(self.tcp_keepalive);
+        connector
+    }
+}
+
+/// Limits on how long docker API calls may take, so a hung daemon does not hang a request
+/// (and the deployments it holds the manager for) forever
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    /// Time allowed for docker to respond to a request
+    pub request: std::time::Duration,
+    /// Time allowed for a whole image load, pull or save
+    pub long: std::time::Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            request: std::time::Duration::from_secs(60),
+            long: std::time::Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Retries for requests which fail with a transient error, i.e. the connection to docker is
+/// refused or reset while the daemon restarts. The delay doubles after each attempt
+#[derive(Debug, Clone)]
+pub struct Retry {
+    /// Total attempts, including the first
+    pub attempts: u32,
+    /// Delay before the first retry
+    pub backoff: std::time::Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: std::time::Duration::from_millis(200),
+        }
+    }
+}
+
+/// Remote docker socket forwarded to a local unix socket by an ssh child process, which is
+/// watched by the supervisor task and restarted if it exits
+struct SshTunnel {
+    supervisor: tokio::task::JoinHandle<()>,
+    socket: std::path::PathBuf,
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        // The ssh process is killed as the supervisor drops it
+        self.supervisor.abort();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+/// Socket used by the docker daemon on the remote end of an ssh:// address
+#[cfg(unix)]
+const REMOTE_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// How long to wait for ssh to create the forwarded socket
+#[cfg(unix)]
+const SSH_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Delay before restarting ssh after it exits, doubling up to SSH_RESTART_MAX while it keeps
+/// failing to connect
+#[cfg(unix)]
+const SSH_RESTART_MIN: std::time::Duration = std::time::Duration::from_secs(1);
+#[cfg(unix)]
+const SSH_RESTART_MAX: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Start ssh forwarding the remote docker socket to socket, once the socket appears
+#[cfg(unix)]
+async fn start_ssh(
+    destination: &str,
+    port: Option<u16>,
+    socket: &std::path::Path,
+) -> std::io::Result<tokio::process::Child> {
+    let _ = tokio::fs::remove_file(socket).await;
+
+    let mut command = tokio::process::Command::new("ssh");
+    command
+        .args(["-nNT", "-o", "ExitOnForwardFailure=yes"])
+        .args(["-o", "StreamLocalBindUnlink=yes"])
+        .arg("-L")
+        .arg(format!("{}:{}", socket.display(), REMOTE_DOCKER_SOCKET))
+        .kill_on_drop(true);
+    if let Some(port) = port {
+        command.arg("-p").arg(port.to_string());
+    }
+    let mut process = command.arg(destination).spawn()?;
+
+    // Wait for the forwarded socket to appear, or for ssh to give up
+    let start = std::time::Instant::now();
+    while tokio::fs::try_exists(socket).await? == false {
+        if let Some(status) = process.try_wait()? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                format!("ssh to {} exited with {}", destination, status),
+            ));
+        }
+        if start.elapsed() > SSH_CONNECT_TIMEOUT {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("Timed out connecting to {} with ssh", destination),
+            ));
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    Ok(process)
+}
+
+/// Restarts ssh whenever it exits (i.e. the connection dropped), requests fail until the
+/// tunnel is back
+#[cfg(unix)]
+async fn supervise_ssh(
+    mut process: tokio::process::Child,
+    destination: String,
+    port: Option<u16>,
+    socket: std::path::PathBuf,
+) {
+    loop {
+        match process.wait().await {
+            Ok(status) => println!("ssh to {} exited with {}", destination, status),
+            Err(e) => println!("Unable to wait for ssh to {}: {}", destination, e),
+        }
+
+        let mut backoff = SSH_RESTART_MIN;
+        process = loop {
+            tokio::time::sleep(backoff).await;
+            match start_ssh(&destination, port, &socket).await {
+                Ok(process) => break process,
+                Err(e) => println!("Unable to restart ssh to {}: {}", destination, e),
+            }
+            backoff = (backoff * 2).min(SSH_RESTART_MAX);
+        };
+        println!("ssh to {} restarted", destination);
+    }
 }
 
 impl DockerClient {
-    pub fn new(address: &str) -> Self {
-        let client = match DockerClient::get_uri_scheme(address) {
-            "unix" | "" => ClientType::Unix(Client::unix()),
+    /// Create a client for the given docker socket, either a unix socket path (optionally
+    /// prefixed with unix://), npipe:// on Windows or tcp://host:port. Use new_ssh for ssh://
+    /// sockets
+    pub fn new(address: &str, pool: &PoolConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (client, address) = match DockerSocket::parse(address)? {
+            #[cfg(unix)]
+            DockerSocket::Unix(path) => {
+                (ClientType::Unix(pool.builder().build(UnixConnector)), path)
+            }
+            #[cfg(not(unix))]
+            DockerSocket::Unix(_) => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "unix:// docker sockets are not supported on this platform, use npipe://",
+                )))
+            }
+            #[cfg(windows)]
+            DockerSocket::NamedPipe(path) => (
+                ClientType::NamedPipe(pool.builder().build(NamedPipeConnector::new(&path))),
+                path,
+            ),
+            #[cfg(not(windows))]
+            DockerSocket::NamedPipe(_) => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "npipe:// docker sockets are only supported on Windows",
+                )))
+            }
+            DockerSocket::Tcp(address) => (
+                ClientType::Tcp(pool.builder().build(pool.http_connector())),
+                address,
+            ),
+            DockerSocket::Ssh { .. } => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "ssh:// docker sockets must be opened with new_ssh",
+                )))
+            }
+        };
+
+        Ok(Self {
+            address,
+            client,
+            tunnel: None,
+            timeouts: Timeouts::default(),
+            retry: Retry::default(),
+        })
+    }
+
+    /// Replace the default timeouts
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Replace the default retry behaviour
+    pub fn with_retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Create a client for a remote docker daemon reached over ssh://user@host[:port]
+    ///
+    /// The system ssh binary is used to forward the remote docker socket to a local unix
+    /// socket so keys, known hosts and ssh config are handled the same way as the docker CLI.
+    /// ssh is restarted if it exits, and stopped when the client is dropped.
+    #[cfg(unix)]
+    pub async fn new_ssh(
+        address: &str,
+        pool: &PoolConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (destination, port) = match DockerSocket::parse(address)? {
+            DockerSocket::Ssh { destination, port } => (destination, port),
             _ => {
-                panic!("Not supported");
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Not an ssh:// docker socket",
+                )))
             }
         };
 
-        Self {
-            address: address.into(),
-            _client: client,
+        let socket = std::env::temp_dir().join(format!("edd-ssh-{}.sock", std::process::id()));
+        let process = start_ssh(&destination, port, &socket).await?;
+
+        let tunnel = SshTunnel {
+            supervisor: tokio::spawn(supervise_ssh(process, destination, port, socket.clone())),
+            socket,
+        };
+
+        Ok(Self {
+            address: tunnel.socket.to_string_lossy().into(),
+            client: ClientType::Unix(pool.builder().build(UnixConnector)),
+            tunnel: Some(tunnel),
+            timeouts: Timeouts::default(),
+            retry: Retry::default(),
+        })
+    }
+
+    /// The ssh tunnel forwards to a unix socket, which is not available on this platform
+    #[cfg(not(unix))]
+    pub async fn new_ssh(
+        _address: &str,
+        _pool: &PoolConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "ssh:// docker sockets are not supported on this platform",
+        )))
+    }
+
+    /// Create a client for a docker daemon listening on tcp://host:port protected with TLS,
+    /// the client certificate is used to authenticate with the daemon
+    pub fn new_tls(
+        address: &str,
+        tls: &TlsFiles,
+        pool: &PoolConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let address = match DockerSocket::parse(address)? {
+            DockerSocket::Tcp(address) => address,
+            _ => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "TLS is only supported for tcp:// docker sockets",
+                )))
+            }
+        };
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in DockerClient::read_certificates(&tls.ca)? {
+            roots.add(&cert)?;
+        }
+
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(
+                DockerClient::read_certificates(&tls.cert)?,
+                DockerClient::read_private_key(&tls.key)?,
+            )?;
+
+        let mut http = pool.http_connector();
+        http.enforce_http(false);
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(config)
+            .https_only()
+            .enable_http1()
+            .wrap_connector(http);
+
+        Ok(Self {
+            address,
+            client: ClientType::Tls(pool.builder().build(connector)),
+            tunnel: None,
+            timeouts: Timeouts::default(),
+            retry: Retry::default(),
+        })
+    }
+
+    fn read_certificates(
+        filename: &str,
+    ) -> Result<Vec<rustls::Certificate>, Box<dyn Error + Send + Sync>> {
+        let mut reader = BufReader::new(std::fs::File::open(filename)?);
+        let certs = rustls_pemfile::certs(&mut reader)?;
+        if certs.is_empty() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("No certificates found in {}", filename),
+            )));
+        }
+
+        Ok(certs.into_iter().map(rustls::Certificate).collect())
+    }
+
+    fn read_private_key(
+        filename: &str,
+    ) -> Result<rustls::PrivateKey, Box<dyn Error + Send + Sync>> {
+        let mut reader = BufReader::new(std::fs::File::open(filename)?);
+        while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+            match item {
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key) => return Ok(rustls::PrivateKey(key)),
+                _ => continue,
+            }
         }
+
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("No private key found in {}", filename),
+        )))
     }
 
     /// Returns a Vec of ImageList containing information about installed images
     ///
     /// More data is available, add it to the ImageList struct in ./src/docker_structs.rs
     /// for serde to extract it
-    pub async fn get_images(&mut self) -> Result<Vec<ImageList>, Box<dyn Error + Send + Sync>> {
+    pub async fn get_images(&self) -> Result<Vec<ImageList>, DockerError> {
         let response = self.get_request("/images/json").await?;
-        let response_string = String::from_utf8(response.to_vec()).unwrap();
-        let images: Vec<ImageList> = serde_json::from_str(&response_string).unwrap();
+        let images: Vec<ImageList> = DockerClient::parse_json("/images/json", &response)?;
         Ok(images)
     }
 
-    /// Gets a list of contianers - including stopped containers
+    /// Gets a list of contianers - including stopped containers - matching all of the
+    /// filters, as with events each filter key may be given more than once
     pub async fn get_containers(
-        &mut self,
-    ) -> Result<Vec<RunningContainer>, Box<dyn Error + Send + Sync>> {
-        let response = self.get_request("/containers/json?all=true").await?;
-        let response_string = String::from_utf8(response.to_vec()).unwrap();
+        &self,
+        filters: &[(&str, &str)],
+    ) -> Result<Vec<RunningContainer>, DockerError> {
+        let mut grouped: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (key, value) in filters {
+            grouped.entry(key).or_default().push(value);
+        }
+        let filters = serde_json::to_string(&grouped)?;
+        let path = format!(
+            "/containers/json?all=true&filters={}",
+            utf8_percent_encode(&filters, NON_ALPHANUMERIC)
+        );
+
+        let response = self.get_request(&path).await?;
         let running_containers: Vec<RunningContainer> =
-            serde_json::from_str(&response_string).unwrap();
+            DockerClient::parse_json("/containers/json", &response)?;
         Ok(running_containers)
     }
 
     /// Gets information in a running container, add fields to InspetContainer in
     /// ./src/docker_structs.rs to gather additional fields
     pub async fn inspect_running_container(
-        &mut self,
+        &self,
         id: &str,
-    ) -> Result<InspectContainer, Box<dyn Error + Send + Sync>> {
+    ) -> Result<InspectContainer, DockerError> {
         let mut response = self
             .request(hyper::Method::GET, &format!("/containers/{}/json", id), "")
             .await?;
         if response.status() != hyper::StatusCode::OK {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Not Found",
-            )));
+            return Err(DockerError::NotFound("Not Found".into()));
         }
 
         let body = hyper::body::to_bytes(response.body_mut()).await?;
-        let response_string = String::from_utf8(body.to_vec()).unwrap();
-        let inspection: InspectContainer = serde_json::from_str(&response_string).unwrap();
+        let inspection: InspectContainer =
+            DockerClient::parse_json(&format!("/containers/{}/json", id), &body)?;
         Ok(inspection)
     }
 
+    /// Get the inspect response of a container without picking out the fields edd uses
+    pub async fn inspect_container_json(&self, id: &str) -> Result<serde_json::Value, DockerError> {
+        let path = format!("/containers/{}/json", id);
+        let mut response = self.request(hyper::Method::GET, &path, "").await?;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        match response.status() {
+            hyper::StatusCode::OK => DockerClient::parse_json(&path, &body),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("No such container: {}", id)))
+            }
+            status => Err(DockerError::Other(format!(
+                "Unable to inspect container {}, status was {}: {}",
+                id,
+                status,
+                String::from_utf8_lossy(&body)
+            ))),
+        }
+    }
+
     /// Load a container image from a given filename
     ///
     /// Will use the /images/load endpoint to load image, but we have no control over the
     /// image naming.
     ///
     /// Determine the image name - the response is not standard, according to the documentation
-    /// there is no response, but it should be json with the image repo:name string. Might
-    /// need to exetend to support image id's
+    /// there is no response, but it should be json with the image repo:name string, or the
+    /// image id for an untagged image (i.e. an OCI image layout, loadable with docker 25+)
     ///
-    /// Retag the image using the internal naming so we can track the image
+    /// Retag the image using the internal naming so we can track the image, the image which
+    /// was previously tagged is left for the caller to remove with remove_image once it is
+    /// no longer in use
     ///
-    /// Do a system prune to remove anything we just untagged
+    /// Returns the name of the image as it was loaded, before being retagged. If overwrite is
+    /// false and new_name already points at a different image an AlreadyExists error is returned
+    ///
+    /// Each message docker sends while loading is passed to progress as it arrives
     pub async fn load_container_image(
-        &mut self,
+        &self,
         filename: &str,
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // Stream the file to the body - we do not want the whole file in RAM
+        let mut file = match tokio::fs::File::open(filename).await {
+            Ok(file) => file,
+            Err(_) => return Err(DockerError::NotFound("File not found...".into())),
+        };
+        self.load_container_image_stream(&mut file, select, new_name, overwrite, progress)
+            .await
+    }
+
+    /// Load an image tarball as it is read from image, i.e. an upload as it arrives, so the
+    /// tarball is never written to disk or held in RAM. Naming and progress are as for
+    /// load_container_image
+    pub async fn load_container_image_stream(
+        &self,
+        image: &mut (dyn AsyncRead + Send + Unpin),
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // The start is read up front to pick the content type from its magic number
+        let first = DockerClient::read_start(image).await?;
+        let mut tarball = DockerClient::decompress(first, image)?;
+        let first = DockerClient::read_start(&mut tarball).await?;
+        let content_type = DockerClient::tarball_content_type(&first)?;
+
+        let (body, pump) = DockerClient::stream_body(first, &mut tarball);
+        let (pumped, loaded) = tokio::join!(
+            pump,
+            self.load_image(body, content_type, select, new_name, overwrite, progress)
+        );
+        // A failure reading the upload explains the failed load better than docker can
+        pumped?;
+        loaded
+    }
+
+    /// Request body which streams reader, starting with the bytes already read from it. The
+    /// body is only filled while the returned future is polled alongside the request
+    fn stream_body<'a>(
+        first: Vec<u8>,
+        reader: &'a mut (dyn AsyncRead + Send + Unpin),
+    ) -> (
+        Body,
+        impl Future<Output = Result<(), DockerError>> + Send + 'a,
+    ) {
+        let (mut sender, body) = Body::channel();
+        let pump = async move {
+            let mut chunk = first;
+            loop {
+                if chunk.is_empty() {
+                    return Ok(());
+                }
+                // Docker stopped reading the body, the request itself reports why
+                if sender.send_data(chunk.into()).await.is_err() {
+                    return Ok(());
+                }
+
+                let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+                match reader.read(&mut buffer).await {
+                    Ok(read) => {
+                        buffer.truncate(read);
+                        chunk = buffer;
+                    }
+                    Err(e) => {
+                        // Abort rather than end the body, so docker does not use a truncated tarball
+                        sender.abort();
+                        return Err(DockerError::Io(e));
+                    }
+                }
+            }
+        };
+
+        (body, pump)
+    }
+
+    /// Send an image tarball to /images/load, then retag, see load_container_image
+    async fn load_image(
+        &self,
+        body: Body,
+        content_type: &str,
+        select: Option<&str>,
         new_name: &str,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // This will stream from a file, so we cannot use the helper function
-        let url = Uri::new(&self.address, "/images/load");
-        let client = Client::unix();
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // This will stream the body, so we cannot use the helper function
+        let url = self.uri("/images/load")?;
         let request = Request::builder()
             .method(hyper::Method::POST)
             .uri(url)
-            .body(self.streaming_file_read(filename).await?)?; // Stream the file to the body - we do not want the whole file in RAM
-        let mut response = client.request(request).await?;
-        let body = hyper::body::to_bytes(response.body_mut()).await?;
-        let response_string = String::from_utf8(body.to_vec()).unwrap();
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(body)?;
 
-        // Determine the name of the loaded image using the response
-        let mut loaded_image_name = None;
-        let load_result: LoadImageResult = serde_json::from_str(&response_string).unwrap();
-        for line in load_result.stream.lines() {
-            if let Some(line) = line.trim().strip_prefix("Loaded image: ") {
-                loaded_image_name = Some(line);
-            }
-        }
+        // Determine the names of the loaded images from the stream messages
+        let mut loaded_images = vec![];
+        with_timeout("/images/load", self.timeouts.long, async {
+            self.wait_for_docker().await?;
+            let mut response = self.dispatch(request).await?;
+            DockerClient::read_progress("/images/load", &mut response, &mut |message| {
+                loaded_images.extend(message.loaded_images());
+                progress(message);
+            })
+            .await
+        })
+        .await?;
 
-        if loaded_image_name.is_none() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Unable to determine loaded image repo and tag, response was:\n\t{}",
-                    response_string
-                ),
-            )));
+        // Only the chosen image is retagged, any others keep the names they were loaded with
+        let loaded_image_name = select_loaded_image(&loaded_images, select)?;
+        self.rename_image(&loaded_image_name, new_name, overwrite)
+            .await?;
+
+        Ok(loaded_image_name)
+    }
+
+    /// Build an image from a tar build context (plain or compressed) as it is read from
+    /// context, i.e. an upload as it arrives, then retag it as for load_container_image.
+    /// dockerfile is the path of the Dockerfile within the context, docker defaults to
+    /// Dockerfile. Returns the id of the built image
+    pub async fn build_image(
+        &self,
+        context: &mut (dyn AsyncRead + Send + Unpin),
+        dockerfile: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // Contexts are checked and decompressed in the same way as image tarballs, docker
+        // detects gzip itself
+        let first = DockerClient::read_start(context).await?;
+        let mut tarball = DockerClient::decompress(first, context)?;
+        let first = DockerClient::read_start(&mut tarball).await?;
+        DockerClient::tarball_content_type(&first)?;
+        let (body, pump) = DockerClient::stream_body(first, &mut tarball);
+
+        // Intermediate containers are removed even if the build fails
+        let mut path = "/build?rm=true&forcerm=true".to_owned();
+        if let Some(dockerfile) = dockerfile {
+            path.push_str(&format!(
+                "&dockerfile={}",
+                utf8_percent_encode(dockerfile, NON_ALPHANUMERIC)
+            ));
         }
+        let request = Request::builder()
+            .method(hyper::Method::POST)
+            .uri(self.uri(&path)?)
+            .header(hyper::header::CONTENT_TYPE, "application/x-tar")
+            .body(body)?;
 
-        self.rename_image(loaded_image_name.unwrap(), new_name)
-            .await?;
+        let mut built = None;
+        let build = with_timeout("/build", self.timeouts.long, async {
+            self.wait_for_docker().await?;
+            let mut response = self.dispatch(request).await?;
+            if response.status() != hyper::StatusCode::OK {
+                let body = hyper::body::to_bytes(response.body_mut()).await?;
+                return Err(DockerError::Other(format!(
+                    "Unable to build image, status was {}: {}",
+                    response.status(),
+                    String::from_utf8_lossy(&body)
+                )));
+            }
 
-        self.request(hyper::Method::POST, "/images/prune", "{}")
-            .await?;
+            DockerClient::read_progress("/build", &mut response, &mut |message| {
+                if let Some(id) = message.built_image() {
+                    built = Some(id);
+                }
+                progress(message);
+            })
+            .await
+        });
+
+        let (pumped, result) = tokio::join!(pump, build);
+        // A failure reading the upload explains the failed build better than docker can
+        pumped?;
+        result?;
+
+        let id = built.ok_or(DockerError::MalformedResponse(
+            "/build did not report the id of the built image".into(),
+        ))?;
+        self.rename_image(&id, new_name, overwrite).await?;
+
+        Ok(id)
+    }
+
+    /// Export an image as a tarball (docker save) to the given file, the tarball can be
+    /// loaded with load_container_image. The response is written to the file as it arrives
+    /// so the whole image is never held in RAM
+    pub async fn save_image(&self, name: &str, filename: &str) -> Result<(), DockerError> {
+        let path = format!("/images/{}/get", name);
+
+        with_timeout(&path, self.timeouts.long, async {
+            let mut response = self.dispatch_retrying(hyper::Method::GET, &path).await?;
+            DockerClient::write_image(name, &mut response, filename).await
+        })
+        .await
+    }
+
+    async fn write_image(
+        name: &str,
+        response: &mut Response<Body>,
+        filename: &str,
+    ) -> Result<(), DockerError> {
+        match response.status() {
+            hyper::StatusCode::OK => {}
+            hyper::StatusCode::NOT_FOUND => {
+                return Err(DockerError::NotFound(format!("Image {} not found", name)))
+            }
+            status => {
+                return Err(DockerError::Other(format!(
+                    "Unable to save image {}, status was {}",
+                    name, status
+                )))
+            }
+        }
+
+        let mut file = tokio::fs::File::create(filename).await?;
+        while let Some(chunk) = response.body_mut().data().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
 
         Ok(())
     }
 
+    /// Pull an image from a registry and retag it with the internal naming, returns the name
+    /// of the image as it was pulled. Retagging follows the same overwrite rules as
+    /// load_container_image, progress is called for each message as with load_container_image
     pub async fn pull_container_image(
-        &mut self,
+        &self,
         image: &str,
+        auth: Option<&AuthConfig>,
         new_name: &str,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // Attempt to pull the image
-        let mut response = self
-            .request(
-                hyper::Method::POST,
-                &format!("/images/create?fromImage={}", image),
-                "{}",
-            )
-            .await?;
-        let body = hyper::body::to_bytes(response.body_mut()).await?;
-        let response_string = String::from_utf8(body.to_vec()).unwrap();
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        // Attempt to pull the image, the daemon exchanges the credentials for a bearer token
+        // with the registry where it needs to so a fresh token is used on every pull
+        let path = format!("/images/create?fromImage={}", image);
+        let auth = match auth {
+            Some(auth) => {
+                Some(base64::engine::general_purpose::URL_SAFE.encode(serde_json::to_vec(auth)?))
+            }
+            None => None,
+        };
+
+        // The final status message names the image just pulled
+        let mut last_status = None;
+        with_timeout("/images/create", self.timeouts.long, async {
+            let method = hyper::Method::POST;
+            let mut response = self
+                .retrying(&method, &path, || async {
+                    let mut request = Request::builder()
+                        .method(method.clone())
+                        .uri(self.uri(&path)?);
+                    if let Some(auth) = &auth {
+                        request = request.header("X-Registry-Auth", auth);
+                    }
+                    self.dispatch(request.body(Body::from("{}"))?).await
+                })
+                .await?;
+            DockerClient::read_progress("/images/create", &mut response, &mut |message| {
+                if let Some(status) = &message.status {
+                    last_status = Some(status.to_owned());
+                }
+                progress(message);
+            })
+            .await
+        })
+        .await?;
 
-        // Extract the name of the image just pulled
-        let image_name = DockerClient::get_status_image(&response_string);
+        let image_name = last_status
+            .as_deref()
+            .and_then(DockerClient::get_status_image);
         if image_name.is_none() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Unable to determine loaded image repo and tag, response was:\n\t{}",
-                    response_string
-                ),
+            return Err(DockerError::Other(format!(
+                "Unable to determine pulled image repo and tag, last status was: {}",
+                last_status.unwrap_or_default()
             )));
         }
 
-        self.rename_image(&image_name.unwrap(), new_name).await?;
-
-        self.request(hyper::Method::POST, "/images/prune", "{}")
-            .await?;
+        let image_name = image_name.unwrap();
+        self.rename_image(&image_name, new_name, overwrite).await?;
 
-        Ok(())
+        Ok(image_name)
     }
 
-    pub async fn start(&self, id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pub async fn start(&self, id: &str) -> Result<(), DockerError> {
         self.request(
             hyper::Method::POST,
             &format!("/containers/{}/start", id),
@@ -179,47 +913,131 @@ impl DockerClient {
         Ok(())
     }
 
-    /// Create a new container using the docker cli
+    /// Create a new named container and start it
     ///
-    /// Docker cli is used so we avoid having to parse/map argments to the docker API
-    pub fn start_with_cli(
+    /// args are given in docker run format and translated to a create request by
+    /// ./src/run_args.rs, unsupported args are returned as an InvalidInput error
+    pub async fn create_and_start(
         &self,
         name: &str,
         image: &str,
         args: Vec<&str>,
-    ) -> io::Result<std::process::Output> {
-        return std::process::Command::new("docker")
-            .args(["run", "-d", "-it"])
-            .args(args)
-            .args([&format!("--name={}", name), image])
-            .output();
+    ) -> Result<String, DockerError> {
+        let create = run_args::parse(&args, image).map_err(DockerError::InvalidRequest)?;
+
+        let path = format!("/containers/create?name={}", name);
+        let mut response = self
+            .request(hyper::Method::POST, &path, &serde_json::to_string(&create)?)
+            .await?;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        if response.status() != hyper::StatusCode::CREATED {
+            return Err(DockerError::Other(format!(
+                "Unable to create container {}, status was {}: {}",
+                name,
+                response.status(),
+                String::from_utf8_lossy(&body)
+            )));
+        }
+
+        let created: CreateContainerResult = DockerClient::parse_json(&path, &body)?;
+        for warning in created.warnings.iter().flatten() {
+            println!("Warning creating container {}: {}", name, warning);
+        }
+
+        let mut response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/containers/{}/start", created.id),
+                "{}",
+            )
+            .await?;
+        if response.status() != hyper::StatusCode::NO_CONTENT {
+            let body = hyper::body::to_bytes(response.body_mut()).await?;
+            return Err(DockerError::Other(format!(
+                "Unable to start container {}, status was {}: {}",
+                name,
+                response.status(),
+                String::from_utf8_lossy(&body)
+            )));
+        }
+
+        Ok(created.id)
     }
 
-    /// Provides a streaming file read, we can take a saved file (i.e. a tempfile from Rocket)
-    /// and push parts of it t oan async handler without needing to load the whole file at once
-    async fn streaming_file_read(
-        &self,
-        filename: &str,
-    ) -> Result<Body, Box<dyn Error + Send + Sync>> {
-        if let Ok(file) = tokio::fs::File::open(filename).await {
-            let stream =
-                tokio_util::codec::FramedRead::new(file, tokio_util::codec::BytesCodec::new());
-            let body = Body::wrap_stream(stream);
-            return Ok(body);
+    /// Read enough of the start of an image tarball to identify it, fewer bytes are only
+    /// returned if the tarball is shorter
+    pub async fn read_start(
+        image: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<Vec<u8>, DockerError> {
+        let mut start = vec![0u8; TARBALL_MAGIC_SIZE];
+        let mut filled = 0;
+        while filled < start.len() {
+            let read = image.read(&mut start[filled..]).await?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
         }
+        start.truncate(filled);
 
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "File not found...",
-        )))
+        Ok(start)
+    }
+
+    /// The tarball read from reader, which started with first, as it is to be sent to docker
+    ///
+    /// Every docker version takes plain and gzipped tarballs, so they are passed through as
+    /// they are. bzip2, xz and zstd (which docker only takes from version 23) are decompressed
+    /// here as they are read instead
+    pub fn decompress<'a>(
+        first: Vec<u8>,
+        reader: &'a mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin + 'a>, DockerError> {
+        const BZIP2: &[u8] = b"BZh";
+        const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+        const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+        let compressed =
+            tokio::io::BufReader::new(std::io::Cursor::new(first.clone()).chain(reader));
+        // Concatenated streams, as written by parallel compressors, are read as one tarball
+        Ok(if first.starts_with(BZIP2) {
+            let mut decoder = async_compression::tokio::bufread::BzDecoder::new(compressed);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        } else if first.starts_with(XZ) {
+            let mut decoder = async_compression::tokio::bufread::XzDecoder::new(compressed);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        } else if first.starts_with(ZSTD) {
+            let mut decoder = async_compression::tokio::bufread::ZstdDecoder::new(compressed);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        } else {
+            Box::new(compressed)
+        })
+    }
+
+    /// Content type for an image tarball starting with the given bytes, once it has been
+    /// through decompress. Anything other than a plain or gzipped tarball is rejected before
+    /// it is sent to docker
+    pub fn tarball_content_type(start: &[u8]) -> Result<&'static str, DockerError> {
+        if start.starts_with(&[0x1f, 0x8b]) {
+            return Ok("application/gzip");
+        }
+
+        // Plain tar has no magic number at the start, the first header has one at 257
+        if start.get(257..262) == Some(b"ustar") {
+            return Ok("application/x-tar");
+        }
+
+        Err(DockerError::UnsupportedImage(
+            "Image is not a tar archive, or a gzip, bzip2, xz or zstd compressed tar archive"
+                .into(),
+        ))
     }
 
     /// Stops a running container, will return Ok(()) if the container is already stopped
     /// but will Err if the container id does not exist
-    pub async fn stop_running_container(
-        &mut self,
-        id: &str,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pub async fn stop_running_container(&self, id: &str) -> Result<(), DockerError> {
         let inspection = self.inspect_running_container(id).await?;
         if inspection.state.running == false {
             // Already stopped
@@ -238,118 +1056,1070 @@ impl DockerClient {
         if response.status() != hyper::StatusCode::NO_CONTENT
             && response.status() != hyper::StatusCode::NOT_MODIFIED
         {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Not Found",
+            return Err(DockerError::Other(format!(
+                "Unable to stop {}, status was {}",
+                id,
+                response.status()
             )));
         }
         Ok(())
     }
 
-    /// Remove a stopped container
-    pub async fn remove_stopped_container(
-        &mut self,
-        id: &str,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let response = self
-            .request(hyper::Method::DELETE, &format!("/containers/{}", id), "{}")
-            .await?;
+    /// Wait for a container to stop running and return its exit code, returns immediately if
+    /// the container is not running
+    pub async fn wait_container(&self, id: &str) -> Result<i64, DockerError> {
+        let path = format!("/containers/{}/wait?condition=not-running", id);
+        let mut response = self.request(hyper::Method::POST, &path, "").await?;
 
-        if response.status() != hyper::StatusCode::NO_CONTENT {
-            // TODO: Better error
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Not Found",
-            )));
+        match response.status() {
+            hyper::StatusCode::OK => {}
+            hyper::StatusCode::NOT_FOUND => {
+                return Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            status => {
+                return Err(DockerError::Other(format!(
+                    "Unable to wait for {}, status was {}",
+                    id, status
+                )))
+            }
         }
 
-        Ok(())
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        let result: WaitResult = DockerClient::parse_json(&path, &body)?;
+        if let Some(error) = result.error {
+            return Err(DockerError::Other(format!(
+                "Error waiting for {}: {}",
+                id, error.message
+            )));
+        }
+
+        Ok(result.status_code)
     }
 
-    /// Helper function for simple GET requests - TODO remove and use request()
-    async fn get_request(&self, path: &str) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
-        let url = Uri::new(&self.address, path).into();
+    /// Restart a container in place using the restart endpoint, the container (and its
+    /// anonymous volumes) are kept. timeout is the number of seconds to wait for the container
+    /// to stop before it is killed, the container's own stop timeout is used if not given
+    pub async fn restart_container(
+        &self,
+        id: &str,
+        timeout: Option<u64>,
+    ) -> Result<(), DockerError> {
+        let mut path = format!("/containers/{}/restart", id);
+        if let Some(timeout) = timeout {
+            path.push_str(&format!("?t={}", timeout));
+        }
+
+        let response = self.request(hyper::Method::POST, &path, "").await?;
+        match response.status() {
+            hyper::StatusCode::NO_CONTENT => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            status => Err(DockerError::Other(format!(
+                "Unable to restart {}, status was {}",
+                id, status
+            ))),
+        }
+    }
+
+    /// Send a signal to the main process of a container, i.e. "SIGKILL"
+    pub async fn kill_container(&self, id: &str, signal: &str) -> Result<(), DockerError> {
+        let response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/containers/{}/kill?signal={}", id, signal),
+                "",
+            )
+            .await?;
+
+        match response.status() {
+            hyper::StatusCode::NO_CONTENT => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            status => Err(DockerError::Other(format!(
+                "Unable to send {} to {}, status was {}",
+                signal, id, status
+            ))),
+        }
+    }
+
+    /// Give a container a new name, the name must not already be in use
+    pub async fn rename_container(&self, id: &str, name: &str) -> Result<(), DockerError> {
+        let response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/containers/{}/rename?name={}", id, name),
+                "",
+            )
+            .await?;
+
+        match response.status() {
+            hyper::StatusCode::NO_CONTENT => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            hyper::StatusCode::CONFLICT => Err(DockerError::Conflict(format!(
+                "Unable to rename {}, name {} is already in use",
+                id, name
+            ))),
+            status => Err(DockerError::Other(format!(
+                "Unable to rename {}, status was {}",
+                id, status
+            ))),
+        }
+    }
+
+    /// Freeze (pause) or thaw (unpause) all processes in a running container
+    pub async fn set_paused(&self, id: &str, paused: bool) -> Result<(), DockerError> {
+        let action = match paused {
+            true => "pause",
+            false => "unpause",
+        };
+        let response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/containers/{}/{}", id, action),
+                "",
+            )
+            .await?;
+
+        match response.status() {
+            hyper::StatusCode::NO_CONTENT => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            status => Err(DockerError::Other(format!(
+                "Unable to {} {}, status was {}",
+                action, id, status
+            ))),
+        }
+    }
+
+    /// Write a tar archive of path in a container to the given file, a directory is archived
+    /// with its contents
+    pub async fn get_archive(
+        &self,
+        id: &str,
+        path: &str,
+        filename: &str,
+    ) -> Result<(), DockerError> {
+        let url = format!(
+            "/containers/{}/archive?path={}",
+            id,
+            utf8_percent_encode(path, NON_ALPHANUMERIC)
+        );
+        with_timeout("/containers/archive", self.timeouts.long, async {
+            let mut response = self.dispatch_retrying(hyper::Method::GET, &url).await?;
+            match response.status() {
+                hyper::StatusCode::OK => {}
+                hyper::StatusCode::NOT_FOUND => {
+                    return Err(DockerError::NotFound(format!(
+                        "{} not found in container {}",
+                        path, id
+                    )))
+                }
+                status => {
+                    let body = hyper::body::to_bytes(response.body_mut()).await?;
+                    return Err(DockerError::Other(format!(
+                        "Unable to copy {} from {}, status was {}: {}",
+                        path,
+                        id,
+                        status,
+                        String::from_utf8_lossy(&body)
+                    )));
+                }
+            }
+
+            let mut file = tokio::fs::File::create(filename).await?;
+            while let Some(chunk) = response.body_mut().data().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.flush().await?;
 
-        let client = Client::unix();
+            Ok(())
+        })
+        .await
+    }
+
+    /// Extract a tar archive, as it is read from archive, into the directory path of a
+    /// container. The archive may be compressed with gzip, bzip2 or xz
+    pub async fn put_archive(
+        &self,
+        id: &str,
+        path: &str,
+        archive: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), DockerError> {
+        let first = DockerClient::read_start(archive).await?;
+        let (body, pump) = DockerClient::stream_body(first, archive);
+
+        let url = format!(
+            "/containers/{}/archive?path={}",
+            id,
+            utf8_percent_encode(path, NON_ALPHANUMERIC)
+        );
+        let request = Request::builder()
+            .method(hyper::Method::PUT)
+            .uri(self.uri(&url)?)
+            .header(hyper::header::CONTENT_TYPE, "application/x-tar")
+            .body(body)?;
+
+        let put = with_timeout("/containers/archive", self.timeouts.long, async {
+            self.wait_for_docker().await?;
+            let mut response = self.dispatch(request).await?;
+            let body = hyper::body::to_bytes(response.body_mut()).await?;
+            let message = String::from_utf8_lossy(&body).trim().to_owned();
+
+            match response.status() {
+                hyper::StatusCode::OK => Ok(()),
+                hyper::StatusCode::NOT_FOUND => Err(DockerError::NotFound(message)),
+                // Not a directory, not a valid tarball, or a read only filesystem
+                hyper::StatusCode::BAD_REQUEST | hyper::StatusCode::FORBIDDEN => {
+                    Err(DockerError::InvalidRequest(message))
+                }
+                status => Err(DockerError::Other(format!(
+                    "Unable to copy to {} in {}, status was {}: {}",
+                    path, id, status, message
+                ))),
+            }
+        });
+
+        let (pumped, put) = tokio::join!(pump, put);
+        // A failure reading the upload explains the failed copy better than docker can
+        pumped?;
+        put
+    }
+
+    /// Change the cpu and memory limits of a container, running or not, in place
+    pub async fn update_resources(
+        &self,
+        id: &str,
+        update: &UpdateContainer,
+    ) -> Result<(), DockerError> {
+        let mut response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/containers/{}/update", id),
+                &serde_json::to_string(update)?,
+            )
+            .await?;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+
+        match response.status() {
+            hyper::StatusCode::OK => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            // i.e. a memory limit below docker's minimum
+            hyper::StatusCode::BAD_REQUEST => Err(DockerError::InvalidRequest(
+                String::from_utf8_lossy(&body).trim().to_owned(),
+            )),
+            status => Err(DockerError::Other(format!(
+                "Unable to update {}, status was {}: {}",
+                id,
+                status,
+                String::from_utf8_lossy(&body)
+            ))),
+        }
+    }
+
+    /// Remove a stopped container
+    pub async fn remove_stopped_container(&self, id: &str) -> Result<(), DockerError> {
+        let response = self
+            .request(hyper::Method::DELETE, &format!("/containers/{}", id), "{}")
+            .await?;
+
+        if response.status() != hyper::StatusCode::NO_CONTENT {
+            // TODO: Better error
+            return Err(DockerError::Other("Not Found".into()));
+        }
 
-        let mut response = client.get(url).await?;
+        Ok(())
+    }
+
+    /// Helper function for simple GET requests - TODO remove and use request()
+    async fn get_request(&self, path: &str) -> Result<Bytes, DockerError> {
+        let mut response = self.request(hyper::Method::GET, path, "").await?;
 
         let body = hyper::body::to_bytes(response.body_mut()).await?;
 
         Ok(body)
     }
 
-    /// Helper function for async requests using Hyper
+    /// Helper function for async requests using Hyper, failed requests are retried as
+    /// described by is_retryable
     async fn request(
         &self,
         method: hyper::Method,
         path: &str,
         body: &str,
-    ) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
-        let url = Uri::new(&self.address, path);
+    ) -> Result<Response<Body>, DockerError> {
+        self.retrying(&method, path, || async {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri(self.uri(path)?)
+                .body(Body::from(body.to_owned()))?;
+            self.send(request).await
+        })
+        .await
+    }
 
-        let client = Client::unix();
+    /// Make a request with send, retrying with a backoff while is_retryable
+    async fn retrying<F, R>(
+        &self,
+        method: &hyper::Method,
+        path: &str,
+        mut send: F,
+    ) -> Result<Response<Body>, DockerError>
+    where
+        F: FnMut() -> R,
+        R: Future<Output = Result<Response<Body>, DockerError>>,
+    {
+        let mut attempt = 1;
+        let mut backoff = self.retry.backoff;
+        loop {
+            let result = send().await;
+            if DockerClient::is_retryable(method, &result) == false
+                || attempt >= self.retry.attempts
+            {
+                return result;
+            }
 
-        let request = Request::builder()
-            .method(method)
-            .uri(url)
-            .body(Body::from(body.to_owned()))?;
+            println!(
+                "Docker request {} failed, retrying in {}ms",
+                path,
+                backoff.as_millis()
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+            backoff *= 2;
+        }
+    }
+
+    /// A request which could not connect to docker never reached it, so is always retried.
+    /// One whose connection dropped part way (i.e. docker restarting) may already have been
+    /// acted on, so only GET and HEAD requests are retried - a repeated exec start would run
+    /// the command again. Only GET requests are retried on a 5xx as docker reports real
+    /// failures (i.e. a port already in use on start) that way, and they would only fail again
+    fn is_retryable(method: &hyper::Method, result: &Result<Response<Body>, DockerError>) -> bool {
+        let idempotent = method == hyper::Method::GET || method == hyper::Method::HEAD;
+        match result {
+            Ok(response) => response.status().is_server_error() && method == hyper::Method::GET,
+            Err(DockerError::DaemonUnreachable(e)) => e.is_connect() || idempotent,
+            Err(_) => false,
+        }
+    }
+
+    /// Waits for docker to answer a ping, retried as any other request, before streaming a
+    /// body which cannot be sent again (i.e. an upload as it arrives). A brief hiccup, such as
+    /// docker restarting, then does not fail the load, build or copy
+    async fn wait_for_docker(&self) -> Result<(), DockerError> {
+        let response = self.request(hyper::Method::GET, "/_ping", "").await?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(DockerError::Other(format!(
+                "Docker ping failed, status was {}",
+                response.status()
+            ))),
+        }
+    }
 
-        let response = client.request(request).await?;
+    /// Docker is on this host, reached through a unix socket rather than an ssh tunnel
+    fn is_local(&self) -> bool {
+        #[cfg(unix)]
+        if let ClientType::Unix(_) = self.client {
+            return self.tunnel.is_none();
+        }
+        false
+    }
+
+    /// Build the full uri for an API path using the configured transport
+    fn uri(&self, path: &str) -> Result<hyper::Uri, DockerError> {
+        match &self.client {
+            #[cfg(unix)]
+            ClientType::Unix(_) => Ok(hyperlocal::Uri::new(&self.address, path).into()),
+            // The pipe is opened by the connector, the host is only there to make a valid uri
+            #[cfg(windows)]
+            ClientType::NamedPipe(_) => Ok(format!("http://docker{}", path).parse()?),
+            ClientType::Tcp(_) => Ok(format!("http://{}{}", self.address, path).parse()?),
+            ClientType::Tls(_) => Ok(format!("https://{}{}", self.address, path).parse()?),
+        }
+    }
+
+    /// Send a request using the configured transport, failing with a Timeout if docker does
+    /// not respond within the request timeout
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, DockerError> {
+        let operation = request.uri().path().to_owned();
+        with_timeout(&operation, self.timeouts.request, self.dispatch(request)).await
+    }
+
+    /// Send a request without a body or a timeout, retrying as for request. For responses
+    /// read as they arrive, the caller applies a timeout to the whole exchange
+    async fn dispatch_retrying(
+        &self,
+        method: hyper::Method,
+        path: &str,
+    ) -> Result<Response<Body>, DockerError> {
+        self.retrying(&method, path, || async {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri(self.uri(path)?)
+                .body(Body::empty())?;
+            self.dispatch(request).await
+        })
+        .await
+    }
+
+    /// Send a request without a timeout, the caller is responsible for applying one
+    async fn dispatch(&self, request: Request<Body>) -> Result<Response<Body>, DockerError> {
+        let response = match &self.client {
+            #[cfg(unix)]
+            ClientType::Unix(client) => client.request(request).await?,
+            #[cfg(windows)]
+            ClientType::NamedPipe(client) => client.request(request).await?,
+            ClientType::Tcp(client) => client.request(request).await?,
+            ClientType::Tls(client) => client.request(request).await?,
+        };
 
         Ok(response)
     }
 
-    /// The pull command can end in one of:
+    /// The final pull status message can be one of:
     ///
     /// - {"status":"Status: Downloaded newer image for alpine:latest"}
     /// - {"status":"Status: Image is up to date for alpine:latest"}
     ///
     /// This function will return Some(alpine:latest) in the above examples, or
     /// None if not found
-    fn get_status_image(response_string: &str) -> Option<String> {
-        let mut last_status_message = None;
+    fn get_status_image(status: &str) -> Option<String> {
+        let split = status.split(" ").last();
+        if let Some(image) = split {
+            if image.contains(":") {
+                return Some(image.into());
+            }
+        }
 
-        for line in response_string.lines() {
-            if line.contains(r#"{"status":"#) {
-                last_status_message = Some(line.trim());
+        None
+    }
+
+    /// Read a newline delimited json response as it arrives, passing each message to progress
+    ///
+    /// The body is never held in full, only the current incomplete line is buffered. An error
+    /// message from docker ends the read with an error.
+    async fn read_progress(
+        endpoint: &str,
+        response: &mut Response<Body>,
+        progress: &mut Progress<'_>,
+    ) -> Result<(), DockerError> {
+        let mut messages = DockerClient::json_lines::<ProgressMessage>(
+            endpoint.to_owned(),
+            std::mem::take(response.body_mut()),
+        );
+        while let Some(message) = messages.next().await {
+            let message = message?;
+            if let Some(error) = &message.error {
+                return Err(DockerError::Other(format!(
+                    "{} failed: {}",
+                    endpoint, error
+                )));
             }
+            progress(&message);
+        }
+
+        Ok(())
+    }
+
+    /// Split a newline delimited json body into a stream of messages as the chunks arrive,
+    /// only the current incomplete line is buffered
+    fn json_lines<T: DeserializeOwned + Send + 'static>(
+        endpoint: String,
+        body: Body,
+    ) -> BoxStream<'static, Result<T, DockerError>> {
+        futures::stream::unfold(
+            (body, Vec::<u8>::new(), false),
+            move |(mut body, mut buffer, mut finished)| {
+                let endpoint = endpoint.clone();
+                async move {
+                    loop {
+                        if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                            let line: Vec<u8> = buffer.drain(..=end).collect();
+                            if line.iter().all(|b| b.is_ascii_whitespace()) {
+                                continue;
+                            }
+                            let message = DockerClient::parse_json(&endpoint, &line);
+                            return Some((message, (body, buffer, finished)));
+                        }
+
+                        if finished {
+                            return None;
+                        }
+
+                        match body.data().await {
+                            Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                            Some(Err(e)) => {
+                                return Some((Err(e.into()), (body, vec![], true)));
+                            }
+                            None => {
+                                // Treat anything left over as the final line
+                                buffer.push(b'\n');
+                                finished = true;
+                            }
+                        }
+                    }
+                }
+            },
+        )
+        .boxed()
+    }
+
+    /// Get a single sample of a container's CPU, memory, network and block IO usage
+    ///
+    /// Docker waits for a second sample before responding so precpu_stats is populated
+    pub async fn stats(&self, id: &str) -> Result<ContainerStats, DockerError> {
+        let path = format!("/containers/{}/stats?stream=false", id);
+        let mut response = self.request(hyper::Method::GET, &path, "").await?;
+        if response.status() != hyper::StatusCode::OK {
+            return Err(DockerError::NotFound(format!(
+                "Unable to get stats for {}, status was {}",
+                id,
+                response.status()
+            )));
         }
 
-        if last_status_message.is_none() {
-            dbg!(response_string);
-            return None;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        DockerClient::parse_json(&path, &body)
+    }
+
+    /// List all volumes
+    pub async fn list_volumes(&self) -> Result<Vec<Volume>, DockerError> {
+        let response = self.get_request("/volumes").await?;
+        let list: VolumeList = DockerClient::parse_json("/volumes", &response)?;
+        Ok(list.volumes.unwrap_or_default())
+    }
+
+    /// Create a named volume, docker returns the existing volume if it already exists
+    pub async fn create_volume(&self, volume: &CreateVolume) -> Result<Volume, DockerError> {
+        let mut response = self
+            .request(
+                hyper::Method::POST,
+                "/volumes/create",
+                &serde_json::to_string(volume)?,
+            )
+            .await?;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        if response.status() != hyper::StatusCode::CREATED {
+            return Err(DockerError::Other(format!(
+                "Unable to create volume {}, status was {}: {}",
+                volume.name,
+                response.status(),
+                String::from_utf8_lossy(&body)
+            )));
         }
 
-        let pull_result: PullImageResult =
-            serde_json::from_str(&last_status_message.unwrap()).unwrap();
+        DockerClient::parse_json("/volumes/create", &body)
+    }
 
-        let split = pull_result.status.split(" ").last();
-        if let Some(image) = split {
-            if image.contains(":") {
-                return Some(image.into());
+    /// Remove a volume, force removes it even if it is in use
+    pub async fn remove_volume(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        let response = self
+            .request(
+                hyper::Method::DELETE,
+                &format!("/volumes/{}?force={}", name, force),
+                "",
+            )
+            .await?;
+
+        match response.status() {
+            hyper::StatusCode::NO_CONTENT => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Volume {} not found", name)))
+            }
+            hyper::StatusCode::CONFLICT => {
+                Err(DockerError::Conflict(format!("Volume {} is in use", name)))
             }
+            status => Err(DockerError::Other(format!(
+                "Unable to remove volume {}, status was {}",
+                name, status
+            ))),
         }
+    }
 
-        None
+    /// List all networks
+    pub async fn list_networks(&self) -> Result<Vec<Network>, DockerError> {
+        let response = self.get_request("/networks").await?;
+        DockerClient::parse_json("/networks", &response)
+    }
+
+    /// Create a network, returning its id
+    pub async fn create_network(&self, network: &CreateNetwork) -> Result<String, DockerError> {
+        let mut response = self
+            .request(
+                hyper::Method::POST,
+                "/networks/create",
+                &serde_json::to_string(network)?,
+            )
+            .await?;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        if response.status() != hyper::StatusCode::CREATED {
+            return Err(DockerError::Other(format!(
+                "Unable to create network {}, status was {}: {}",
+                network.name,
+                response.status(),
+                String::from_utf8_lossy(&body)
+            )));
+        }
+
+        let created: CreateNetworkResult = DockerClient::parse_json("/networks/create", &body)?;
+        Ok(created.id)
+    }
+
+    /// Attach a container (by id or name) to a network
+    pub async fn connect_network(
+        &self,
+        network: &str,
+        container: &str,
+        aliases: &[String],
+    ) -> Result<(), DockerError> {
+        let connect = ConnectNetwork {
+            container: container.to_owned(),
+            endpoint_config: EndpointConfig {
+                aliases: aliases.to_owned(),
+            },
+        };
+        let mut response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/networks/{}/connect", network),
+                &serde_json::to_string(&connect)?,
+            )
+            .await?;
+        if response.status() != hyper::StatusCode::OK {
+            let body = hyper::body::to_bytes(response.body_mut()).await?;
+            return Err(DockerError::Other(format!(
+                "Unable to connect {} to network {}, status was {}: {}",
+                container,
+                network,
+                response.status(),
+                String::from_utf8_lossy(&body)
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Run a command in a running container and wait for it to finish
+    ///
+    /// Uses the exec create, start and inspect endpoints, stdout and stderr are collected up
+    /// to EXEC_OUTPUT_LIMIT so this is intended for short diagnostic commands
+    pub async fn exec(&self, id: &str, command: &[String]) -> Result<ExecResult, DockerError> {
+        let create = CreateExec {
+            attach_stdout: true,
+            attach_stderr: true,
+            tty: false,
+            cmd: command.to_owned(),
+        };
+        let path = format!("/containers/{}/exec", id);
+        let mut response = self
+            .request(hyper::Method::POST, &path, &serde_json::to_string(&create)?)
+            .await?;
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        match response.status() {
+            hyper::StatusCode::CREATED => {}
+            hyper::StatusCode::NOT_FOUND => {
+                return Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            hyper::StatusCode::CONFLICT => {
+                return Err(DockerError::Conflict(format!(
+                    "Container {} is not running",
+                    id
+                )))
+            }
+            status => {
+                return Err(DockerError::Other(format!(
+                    "Unable to create exec in {}, status was {}: {}",
+                    id,
+                    status,
+                    String::from_utf8_lossy(&body)
+                )))
+            }
+        }
+        let exec: CreateExecResult = DockerClient::parse_json(&path, &body)?;
+
+        // Without detach the start response is the multiplexed output of the command
+        let start = StartExec {
+            detach: false,
+            tty: false,
+        };
+        let mut response = self
+            .request(
+                hyper::Method::POST,
+                &format!("/exec/{}/start", exec.id),
+                &serde_json::to_string(&start)?,
+            )
+            .await?;
+        if response.status() != hyper::StatusCode::OK {
+            return Err(DockerError::Other(format!(
+                "Unable to start exec in {}, status was {}",
+                id,
+                response.status()
+            )));
+        }
+
+        let mut captured = ExecOutput::default();
+        let body = std::mem::take(response.body_mut());
+        let mut output = DockerClient::demultiplex(body.map(|c| c.map_err(DockerError::from)));
+        while let Some(chunk) = output.next().await {
+            let chunk = chunk?;
+            captured.push(chunk.source, &chunk.data);
+        }
+
+        let path = format!("/exec/{}/json", exec.id);
+        let inspection: InspectExec =
+            DockerClient::parse_json(&path, &self.get_request(&path).await?)?;
+
+        Ok(captured.finish(inspection.exit_code))
+    }
+
+    /// Get the stdout and stderr logs of a container
+    ///
+    /// tail limits the output to the last lines, since to lines after a unix timestamp. With
+    /// follow the stream stays open and returns new output until the container stops. Unless
+    /// the container has a TTY docker multiplexes stdout and stderr, which is split back out.
+    pub async fn get_logs(
+        &self,
+        id: &str,
+        tail: Option<usize>,
+        since: Option<i64>,
+        follow: bool,
+    ) -> Result<LogChunks, DockerError> {
+        let tty = self
+            .inspect_running_container(id)
+            .await?
+            .config
+            .map(|c| c.tty)
+            .unwrap_or(false);
+
+        let mut path = format!(
+            "/containers/{}/logs?stdout=true&stderr=true&follow={}&tail={}",
+            id,
+            follow,
+            tail.map(|t| t.to_string()).unwrap_or("all".into())
+        );
+        if let Some(since) = since {
+            path.push_str(&format!("&since={}", since));
+        }
+
+        let mut response = self.request(hyper::Method::GET, &path, "").await?;
+        if response.status() != hyper::StatusCode::OK {
+            return Err(DockerError::NotFound(format!(
+                "Unable to get logs for {}, status was {}",
+                id,
+                response.status()
+            )));
+        }
+        let body = std::mem::take(response.body_mut());
+
+        if tty {
+            let chunks = body.map(|chunk| match chunk {
+                Ok(data) => Ok(LogChunk {
+                    source: LogSource::Stdout,
+                    data: data.to_vec(),
+                }),
+                Err(e) => Err(e.into()),
+            });
+            return Ok(chunks.boxed());
+        }
+
+        Ok(DockerClient::demultiplex(
+            body.map(|c| c.map_err(DockerError::from)),
+        ))
+    }
+
+    /// Attach to the stdin, stdout and stderr of a running container
+    ///
+    /// The connection is upgraded to a raw stream, output is returned as chunks (split in to
+    /// stdout and stderr unless the container has a TTY) and input is written straight to the
+    /// container's stdin. Dropping both detaches, the container keeps running
+    pub async fn attach(&self, id: &str) -> Result<Attached, DockerError> {
+        let tty = self
+            .inspect_running_container(id)
+            .await?
+            .config
+            .map(|c| c.tty)
+            .unwrap_or(false);
+
+        let request = Request::builder()
+            .method(hyper::Method::POST)
+            .uri(self.uri(&format!(
+                "/containers/{}/attach?stream=true&stdin=true&stdout=true&stderr=true",
+                id
+            ))?)
+            .header(hyper::header::CONNECTION, "Upgrade")
+            .header(hyper::header::UPGRADE, "tcp")
+            .body(Body::empty())?;
+
+        let mut response = self.send(request).await?;
+        match response.status() {
+            hyper::StatusCode::SWITCHING_PROTOCOLS | hyper::StatusCode::OK => {}
+            hyper::StatusCode::NOT_FOUND => {
+                return Err(DockerError::NotFound(format!("Container {} not found", id)))
+            }
+            status => {
+                let body = hyper::body::to_bytes(response.body_mut()).await?;
+                return Err(DockerError::Other(format!(
+                    "Unable to attach to {}, status was {}: {}",
+                    id,
+                    status,
+                    String::from_utf8_lossy(&body)
+                )));
+            }
+        }
+
+        let upgraded = hyper::upgrade::on(&mut response).await?;
+        let (reader, writer) = tokio::io::split(upgraded);
+        let bytes =
+            tokio_util::codec::FramedRead::new(reader, tokio_util::codec::BytesCodec::new()).map(
+                |chunk| match chunk {
+                    Ok(data) => Ok(data.freeze()),
+                    Err(e) => Err(DockerError::from(e)),
+                },
+            );
+
+        let output = match tty {
+            true => bytes
+                .map(|chunk| {
+                    chunk.map(|data| LogChunk {
+                        source: LogSource::Stdout,
+                        data: data.to_vec(),
+                    })
+                })
+                .boxed(),
+            false => DockerClient::demultiplex(bytes),
+        };
+
+        Ok(Attached {
+            output,
+            input: Box::pin(writer),
+        })
+    }
+
+    /// Split a multiplexed stdout/stderr body into chunks, each frame is an 8 byte header
+    /// (stream type, 3 bytes padding, big endian u32 length) followed by the data
+    fn demultiplex<S>(body: S) -> LogChunks
+    where
+        S: futures::Stream<Item = Result<Bytes, DockerError>> + Send + Unpin + 'static,
+    {
+        let frames = futures::stream::unfold(
+            (body, Vec::<u8>::new(), false),
+            |(mut body, mut buffer, finished)| async move {
+                loop {
+                    if buffer.len() >= 8 {
+                        let length =
+                            u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]])
+                                as usize;
+                        if buffer.len() >= 8 + length {
+                            let source = match buffer[0] {
+                                2 => LogSource::Stderr,
+                                _ => LogSource::Stdout,
+                            };
+                            let frame: Vec<u8> = buffer.drain(..8 + length).skip(8).collect();
+                            let chunk = LogChunk {
+                                source,
+                                data: frame,
+                            };
+                            return Some((Ok(chunk), (body, buffer, finished)));
+                        }
+                    }
+
+                    if finished {
+                        return None;
+                    }
+
+                    match body.next().await {
+                        Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                        Some(Err(e)) => {
+                            return Some((Err(e), (body, vec![], true)));
+                        }
+                        // An incomplete frame at the end of the body is dropped
+                        None => return None,
+                    }
+                }
+            },
+        );
+
+        frames.boxed()
+    }
+
+    /// Subscribe to container start, stop, die and health status events
+    ///
+    /// Only events for the named containers are returned, or all containers if names is empty.
+    /// The stream runs until the connection to docker is closed.
+    pub async fn events(&self, names: &[String]) -> Result<ContainerEvents, DockerError> {
+        let mut filters: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        filters.insert("type", vec!["container"]);
+        filters.insert("event", vec!["start", "stop", "die", "health_status"]);
+        if names.is_empty() == false {
+            filters.insert("container", names.iter().map(|n| n.as_str()).collect());
+        }
+        let filters = serde_json::to_string(&filters)?;
+        let path = format!(
+            "/events?filters={}",
+            utf8_percent_encode(&filters, NON_ALPHANUMERIC)
+        );
+
+        let mut response = self.request(hyper::Method::GET, &path, "").await?;
+        if response.status() != hyper::StatusCode::OK {
+            return Err(DockerError::Other(format!(
+                "Unable to subscribe to events, status was {}",
+                response.status()
+            )));
+        }
+
+        let events = DockerClient::json_lines::<EventMessage>(
+            "/events".into(),
+            std::mem::take(response.body_mut()),
+        )
+        .filter_map(|message| async move {
+            match message {
+                Ok(message) => ContainerEvent::from_message(message).map(Ok),
+                Err(e) => Some(Err(e)),
+            }
+        });
+
+        Ok(events.boxed())
+    }
+
+    /// Parse a json response body, on failure the error includes the endpoint and the start of
+    /// the response so unexpected responses from docker can be diagnosed from the logs
+    fn parse_json<T: DeserializeOwned>(endpoint: &str, body: &[u8]) -> Result<T, DockerError> {
+        serde_json::from_slice(body).map_err(|e| {
+            let body = String::from_utf8_lossy(body);
+            let mut snippet: String = body.chars().take(200).collect();
+            if body.chars().count() > 200 {
+                snippet.push_str("...");
+            }
+
+            DockerError::MalformedResponse(format!(
+                "Unable to parse response from {}: {}, response was:\n\t{}",
+                endpoint, e, snippet
+            )) as DockerError
+        })
+    }
+
+    /// Free space in bytes on the filesystem docker keeps images on, or None if docker is not
+    /// on this host
+    ///
+    /// Docker does not report free space (/system/df only covers what docker itself uses) so
+    /// the docker root dir from /info is looked up on the local filesystem, which is only
+    /// possible when docker is reached through a local unix socket
+    pub async fn free_space(&self) -> Result<Option<u64>, DockerError> {
+        if self.is_local() == false {
+            return Ok(None);
+        }
+
+        let info = self.system_info().await?;
+        Ok(filesystem_free_space(&info.docker_root_dir))
+    }
+
+    /// Version of the docker engine from /version
+    pub async fn version(&self) -> Result<EngineVersion, DockerError> {
+        DockerClient::parse_json("/version", &self.get_request("/version").await?)
+    }
+
+    /// Information on the docker engine from /info
+    pub async fn system_info(&self) -> Result<SystemInfo, DockerError> {
+        DockerClient::parse_json("/info", &self.get_request("/info").await?)
+    }
+
+    /// Remove an image by name or id, untagged layers it leaves behind are removed with it.
+    /// Fails with a Conflict if the image is used by a container or has other tags, unless
+    /// forced
+    pub async fn remove_image(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        let mut response = self
+            .request(
+                hyper::Method::DELETE,
+                &format!("/images/{}?force={}", name, force),
+                "",
+            )
+            .await?;
+
+        match response.status() {
+            hyper::StatusCode::OK => Ok(()),
+            hyper::StatusCode::NOT_FOUND => {
+                Err(DockerError::NotFound(format!("Image {} not found", name)))
+            }
+            hyper::StatusCode::CONFLICT => {
+                let body = hyper::body::to_bytes(response.body_mut()).await?;
+                Err(DockerError::Conflict(format!(
+                    "Unable to remove image {}: {}",
+                    name,
+                    String::from_utf8_lossy(&body).trim()
+                )))
+            }
+            status => Err(DockerError::Other(format!(
+                "Unable to remove image {}, status was {}",
+                name, status
+            ))),
+        }
+    }
+
+    /// Returns the id of the named image, or None if there is no such image
+    pub async fn get_image_id(&self, name: &str) -> Result<Option<String>, DockerError> {
+        Ok(self.inspect_image(name).await?.map(|i| i.id))
+    }
+
+    /// Gets information on an image, or None if there is no image with the name
+    pub async fn inspect_image(&self, name: &str) -> Result<Option<InspectImage>, DockerError> {
+        let path = format!("/images/{}/json", name);
+        let mut response = self.request(hyper::Method::GET, &path, "").await?;
+        if response.status() == hyper::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        let inspection: InspectImage = DockerClient::parse_json(&path, &body)?;
+        Ok(Some(inspection))
     }
 
-    /// Process uri to get scheme - TODO: a lot!
-    fn get_uri_scheme(_address: &str) -> &str {
-        return "unix";
+    /// Get the layer history of an image, or None if there is no image with the name
+    pub async fn image_history(
+        &self,
+        name: &str,
+    ) -> Result<Option<Vec<ImageHistory>>, DockerError> {
+        let path = format!("/images/{}/history", name);
+        let mut response = self.request(hyper::Method::GET, &path, "").await?;
+        if response.status() == hyper::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let body = hyper::body::to_bytes(response.body_mut()).await?;
+        let history: Vec<ImageHistory> = DockerClient::parse_json(&path, &body)?;
+        Ok(Some(history))
     }
 
     async fn rename_image(
-        &mut self,
+        &self,
         existing_name: &str,
         new_name: &str,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        overwrite: bool,
+    ) -> Result<(), DockerError> {
         let split: Vec<&str> = new_name.split(":").collect();
         if split.len() != 2 {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Unable to determine repo and tag for provided new_name",
-            )));
+            return Err(DockerError::Other(
+                "Unable to determine repo and tag for provided new_name".into(),
+            ));
+        }
+
+        // Refuse to move the tag if it is already in use by a different image
+        if overwrite == false {
+            if let Some(current) = self.get_image_id(new_name).await? {
+                if self.get_image_id(existing_name).await? != Some(current) {
+                    return Err(DockerError::Conflict(format!(
+                        "{} already exists and refers to a different image",
+                        new_name
+                    )));
+                }
+            }
         }
         let repo = split[0];
         let tag = split[1];
@@ -364,15 +2134,342 @@ impl DockerClient {
             let response_bytes = hyper::body::to_bytes(response.body_mut())
                 .await
                 .unwrap_or(Bytes::default());
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Unable to tag image, response was:\n\t{}",
-                    String::from_utf8(response_bytes.to_vec())
-                        .into_iter()
-                        .collect::<String>()
-                ),
+            return Err(DockerError::Other(format!(
+                "Unable to tag image, response was:\n\t{}",
+                String::from_utf8(response_bytes.to_vec())
+                    .into_iter()
+                    .collect::<String>()
             )));
         })
     }
 }
+
+#[rocket::async_trait]
+impl ContainerRuntime for DockerClient {
+    async fn get_images(&self) -> Result<Vec<ImageList>, DockerError> {
+        DockerClient::get_images(self).await
+    }
+
+    async fn get_containers(
+        &self,
+        filters: &[(&str, &str)],
+    ) -> Result<Vec<RunningContainer>, DockerError> {
+        DockerClient::get_containers(self, filters).await
+    }
+
+    async fn inspect_running_container(&self, id: &str) -> Result<InspectContainer, DockerError> {
+        DockerClient::inspect_running_container(self, id).await
+    }
+
+    async fn inspect_container_json(&self, id: &str) -> Result<serde_json::Value, DockerError> {
+        DockerClient::inspect_container_json(self, id).await
+    }
+
+    async fn load_container_image(
+        &self,
+        filename: &str,
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        DockerClient::load_container_image(self, filename, select, new_name, overwrite, progress)
+            .await
+    }
+
+    async fn load_container_image_stream(
+        &self,
+        image: &mut (dyn AsyncRead + Send + Unpin),
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        DockerClient::load_container_image_stream(
+            self, image, select, new_name, overwrite, progress,
+        )
+        .await
+    }
+
+    async fn pull_container_image(
+        &self,
+        image: &str,
+        auth: Option<&AuthConfig>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        DockerClient::pull_container_image(self, image, auth, new_name, overwrite, progress).await
+    }
+
+    async fn build_image(
+        &self,
+        context: &mut (dyn AsyncRead + Send + Unpin),
+        dockerfile: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError> {
+        DockerClient::build_image(self, context, dockerfile, new_name, overwrite, progress).await
+    }
+
+    async fn version(&self) -> Result<EngineVersion, DockerError> {
+        DockerClient::version(self).await
+    }
+
+    async fn system_info(&self) -> Result<SystemInfo, DockerError> {
+        DockerClient::system_info(self).await
+    }
+
+    async fn free_space(&self) -> Result<Option<u64>, DockerError> {
+        DockerClient::free_space(self).await
+    }
+
+    async fn remove_image(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        DockerClient::remove_image(self, name, force).await
+    }
+
+    async fn tag_image(&self, name: &str, new_name: &str) -> Result<(), DockerError> {
+        self.rename_image(name, new_name, true).await
+    }
+
+    async fn inspect_image(&self, name: &str) -> Result<Option<InspectImage>, DockerError> {
+        DockerClient::inspect_image(self, name).await
+    }
+
+    async fn image_history(&self, name: &str) -> Result<Option<Vec<ImageHistory>>, DockerError> {
+        DockerClient::image_history(self, name).await
+    }
+
+    async fn save_image(&self, name: &str, filename: &str) -> Result<(), DockerError> {
+        DockerClient::save_image(self, name, filename).await
+    }
+
+    async fn events(&self, names: &[String]) -> Result<ContainerEvents, DockerError> {
+        DockerClient::events(self, names).await
+    }
+
+    async fn stats(&self, id: &str) -> Result<ContainerStats, DockerError> {
+        DockerClient::stats(self, id).await
+    }
+
+    async fn list_volumes(&self) -> Result<Vec<Volume>, DockerError> {
+        DockerClient::list_volumes(self).await
+    }
+
+    async fn create_volume(&self, volume: &CreateVolume) -> Result<Volume, DockerError> {
+        DockerClient::create_volume(self, volume).await
+    }
+
+    async fn remove_volume(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        DockerClient::remove_volume(self, name, force).await
+    }
+
+    async fn list_networks(&self) -> Result<Vec<Network>, DockerError> {
+        DockerClient::list_networks(self).await
+    }
+
+    async fn create_network(&self, network: &CreateNetwork) -> Result<String, DockerError> {
+        DockerClient::create_network(self, network).await
+    }
+
+    async fn connect_network(
+        &self,
+        network: &str,
+        container: &str,
+        aliases: &[String],
+    ) -> Result<(), DockerError> {
+        DockerClient::connect_network(self, network, container, aliases).await
+    }
+
+    async fn exec(&self, id: &str, command: &[String]) -> Result<ExecResult, DockerError> {
+        DockerClient::exec(self, id, command).await
+    }
+
+    async fn get_logs(
+        &self,
+        id: &str,
+        tail: Option<usize>,
+        since: Option<i64>,
+        follow: bool,
+    ) -> Result<LogChunks, DockerError> {
+        DockerClient::get_logs(self, id, tail, since, follow).await
+    }
+
+    async fn attach(&self, id: &str) -> Result<Attached, DockerError> {
+        DockerClient::attach(self, id).await
+    }
+
+    async fn run_container(
+        &self,
+        name: &str,
+        image: &str,
+        args: Vec<&str>,
+    ) -> Result<(), DockerError> {
+        self.create_and_start(name, image, args).await?;
+        Ok(())
+    }
+
+    async fn start(&self, id: &str) -> Result<(), DockerError> {
+        DockerClient::start(self, id).await
+    }
+
+    async fn stop_running_container(&self, id: &str) -> Result<(), DockerError> {
+        DockerClient::stop_running_container(self, id).await
+    }
+
+    async fn restart_container(&self, id: &str, timeout: Option<u64>) -> Result<(), DockerError> {
+        DockerClient::restart_container(self, id, timeout).await
+    }
+
+    async fn wait_container(&self, id: &str) -> Result<i64, DockerError> {
+        DockerClient::wait_container(self, id).await
+    }
+
+    async fn kill_container(&self, id: &str, signal: &str) -> Result<(), DockerError> {
+        DockerClient::kill_container(self, id, signal).await
+    }
+
+    async fn update_resources(
+        &self,
+        id: &str,
+        update: &UpdateContainer,
+    ) -> Result<(), DockerError> {
+        DockerClient::update_resources(self, id, update).await
+    }
+
+    async fn get_archive(&self, id: &str, path: &str, filename: &str) -> Result<(), DockerError> {
+        DockerClient::get_archive(self, id, path, filename).await
+    }
+
+    async fn put_archive(
+        &self,
+        id: &str,
+        path: &str,
+        archive: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), DockerError> {
+        DockerClient::put_archive(self, id, path, archive).await
+    }
+
+    async fn rename_container(&self, id: &str, name: &str) -> Result<(), DockerError> {
+        DockerClient::rename_container(self, id, name).await
+    }
+
+    async fn set_paused(&self, id: &str, paused: bool) -> Result<(), DockerError> {
+        DockerClient::set_paused(self, id, paused).await
+    }
+
+    async fn remove_stopped_container(&self, id: &str) -> Result<(), DockerError> {
+        DockerClient::remove_stopped_container(self, id).await
+    }
+}
+
+/// Run a docker API call, failing with a Timeout if it does not complete in time
+pub async fn with_timeout<T>(
+    operation: &str,
+    after: std::time::Duration,
+    call: impl std::future::Future<Output = Result<T, DockerError>>,
+) -> Result<T, DockerError> {
+    match tokio::time::timeout(after, call).await {
+        Ok(result) => result,
+        Err(_) => Err(DockerError::Timeout {
+            operation: operation.to_owned(),
+            after,
+        }),
+    }
+}
+
+/// Free space in bytes available to unprivileged processes on the filesystem holding path
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // statvfs field types differ between targets
+pub fn filesystem_free_space(path: &str) -> Option<u64> {
+    let path = std::ffi::CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+pub fn filesystem_free_space(_path: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docker_socket_schemes() {
+        assert_eq!(
+            DockerSocket::parse("/var/run/docker.sock"),
+            Ok(DockerSocket::Unix("/var/run/docker.sock".into()))
+        );
+        assert_eq!(
+            DockerSocket::parse("unix:///var/run/docker.sock"),
+            Ok(DockerSocket::Unix("/var/run/docker.sock".into()))
+        );
+        assert_eq!(
+            DockerSocket::parse("npipe:////./pipe/docker_engine"),
+            Ok(DockerSocket::NamedPipe("\\\\.\\pipe\\docker_engine".into()))
+        );
+        assert_eq!(
+            DockerSocket::parse("ssh://deploy@edge:2222"),
+            Ok(DockerSocket::Ssh {
+                destination: "deploy@edge".into(),
+                port: Some(2222)
+            })
+        );
+        assert_eq!(
+            DockerSocket::parse("ssh://edge/"),
+            Ok(DockerSocket::Ssh {
+                destination: "edge".into(),
+                port: None
+            })
+        );
+    }
+
+    #[test]
+    fn docker_socket_tcp_ports() {
+        assert_eq!(
+            DockerSocket::parse("tcp://docker"),
+            Ok(DockerSocket::Tcp("docker:2375".into()))
+        );
+        assert_eq!(
+            DockerSocket::parse("http://docker:2376/"),
+            Ok(DockerSocket::Tcp("docker:2376".into()))
+        );
+        assert_eq!(
+            DockerSocket::parse("tcp://[::1]:2376"),
+            Ok(DockerSocket::Tcp("[::1]:2376".into()))
+        );
+    }
+
+    #[test]
+    fn malformed_docker_sockets() {
+        for address in [
+            "unix://",
+            "npipe://",
+            "npipe:////",
+            "tcp://",
+            "tcp://:2375",
+            "tcp://docker:",
+            "tcp://docker:port",
+            "tcp://docker:70000",
+            "tcp://docker:2375/v1.41",
+            "ssh://deploy@",
+            "ssh://deploy@:22",
+            "ssh://edge/home/deploy",
+            "ftp://docker",
+            "://docker",
+        ] {
+            assert!(
+                DockerSocket::parse(address).is_err(),
+                "{} should not parse",
+                address
+            );
+        }
+    }
+}
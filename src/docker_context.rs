@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::docker_client::TlsFiles;
+
+/// The parts of a docker CLI context we use, from contexts/meta/<id>/meta.json
+#[derive(Debug, Deserialize)]
+struct ContextMeta {
+    #[serde(alias = "Name")]
+    name: String,
+    #[serde(alias = "Endpoints", default)]
+    endpoints: BTreeMap<String, ContextEndpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContextEndpoint {
+    #[serde(alias = "Host")]
+    host: Option<String>,
+    #[serde(alias = "SkipTLSVerify", default)]
+    skip_tls_verify: bool,
+}
+
+/// Directory the docker CLI keeps its configuration in, $DOCKER_CONFIG or ~/.docker
+fn docker_config_dir() -> Result<PathBuf, String> {
+    if let Ok(dir) = std::env::var("DOCKER_CONFIG") {
+        return Ok(PathBuf::from(dir));
+    }
+
+    match std::env::var("HOME") {
+        Ok(home) => Ok(PathBuf::from(home).join(".docker")),
+        Err(_) => Err("Unable to find the docker config directory, HOME is not set".into()),
+    }
+}
+
+/// Resolve a named docker CLI context (as created with `docker context create`) to its
+/// docker endpoint and, if the context has them, the TLS files used to connect to it
+///
+/// Context directories are named by a hash of the context name, rather than hashing the
+/// name each meta.json is read to find the one with a matching name
+pub fn resolve(name: &str) -> Result<(String, Option<TlsFiles>), String> {
+    let contexts = docker_config_dir()?.join("contexts");
+    let meta_dir = contexts.join("meta");
+
+    let entries = std::fs::read_dir(&meta_dir).map_err(|e| {
+        format!(
+            "Unable to read docker contexts ({}): {}",
+            meta_dir.display(),
+            e
+        )
+    })?;
+
+    for entry in entries.flatten() {
+        let meta_file = entry.path().join("meta.json");
+        let meta: ContextMeta = match std::fs::read_to_string(&meta_file)
+            .ok()
+            .and_then(|m| serde_json::from_str(&m).ok())
+        {
+            Some(meta) => meta,
+            None => continue,
+        };
+        if meta.name != name {
+            continue;
+        }
+
+        let endpoint = meta
+            .endpoints
+            .get("docker")
+            .ok_or(format!("docker context '{}' has no docker endpoint", name))?;
+        let host = endpoint
+            .host
+            .to_owned()
+            .ok_or(format!("docker context '{}' has no docker host", name))?;
+        if endpoint.skip_tls_verify {
+            return Err(format!(
+                "docker context '{}' skips TLS verification, which is not supported",
+                name
+            ));
+        }
+
+        let tls = tls_files(&contexts.join("tls").join(entry.file_name()).join("docker"));
+        return Ok((host, tls));
+    }
+
+    Err(format!("docker context '{}' does not exist", name))
+}
+
+/// TLS material stored with the context, the CLI names the files ca.pem, cert.pem and key.pem
+fn tls_files(dir: &Path) -> Option<TlsFiles> {
+    let files = TlsFiles {
+        cert: dir.join("cert.pem").to_string_lossy().into(),
+        key: dir.join("key.pem").to_string_lossy().into(),
+        ca: dir.join("ca.pem").to_string_lossy().into(),
+    };
+
+    match dir.join("cert.pem").exists() {
+        true => Some(files),
+        false => None,
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Errors returned by the docker runtime
+///
+/// The variants separate the failures the API reports differently, anything which is not
+/// one of them is Other with a description of what went wrong
+#[derive(Debug, Error)]
+pub enum DockerError {
+    /// The container, image, volume, network or exec instance does not exist
+    #[error("{0}")]
+    NotFound(String),
+    /// The request conflicts with the current state, i.e. a name or tag already in use
+    #[error("{0}")]
+    Conflict(String),
+    /// Docker could not be reached, or dropped the connection
+    #[error("Unable to reach docker: {0}")]
+    DaemonUnreachable(#[source] hyper::Error),
+    /// Docker did not respond within the configured timeout
+    #[error("Docker request {operation} timed out after {}s", .after.as_secs())]
+    Timeout { operation: String, after: Duration },
+    /// Docker responded with something which could not be understood
+    #[error("{0}")]
+    MalformedResponse(String),
+    /// The request could not be built, i.e. unsupported run args
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// An image to load is not a tarball, or is compressed in a format docker does not support
+    #[error("{0}")]
+    UnsupportedImage(String),
+    /// The image to deploy from a tarball holding several images was not chosen, or is not
+    /// one of the images in it
+    #[error("{0}")]
+    ImageSelection(String),
+    /// Docker does not have enough free disk space for an image
+    #[error("{0}")]
+    InsufficientStorage(String),
+    /// Reading or writing a local file, i.e. an image tarball
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// An error from the HTTP connection to docker other than it being unreachable
+    #[error(transparent)]
+    Http(hyper::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl DockerError {
+    /// Short name of the variant, used to count errors by kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DockerError::NotFound(_) => "not_found",
+            DockerError::Conflict(_) => "conflict",
+            DockerError::DaemonUnreachable(_) => "unreachable",
+            DockerError::Timeout { .. } => "timeout",
+            DockerError::MalformedResponse(_) => "malformed_response",
+            DockerError::InvalidRequest(_) => "invalid_request",
+            DockerError::UnsupportedImage(_) => "unsupported_image",
+            DockerError::ImageSelection(_) => "image_selection",
+            DockerError::InsufficientStorage(_) => "insufficient_storage",
+            DockerError::Io(_) => "io",
+            DockerError::Http(_) => "http",
+            DockerError::Other(_) => "other",
+        }
+    }
+}
+
+impl From<hyper::Error> for DockerError {
+    fn from(e: hyper::Error) -> Self {
+        if e.is_connect() || e.is_incomplete_message() {
+            return DockerError::DaemonUnreachable(e);
+        }
+        DockerError::Http(e)
+    }
+}
+
+impl From<hyper::http::Error> for DockerError {
+    fn from(e: hyper::http::Error) -> Self {
+        DockerError::InvalidRequest(e.to_string())
+    }
+}
+
+impl From<hyper::http::uri::InvalidUri> for DockerError {
+    fn from(e: hyper::http::uri::InvalidUri) -> Self {
+        DockerError::InvalidRequest(e.to_string())
+    }
+}
+
+/// Request bodies which fail to serialize
+impl From<serde_json::Error> for DockerError {
+    fn from(e: serde_json::Error) -> Self {
+        DockerError::InvalidRequest(e.to_string())
+    }
+}
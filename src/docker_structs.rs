@@ -1,4 +1,7 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize)]
 pub struct ImageList {
@@ -6,6 +9,49 @@ pub struct ImageList {
     pub id: String,
     #[serde(alias = "RepoTags")]
     pub repo_tags: Vec<String>,
+    #[serde(alias = "RepoDigests", default)]
+    pub repo_digests: Option<Vec<String>>,
+    /// Unix timestamp
+    #[serde(alias = "Created", default)]
+    pub created: i64,
+    /// Bytes, including layers shared with other images
+    #[serde(alias = "Size", default)]
+    pub size: i64,
+}
+
+/// The parts of /info we use
+#[derive(Debug, Deserialize)]
+pub struct SystemInfo {
+    /// Where docker keeps images, containers and volumes, i.e. /var/lib/docker
+    #[serde(alias = "DockerRootDir")]
+    pub docker_root_dir: String,
+    /// Includes "name=rootless" when docker runs rootless
+    #[serde(alias = "SecurityOptions", default)]
+    pub security_options: Vec<String>,
+    /// "1" or "2"
+    #[serde(alias = "CgroupVersion", default)]
+    pub cgroup_version: Option<String>,
+    #[serde(alias = "MemoryLimit", default)]
+    pub memory_limit: bool,
+    #[serde(alias = "SwapLimit", default)]
+    pub swap_limit: bool,
+    #[serde(alias = "CpuCfsQuota", default)]
+    pub cpu_cfs_quota: bool,
+    #[serde(alias = "PidsLimit", default)]
+    pub pids_limit: bool,
+    /// Containers keep running while the docker daemon restarts
+    #[serde(alias = "LiveRestoreEnabled", default)]
+    pub live_restore_enabled: bool,
+}
+
+/// The parts of /version we use
+#[derive(Debug, Deserialize)]
+pub struct EngineVersion {
+    #[serde(alias = "Version")]
+    pub version: String,
+    /// Newest API version the engine supports, i.e. "1.43"
+    #[serde(alias = "ApiVersion")]
+    pub api_version: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -16,14 +62,59 @@ pub struct RunningContainer {
     pub names: Vec<String>,
     #[serde(alias = "Image")]
     pub image: String,
+    #[serde(alias = "ImageID", default)]
+    pub image_id: String,
     #[serde(alias = "State")]
     pub state: String,
+    #[serde(alias = "Labels", default)]
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(alias = "Ports", default)]
+    pub ports: Vec<ListPort>,
+}
+
+/// A container port in the container list, public_port is set when it is published
+#[derive(Debug, Deserialize)]
+pub struct ListPort {
+    #[serde(alias = "PublicPort", default)]
+    pub public_port: Option<u16>,
+    #[serde(alias = "Type", default)]
+    pub protocol: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct InspectContainer {
+    #[serde(alias = "Id", default)]
+    pub id: String,
+    /// RFC 3339 timestamp
+    #[serde(alias = "Created", default)]
+    pub created: String,
+    /// Id of the image the container was created from
+    #[serde(alias = "Image", default)]
+    pub image: String,
     #[serde(alias = "State")]
     pub state: InspectContainerState,
+    #[serde(alias = "Config")]
+    pub config: Option<InspectContainerConfig>,
+    /// Times docker has restarted the container under its restart policy
+    #[serde(alias = "RestartCount", default)]
+    pub restart_count: i64,
+    #[serde(alias = "NetworkSettings")]
+    pub network_settings: Option<InspectNetworkSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InspectNetworkSettings {
+    /// Container ports, i.e. "80/tcp", with the host ports they are published on, null for
+    /// ports which are not published
+    #[serde(alias = "Ports")]
+    pub ports: Option<BTreeMap<String, Option<Vec<PortBinding>>>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InspectContainerConfig {
+    /// Logs (and attach) are not multiplexed when the container has a TTY
+    #[serde(alias = "Tty", default)]
+    pub tty: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,6 +123,15 @@ pub struct InspectContainerState {
     pub health: Option<InspectContainerStateHealth>,
     #[serde(alias = "Running")]
     pub running: bool,
+    /// RFC 3339 timestamps, "0001-01-01T00:00:00Z" if the container has never started (or
+    /// finished)
+    #[serde(alias = "StartedAt", default)]
+    pub started_at: String,
+    #[serde(alias = "FinishedAt", default)]
+    pub finished_at: String,
+    /// Exit code of the last run
+    #[serde(alias = "ExitCode", default)]
+    pub exit_code: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,14 +141,142 @@ pub struct InspectContainerStateHealth {
 }
 
 #[derive(Debug, Deserialize)]
-pub struct LoadImageResult {
-    #[serde(alias = "Stream")]
-    pub stream: String,
+pub struct InspectImage {
+    #[serde(alias = "Id")]
+    pub id: String,
+    /// repo@digest for each registry the image was pulled from, null for local images
+    #[serde(alias = "RepoDigests")]
+    pub repo_digests: Option<Vec<String>>,
+    #[serde(alias = "RepoTags")]
+    pub repo_tags: Option<Vec<String>>,
+    /// RFC 3339 timestamp, i.e. "2024-01-31T10:12:45.123456789Z"
+    #[serde(alias = "Created", default)]
+    pub created: String,
+    /// Size in bytes
+    #[serde(alias = "Size", default)]
+    pub size: i64,
+    #[serde(alias = "Config")]
+    pub config: Option<InspectImageConfig>,
+    #[serde(alias = "RootFS")]
+    pub root_fs: Option<RootFs>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct PullImageResult {
-    pub status: String,
+pub struct InspectImageConfig {
+    #[serde(alias = "Labels")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Ports declared with EXPOSE, i.e. "80/tcp", the values are always empty
+    #[serde(alias = "ExposedPorts")]
+    pub exposed_ports: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    #[serde(alias = "Healthcheck")]
+    pub healthcheck: Option<ImageHealthcheck>,
+    #[serde(alias = "Entrypoint")]
+    pub entrypoint: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageHealthcheck {
+    /// i.e. ["CMD-SHELL", "curl -f http://localhost/"], ["NONE"] when a check inherited from
+    /// the base image is disabled
+    #[serde(alias = "Test", default)]
+    pub test: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RootFs {
+    /// Layer digests, base layer first
+    #[serde(alias = "Layers", default)]
+    pub layers: Vec<String>,
+}
+
+/// One entry from /images/{name}/history, newest layer first
+#[derive(Debug, Deserialize)]
+pub struct ImageHistory {
+    /// Image id, "<missing>" for layers which were not built locally
+    #[serde(alias = "Id")]
+    pub id: String,
+    /// Unix timestamp
+    #[serde(alias = "Created")]
+    pub created: i64,
+    #[serde(alias = "CreatedBy")]
+    pub created_by: String,
+    #[serde(alias = "Tags")]
+    pub tags: Option<Vec<String>>,
+    #[serde(alias = "Size")]
+    pub size: i64,
+    #[serde(alias = "Comment", default)]
+    pub comment: String,
+}
+
+/// One message from the newline delimited json returned by /images/load, /images/create
+/// and /build
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ProgressMessage {
+    /// Output from a load, i.e. "Loaded image: nginx:latest\n"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    /// Pull status, i.e. "Pulling fs layer" or "Status: Downloaded newer image for nginx:latest"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Layer the status refers to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Progress bar text while a layer is downloading or extracting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Sent by a build once the image is built
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aux: Option<BuildResult>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BuildResult {
+    #[serde(alias = "ID")]
+    pub id: Option<String>,
+}
+
+impl ProgressMessage {
+    /// The id of the image a build message reports was built
+    pub fn built_image(&self) -> Option<String> {
+        self.aux.as_ref().and_then(|a| a.id.to_owned())
+    }
+
+    /// The images named in a load message, "Loaded image: <repo:tag>" for a tarball with
+    /// tags or "Loaded image ID: <id>" for one without, i.e. an OCI image layout without a
+    /// reference name annotation
+    pub fn loaded_images(&self) -> Vec<String> {
+        self.stream
+            .iter()
+            .flat_map(|s| s.lines())
+            .filter_map(|line| {
+                let line = line.trim();
+                line.strip_prefix("Loaded image: ")
+                    .or(line.strip_prefix("Loaded image ID: "))
+            })
+            .map(|image| image.to_owned())
+            .collect()
+    }
+
+    /// Human readable text of the message, ignoring progress bar updates
+    pub fn text(&self) -> Option<String> {
+        if self.progress.is_some() {
+            return None;
+        }
+
+        if let Some(status) = &self.status {
+            return match &self.id {
+                Some(id) => Some(format!("{}: {}", id, status)),
+                None => Some(status.to_owned()),
+            };
+        }
+
+        self.stream
+            .as_ref()
+            .map(|s| s.trim().to_owned())
+            .filter(|s| s.is_empty() == false)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -56,3 +284,522 @@ pub struct PullImage {
     #[serde(alias = "fromImage")]
     pub from_image: String,
 }
+
+/// A single sample from /containers/{id}/stats?stream=false, docker uses snake case here
+#[derive(Debug, Deserialize)]
+pub struct ContainerStats {
+    pub read: String,
+    pub cpu_stats: CpuStats,
+    /// The previous sample, used to calculate CPU usage over the sample period
+    pub precpu_stats: CpuStats,
+    pub memory_stats: MemoryStats,
+    /// Keyed by interface name, not present for containers without networking
+    pub networks: Option<BTreeMap<String, NetworkStats>>,
+    pub blkio_stats: BlkioStats,
+}
+
+impl ContainerStats {
+    /// CPU usage over the sample period as a percentage of one CPU, as reported by docker
+    /// stats (a container using two CPUs fully is at 200%)
+    pub fn cpu_percent(&self) -> f64 {
+        let cpu_delta = self
+            .cpu_stats
+            .cpu_usage
+            .total_usage
+            .saturating_sub(self.precpu_stats.cpu_usage.total_usage);
+        let system_delta = self
+            .cpu_stats
+            .system_cpu_usage
+            .unwrap_or(0)
+            .saturating_sub(self.precpu_stats.system_cpu_usage.unwrap_or(0));
+        if cpu_delta == 0 || system_delta == 0 {
+            return 0.0;
+        }
+
+        let cpus = self.cpu_stats.online_cpus.unwrap_or(1) as f64;
+        cpu_delta as f64 / system_delta as f64 * cpus * 100.0
+    }
+
+    /// Memory in use, less the page cache which can be reclaimed, as reported by docker stats
+    pub fn memory_used(&self) -> u64 {
+        let usage = self.memory_stats.usage.unwrap_or(0);
+        // total_inactive_file on cgroup v1, inactive_file on cgroup v2
+        let inactive = self.memory_stats.stats.as_ref().and_then(|s| {
+            s.get("total_inactive_file")
+                .or(s.get("inactive_file"))
+                .copied()
+        });
+        match inactive {
+            Some(inactive) if inactive < usage => usage - inactive,
+            _ => usage,
+        }
+    }
+
+    /// Bytes received and sent over all interfaces
+    pub fn network_bytes(&self) -> (u64, u64) {
+        self.networks
+            .iter()
+            .flat_map(|n| n.values())
+            .fold((0, 0), |(rx, tx), n| (rx + n.rx_bytes, tx + n.tx_bytes))
+    }
+
+    /// Bytes read from and written to block devices
+    pub fn block_bytes(&self) -> (u64, u64) {
+        self.blkio_stats
+            .io_service_bytes_recursive
+            .iter()
+            .flatten()
+            .fold((0, 0), |(read, write), entry| {
+                match entry.op.to_lowercase().as_str() {
+                    "read" => (read + entry.value, write),
+                    "write" => (read, write + entry.value),
+                    _ => (read, write),
+                }
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CpuStats {
+    pub cpu_usage: CpuUsage,
+    pub system_cpu_usage: Option<u64>,
+    pub online_cpus: Option<u32>,
+}
+
+/// CPU time used in nanoseconds
+#[derive(Debug, Deserialize)]
+pub struct CpuUsage {
+    pub total_usage: u64,
+}
+
+/// Memory use in bytes, the stats map contents differ between cgroup v1 and v2
+#[derive(Debug, Deserialize)]
+pub struct MemoryStats {
+    pub usage: Option<u64>,
+    pub limit: Option<u64>,
+    pub stats: Option<BTreeMap<String, u64>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlkioStats {
+    pub io_service_bytes_recursive: Option<Vec<BlkioEntry>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlkioEntry {
+    /// "read" or "write" (capitalised on cgroup v1)
+    pub op: String,
+    pub value: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Volume {
+    #[serde(alias = "Name")]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VolumeList {
+    #[serde(alias = "Volumes")]
+    pub volumes: Option<Vec<Volume>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateVolume {
+    pub name: String,
+    pub driver: String,
+    pub driver_opts: BTreeMap<String, String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Network {
+    #[serde(alias = "Name")]
+    pub name: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateNetwork {
+    pub name: String,
+    pub driver: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNetworkResult {
+    #[serde(alias = "Id")]
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ConnectNetwork {
+    pub container: String,
+    pub endpoint_config: EndpointConfig,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EndpointConfig {
+    /// Extra names the container can be reached by on the network
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateExec {
+    pub attach_stdout: bool,
+    pub attach_stderr: bool,
+    pub tty: bool,
+    pub cmd: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExecResult {
+    #[serde(alias = "Id")]
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StartExec {
+    pub detach: bool,
+    pub tty: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InspectExec {
+    #[serde(alias = "ExitCode")]
+    pub exit_code: Option<i64>,
+}
+
+/// Output of a command run in a container
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i64>,
+    /// More output than EXEC_OUTPUT_LIMIT was written to stdout or stderr, and discarded
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogSource {
+    Stdout,
+    Stderr,
+}
+
+/// Part of a container's log output, from one of stdout or stderr
+#[derive(Debug, Clone)]
+pub struct LogChunk {
+    pub source: LogSource,
+    pub data: Vec<u8>,
+}
+
+/// A message from the /events stream
+#[derive(Debug, Deserialize)]
+pub struct EventMessage {
+    #[serde(alias = "Type")]
+    pub event_type: String,
+    #[serde(alias = "Action")]
+    pub action: String,
+    #[serde(alias = "Actor")]
+    pub actor: EventActor,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventActor {
+    #[serde(alias = "ID")]
+    pub id: String,
+    #[serde(alias = "Attributes", default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContainerEventKind {
+    Start,
+    Stop,
+    Die {
+        exit_code: Option<i64>,
+    },
+    /// The new health status, i.e. "healthy"
+    Health(String),
+}
+
+/// A container event of interest to the manager
+#[derive(Debug, Clone)]
+pub struct ContainerEvent {
+    pub id: String,
+    /// Container name, without the leading /
+    pub name: String,
+    pub kind: ContainerEventKind,
+}
+
+impl ContainerEvent {
+    /// Convert a raw event message, returns None for events which are not of interest
+    pub fn from_message(message: EventMessage) -> Option<Self> {
+        if message.event_type != "container" {
+            return None;
+        }
+
+        let kind = match message.action.as_str() {
+            "start" => ContainerEventKind::Start,
+            "stop" => ContainerEventKind::Stop,
+            "die" => ContainerEventKind::Die {
+                exit_code: message
+                    .actor
+                    .attributes
+                    .get("exitCode")
+                    .and_then(|c| c.parse().ok()),
+            },
+            action => {
+                ContainerEventKind::Health(action.strip_prefix("health_status:")?.trim().to_owned())
+            }
+        };
+
+        Some(ContainerEvent {
+            id: message.actor.id,
+            name: message
+                .actor
+                .attributes
+                .get("name")
+                .cloned()
+                .unwrap_or_default(),
+            kind,
+        })
+    }
+}
+
+/// Registry credentials sent base64 encoded in the X-Registry-Auth header
+#[derive(Debug, Default, Serialize)]
+pub struct AuthConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identitytoken: Option<String>,
+    pub serveraddress: String,
+}
+
+/// Body for /containers/create, built from the deployment args by ./src/run_args.rs
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateContainer {
+    pub image: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub exposed_ports: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_timeout: Option<i64>,
+    pub tty: bool,
+    pub open_stdin: bool,
+    pub host_config: HostConfig,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HostConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub binds: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub port_bindings: BTreeMap<String, Vec<PortBinding>>,
+    pub publish_all_ports: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_policy: Option<RestartPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_mode: Option<String>,
+    pub privileged: bool,
+    pub readonly_rootfs: bool,
+    pub auto_remove: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cap_add: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cap_drop: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceMapping>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra_hosts: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dns: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_config: Option<LogConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nano_cpus: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<Mount>,
+    /// Path in the container to the tmpfs mount options, i.e. "rw,size=64m"
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tmpfs: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ulimits: Vec<Ulimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shm_size: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub security_opt: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub sysctls: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub device_requests: Vec<DeviceRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipc_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_swap: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_reservation: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_shares: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpuset_cpus: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub group_add: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dns_search: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Mount {
+    /// bind, volume or tmpfs
+    #[serde(rename = "Type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub target: String,
+    pub read_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_options: Option<MountBindOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tmpfs_options: Option<MountTmpfsOptions>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MountBindOptions {
+    pub propagation: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MountTmpfsOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+/// A request for devices from a device driver, i.e. GPUs through --gpus
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeviceRequest {
+    pub driver: String,
+    /// -1 for all devices
+    pub count: i64,
+    #[serde(rename = "DeviceIDs", skip_serializing_if = "Vec::is_empty")]
+    pub device_ids: Vec<String>,
+    pub capabilities: Vec<Vec<String>>,
+}
+
+/// Body for /containers/{id}/update, limits which are not set are left unchanged
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UpdateContainer {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_swap: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nano_cpus: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PortBinding {
+    #[serde(default)]
+    pub host_ip: String,
+    #[serde(default)]
+    pub host_port: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RestartPolicy {
+    pub name: String,
+    pub maximum_retry_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeviceMapping {
+    pub path_on_host: String,
+    pub path_in_container: String,
+    pub cgroup_permissions: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LogConfig {
+    #[serde(rename = "Type")]
+    pub driver: String,
+    pub config: BTreeMap<String, String>,
+}
+
+/// Response from /containers/{id}/wait once the container has exited
+#[derive(Debug, Deserialize)]
+pub struct WaitResult {
+    #[serde(alias = "StatusCode")]
+    pub status_code: i64,
+    #[serde(alias = "Error")]
+    pub error: Option<WaitError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WaitError {
+    #[serde(alias = "Message")]
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateContainerResult {
+    #[serde(alias = "Id")]
+    pub id: String,
+    #[serde(alias = "Warnings")]
+    pub warnings: Option<Vec<String>>,
+}
@@ -0,0 +1,231 @@
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use base64::Engine;
+use hyper::body::HttpBody;
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::{Body, Client, Request, Uri};
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+
+/// Largest CONNECT response accepted from a proxy, the response is only a status line and a
+/// few headers
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+
+/// A http proxy downloads are sent through, from `http://[user:password@]host[:port]`
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    host: String,
+    port: u16,
+    /// Proxy-Authorization header value, for proxies which need credentials
+    authorization: Option<String>,
+}
+
+impl Proxy {
+    pub fn parse(url: &str) -> Result<Proxy, String> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|e| format!("Invalid https_proxy ({}): {}", url, e))?;
+        if uri.scheme_str() != Some("http") {
+            return Err(format!(
+                "https_proxy ({}) must be a http:// url, TLS to the proxy is not supported",
+                url
+            ));
+        }
+        let authority = uri
+            .authority()
+            .ok_or(format!("https_proxy ({}) has no host", url))?;
+
+        let authorization = authority.as_str().rsplit_once('@').map(|(userinfo, _)| {
+            let userinfo = percent_decode_str(userinfo).decode_utf8_lossy();
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(userinfo.as_bytes())
+            )
+        });
+
+        Ok(Proxy {
+            host: authority.host().to_owned(),
+            port: authority.port_u16().unwrap_or(80),
+            authorization,
+        })
+    }
+}
+
+/// Connects through a http proxy. https urls are tunnelled with CONNECT so TLS is still
+/// negotiated with the server, plain http requests are sent to the proxy with the full url
+#[derive(Clone)]
+struct ProxyConnector {
+    proxy: Proxy,
+}
+
+/// The connection to the proxy, proxied is set when requests must be sent with the full url
+struct ProxyStream {
+    stream: TcpStream,
+    proxied: bool,
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = ProxyStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<ProxyStream, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let proxy = self.proxy.clone();
+        Box::pin(async move {
+            let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port)).await?;
+            if uri.scheme_str() != Some("https") {
+                return Ok(ProxyStream {
+                    stream,
+                    proxied: true,
+                });
+            }
+
+            let host = uri.host().ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "url has no host",
+            ))?;
+            let target = format!("{}:{}", host, uri.port_u16().unwrap_or(443));
+            let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+            if let Some(authorization) = &proxy.authorization {
+                request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).await?;
+
+            // Read the response a byte at a time so nothing after it (the start of the TLS
+            // handshake) is consumed
+            let mut response = vec![];
+            while response.ends_with(b"\r\n\r\n") == false {
+                if response.len() >= MAX_CONNECT_RESPONSE {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Proxy CONNECT response is too large",
+                    ));
+                }
+                let mut byte = [0u8; 1];
+                if stream.read(&mut byte).await? == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Proxy closed the connection during CONNECT",
+                    ));
+                }
+                response.push(byte[0]);
+            }
+
+            let response = String::from_utf8_lossy(&response);
+            let status_line = response.lines().next().unwrap_or_default();
+            if status_line.split_whitespace().nth(1) != Some("200") {
+                return Err(io::Error::other(format!(
+                    "Proxy refused CONNECT to {}: {}",
+                    target, status_line
+                )));
+            }
+
+            Ok(ProxyStream {
+                stream,
+                proxied: false,
+            })
+        })
+    }
+}
+
+impl Connection for ProxyStream {
+    fn connected(&self) -> Connected {
+        Connected::new().proxy(self.proxied)
+    }
+}
+
+impl AsyncRead for ProxyStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ProxyStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Download a http(s) url to the given file, through the proxy if one is given
+///
+/// The response body is written to the file as it arrives so the whole image is never
+/// held in RAM
+pub async fn download_to_file(
+    url: &str,
+    filename: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let uri: Uri = url.parse()?;
+
+    let builder = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_or_http()
+        .enable_http1();
+
+    let mut response = match proxy {
+        Some(proxy) => {
+            // Credentials for a tunnel are sent with the CONNECT, plain http requests carry
+            // them themselves
+            let mut request = Request::get(uri.clone());
+            if let (Some(authorization), false) =
+                (&proxy.authorization, uri.scheme_str() == Some("https"))
+            {
+                request = request.header(hyper::header::PROXY_AUTHORIZATION, authorization);
+            }
+
+            let connector = builder.wrap_connector(ProxyConnector {
+                proxy: proxy.clone(),
+            });
+            let client: Client<_, Body> = Client::builder().build(connector);
+            client.request(request.body(Body::empty())?).await?
+        }
+        None => {
+            let client: Client<_, Body> = Client::builder().build(builder.build());
+            client.get(uri).await?
+        }
+    };
+
+    if response.status() != hyper::StatusCode::OK {
+        return Err(Box::new(std::io::Error::other(format!(
+            "Download of {} failed, status was {}",
+            url,
+            response.status()
+        ))));
+    }
+
+    let mut file = tokio::fs::File::create(filename).await?;
+    while let Some(chunk) = response.body_mut().data().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+
+    Ok(())
+}
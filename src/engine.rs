@@ -0,0 +1,54 @@
+use crate::config_file::Config;
+use crate::docker_error::DockerError;
+use crate::runtime::ContainerRuntime;
+
+/// Oldest docker API edd works with, 1.30 added the not-running condition edd waits for
+/// containers to stop with. NanoCpus and Init were added in 1.25 and healthchecks in 1.24
+pub const MIN_API_VERSION: (u32, u32) = (1, 30);
+
+/// Problems found with the docker engine when edd started
+///
+/// Empty when the engine meets every requirement. When the check is not strict edd starts
+/// with the problems reported as warnings and /v1/health reports degraded
+#[derive(Debug, Default)]
+pub struct EngineReport {
+    pub problems: Vec<String>,
+}
+
+/// Check the docker engine meets edd's requirements, errors are only returned when docker
+/// cannot be asked
+pub async fn check(
+    config: &Config,
+    docker: &dyn ContainerRuntime,
+) -> Result<EngineReport, DockerError> {
+    let version = docker.version().await?;
+    let info = docker.system_info().await?;
+
+    let mut problems = vec![];
+    match parse_api_version(&version.api_version) {
+        Some(api) if api >= MIN_API_VERSION => {}
+        Some(_) => problems.push(format!(
+            "Docker {} supports API {}, API {}.{} or newer is required",
+            version.version, version.api_version, MIN_API_VERSION.0, MIN_API_VERSION.1
+        )),
+        None => problems.push(format!(
+            "Docker {} reported an invalid API version ({})",
+            version.version, version.api_version
+        )),
+    }
+
+    if config.require_live_restore && info.live_restore_enabled == false {
+        problems.push(
+            "Docker live restore is disabled, set \"live-restore\": true in the docker daemon.json"
+                .into(),
+        );
+    }
+
+    Ok(EngineReport { problems })
+}
+
+/// Parse a major.minor API version
+fn parse_api_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
@@ -0,0 +1,147 @@
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Status};
+use rocket::serde::json::Value;
+use rocket::serde::Serialize;
+use rocket::{Data, Request, Response};
+
+/// Requests handled since the daemon started, for request ids
+static REQUEST_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Longest request id accepted from a client
+const MAX_REQUEST_ID: usize = 64;
+
+/// /v1/ routes which have no /v2/ equivalent, the OpenAPI description and Swagger UI
+const V1_ONLY: [&str; 2] = ["openapi.json", "docs"];
+
+/// Every /v2/ response body, other than streams, tarballs and WebSocket upgrades
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Envelope {
+    /// The /v1/ response body, null for errors
+    pub data: Value,
+    pub error: Option<EnvelopeError>,
+    pub request_id: String,
+}
+
+/// The /v1/ error body, with the message as the reason
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EnvelopeError {
+    pub status: u16,
+    pub code: String,
+    pub reason: String,
+    pub detail: Option<String>,
+}
+
+/// The id of a request, either the client's X-Request-Id or one generated for it
+struct RequestId(String);
+
+/// Adds an X-Request-Id header to every response, wraps /v2/ responses in an Envelope and
+/// marks /v1/ responses as deprecated in favour of their /v2/ equivalent
+pub struct Envelopes;
+
+#[rocket::async_trait]
+impl Fairing for Envelopes {
+    fn info(&self) -> Info {
+        Info {
+            name: "Response envelopes",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| request_id(request));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let id = id(request);
+        response.set_header(Header::new("X-Request-Id", id.clone()));
+
+        let path = request.uri().path();
+        if let Some(rest) = path.as_str().strip_prefix("/v1/") {
+            if V1_ONLY.iter().any(|p| rest.split('/').next() == Some(*p)) {
+                return;
+            }
+            response.set_header(Header::new("Deprecation", "true"));
+            response.set_header(Header::new(
+                "Link",
+                format!("</v2/{}>; rel=\"successor-version\"", rest),
+            ));
+            return;
+        }
+        if path.as_str().starts_with("/v2/") == false {
+            return;
+        }
+
+        // Streams, tarballs and upgrades have no preset size and are passed through as is
+        if response.status() == Status::SwitchingProtocols
+            || (response.body().is_none() == false && response.body().preset_size().is_none())
+        {
+            return;
+        }
+
+        let body = response.body_mut().to_string().await.unwrap_or_default();
+        let status = response.status();
+        let envelope = match status.class().is_success() {
+            true => Envelope {
+                // Most routes respond with JSON as a plain string
+                data: match body.is_empty() {
+                    true => Value::Object(Default::default()),
+                    false => rocket::serde::json::from_str(&body).unwrap_or(Value::String(body)),
+                },
+                error: None,
+                request_id: id,
+            },
+            false => {
+                let error: Value = rocket::serde::json::from_str(&body).unwrap_or_default();
+                let field = |name: &str| error.get(name).and_then(|v| v.as_str()).map(String::from);
+                Envelope {
+                    data: Value::Null,
+                    error: Some(EnvelopeError {
+                        status: status.code,
+                        code: field("code").unwrap_or_default(),
+                        reason: field("message")
+                            .unwrap_or_else(|| status.reason_lossy().to_owned()),
+                        detail: field("detail"),
+                    }),
+                    request_id: id,
+                }
+            }
+        };
+
+        let envelope = rocket::serde::json::to_string(&envelope).unwrap();
+        response.set_header(ContentType::JSON);
+        response.set_sized_body(envelope.len(), Cursor::new(envelope));
+    }
+}
+
+/// The id of the request, as returned in its X-Request-Id header
+pub fn id(request: &Request<'_>) -> String {
+    request.local_cache(|| request_id(request)).0.clone()
+}
+
+/// The client's X-Request-Id if it is short and printable, otherwise a new id
+fn request_id(request: &Request<'_>) -> RequestId {
+    if let Some(id) = request.headers().get_one("x-request-id") {
+        if id.is_empty() == false
+            && id.len() <= MAX_REQUEST_ID
+            && id.bytes().all(|b| b.is_ascii_graphic())
+        {
+            return RequestId(id.to_owned());
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    RequestId(format!(
+        "{:x}-{:x}",
+        now,
+        REQUEST_COUNT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
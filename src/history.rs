@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::authorization::Action;
+
+/// Entries kept for each deployment, the oldest are dropped first
+const MAX_ENTRIES: usize = 50;
+
+/// An image being loaded, pulled, built or rolled back to for a deployment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(as = DeploymentHistoryEntry)]
+pub struct HistoryEntry {
+    /// Unix timestamp of when the deployment was started with the image, or the attempt failed
+    pub time: u64,
+    pub action: Action,
+    pub client: String,
+    /// The repo:tag (or id) of the image as it was loaded, pulled or built
+    pub source_image: Option<String>,
+    /// Id (the sha256 content digest) of the image the deployment was started with
+    pub image_id: Option<String>,
+    /// Registry digest the image was pulled by
+    pub digest: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// The images each deployment has run, persisted in a JSON file so it survives restarts
+pub struct History {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, Vec<HistoryEntry>>>,
+}
+
+impl History {
+    /// Load the history from the file, which does not need to exist until the first load
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let entries = match std::fs::read(path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|e| format!("Unable to read history_file ({}): {}", path.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(format!(
+                    "Unable to read history_file ({}): {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+
+        Ok(History {
+            path: path.to_owned(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Add an entry to the deployment's history. Failing to save is logged rather than
+    /// failing the load it records
+    pub fn record(&self, deployment: &str, entry: HistoryEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        let history = entries.entry(deployment.to_owned()).or_default();
+        history.push(entry);
+        if history.len() > MAX_ENTRIES {
+            history.drain(..history.len() - MAX_ENTRIES);
+        }
+
+        if let Err(e) = self.save(&entries) {
+            println!(
+                "Unable to save history_file ({}): {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    /// The deployment's history, newest first
+    pub fn get(&self, deployment: &str) -> Vec<HistoryEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(deployment)
+            .map(|h| h.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Write to a temporary file and rename it over the history file, so the file is never
+    /// left part written
+    fn save(&self, entries: &BTreeMap<String, Vec<HistoryEntry>>) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temporary = self.path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_vec(entries)?)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
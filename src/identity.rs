@@ -0,0 +1,215 @@
+use std::fmt;
+use std::sync::Arc;
+
+use rocket::http::Status;
+use rocket::mtls::x509::{GeneralName, ParsedExtension};
+use rocket::mtls::Certificate;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+
+use crate::api_keys::ApiKeys;
+use crate::authorization::Grant;
+use crate::config_file::Config;
+
+/// Identity of the client making a request, taken from a bearer token or the mutual TLS peer
+/// certificate
+///
+/// A bearer token identifies the client by the token name, the identity claim of a JWT or the
+/// name of an API key, which also carries the key's grant. Invalid tokens are rejected with 401.
+/// For certificates the subject common name is preferred, falling back to the subject email,
+/// and the DNS, email and URI subject alternative names are kept for authorization rules.
+/// Clients which present neither are anonymous, or rejected with 401 when token
+/// authentication is enabled or API keys have been issued.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIdentity {
+    pub name: Option<String>,
+    /// Subject alternative names from the client certificate
+    pub alternative_names: Vec<String>,
+    /// What the client may do when it authenticated with an API key
+    pub grant: Option<Grant>,
+}
+
+impl ClientIdentity {
+    /// Whether the client is known by the name, either its name or an alternative name
+    pub fn is(&self, client: &str) -> bool {
+        self.name.as_deref() == Some(client) || self.alternative_names.iter().any(|n| n == client)
+    }
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "anonymous"),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIdentity {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        // Cached so the audit log can tell who made the request without authenticating again
+        match request.local_cache_async(identify(request)).await {
+            Ok(identity) => Outcome::Success(identity.clone()),
+            Err(status) => Outcome::Error((*status, ())),
+        }
+    }
+}
+
+async fn identify<'r>(request: &'r Request<'_>) -> Result<ClientIdentity, Status> {
+    let config = match request.rocket().state::<Arc<Config>>() {
+        Some(config) => config,
+        None => return Err(Status::InternalServerError),
+    };
+    let api_keys = request.rocket().state::<ApiKeys>();
+    let token_auth = config.token_auth.enabled();
+    let api_key_auth = api_keys.map(|k| k.is_empty() == false) == Some(true);
+
+    if let Some(authorization) = request.headers().get_one("Authorization") {
+        if token_auth || api_key_auth {
+            let token = authorization
+                .strip_prefix("Bearer ")
+                .or(authorization.strip_prefix("bearer "))
+                .map(|t| t.trim());
+            if let Some(key) = token.and_then(|t| api_keys.and_then(|k| k.authenticate(t))) {
+                return Ok(ClientIdentity {
+                    name: Some(key.name),
+                    alternative_names: vec![],
+                    grant: Some(key.grant),
+                });
+            }
+            return match token.and_then(|t| config.token_auth.authenticate(t)) {
+                Some(name) => Ok(ClientIdentity {
+                    name: Some(name),
+                    alternative_names: vec![],
+                    grant: None,
+                }),
+                None => {
+                    warn!(
+                        "Rejected invalid bearer token from {:?}",
+                        request.client_ip()
+                    );
+                    Err(Status::Unauthorized)
+                }
+            };
+        }
+    }
+
+    let (name, alternative_names) = match request.guard::<Certificate<'r>>().await {
+        Outcome::Success(certificate) => (
+            certificate
+                .subject()
+                .common_name()
+                .or_else(|| certificate.subject().email())
+                .map(|n| n.to_owned()),
+            alternative_names(&certificate),
+        ),
+        _ => (None, vec![]),
+    };
+
+    // Once API keys are issued they must not be bypassed by sending no credentials at all
+    if name.is_none() && (token_auth || api_key_auth) {
+        return Err(Status::Unauthorized);
+    }
+
+    Ok(ClientIdentity {
+        name,
+        alternative_names,
+        grant: None,
+    })
+}
+
+/// The DNS names, emails and URIs in the certificate's subject alternative name extension
+fn alternative_names(certificate: &Certificate<'_>) -> Vec<String> {
+    let mut names = vec![];
+    for extension in certificate.extensions() {
+        if let ParsedExtension::SubjectAlternativeName(san) = extension.parsed_extension() {
+            for name in &san.general_names {
+                match name {
+                    GeneralName::DNSName(n) | GeneralName::RFC822Name(n) | GeneralName::URI(n) => {
+                        names.push(n.to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::Action;
+    use crate::config_file::tests::test_config;
+    use rocket::local::asynchronous::Client;
+
+    #[rocket::get("/")]
+    fn whoami(identity: ClientIdentity) -> String {
+        identity.to_string()
+    }
+
+    async fn client(api_keys: ApiKeys) -> Client {
+        let rocket = rocket::build()
+            .manage(Arc::new(test_config(serde_json::json!({}))))
+            .manage(api_keys)
+            .mount("/", rocket::routes![whoami]);
+        Client::untracked(rocket).await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn api_keys_require_credentials() {
+        let file =
+            std::env::temp_dir().join(format!("edd-apikeys-test-{}.json", std::process::id()));
+        std::fs::remove_file(&file).ok();
+        let api_keys = ApiKeys::load(&file).unwrap();
+        let (_, key) = api_keys
+            .create(
+                "integrator",
+                Grant {
+                    deployments: vec!["vendor-a-*".into()],
+                    actions: vec![Action::Logs],
+                },
+                None,
+            )
+            .unwrap();
+        let client = client(api_keys).await;
+        std::fs::remove_file(&file).ok();
+
+        let response = client.get("/").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get("/")
+            .header(rocket::http::Header::new(
+                "Authorization",
+                "Bearer edd_invalid",
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get("/")
+            .header(rocket::http::Header::new(
+                "Authorization",
+                format!("Bearer {}", key),
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().await.unwrap(), "integrator");
+    }
+
+    #[rocket::async_test]
+    async fn anonymous_without_api_keys() {
+        let file =
+            std::env::temp_dir().join(format!("edd-apikeys-none-{}.json", std::process::id()));
+        let client = client(ApiKeys::load(&file).unwrap()).await;
+
+        let response = client.get("/").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
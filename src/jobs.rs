@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::api_error::ApiError;
+use crate::authorization::Action;
+
+/// Finished jobs kept for clients to collect the result, the oldest are forgotten first
+const MAX_FINISHED_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobError {
+    pub status: u16,
+    pub code: String,
+    pub reason: String,
+    pub detail: Option<String>,
+}
+
+/// A load or pull running after its request has returned
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Job {
+    pub id: u64,
+    /// The action the job performs, i.e. load or pull
+    pub action: Action,
+    pub deployment: String,
+    pub state: JobState,
+    /// Unix timestamps
+    pub created: u64,
+    pub finished: Option<u64>,
+    /// The latest progress message reported by docker
+    pub progress: Option<String>,
+    /// The response the request would have returned, once the job has succeeded
+    pub result: Option<Value>,
+    pub error: Option<JobError>,
+}
+
+/// Background jobs since the daemon started, ids are not reused until it restarts
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    next_id: Mutex<u64>,
+}
+
+impl Jobs {
+    /// Record a new running job, returning its id
+    pub fn create(&self, action: Action, deployment: &str) -> u64 {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+
+        let mut jobs = self.jobs.lock().unwrap();
+        let finished: Vec<u64> = jobs
+            .values()
+            .filter(|j| j.state != JobState::Running)
+            .map(|j| j.id)
+            .collect();
+        if finished.len() >= MAX_FINISHED_JOBS {
+            for id in &finished[..=finished.len() - MAX_FINISHED_JOBS] {
+                jobs.remove(id);
+            }
+        }
+
+        jobs.insert(
+            id,
+            Job {
+                id,
+                action,
+                deployment: deployment.to_owned(),
+                state: JobState::Running,
+                created: now(),
+                finished: None,
+                progress: None,
+                result: None,
+                error: None,
+            },
+        );
+        id
+    }
+
+    pub fn progress(&self, id: u64, message: String) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.progress = Some(message);
+        }
+    }
+
+    /// Record the outcome of a job, the result is the response body it replaces
+    pub fn finish<T: Serialize>(&self, id: u64, result: Result<T, &ApiError>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = match jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+
+        job.finished = Some(now());
+        match result {
+            Ok(result) => {
+                job.state = JobState::Succeeded;
+                job.result = serde_json::to_value(result).ok();
+            }
+            Err(e) => {
+                job.state = JobState::Failed;
+                job.error = Some(JobError {
+                    status: e.status.code,
+                    code: e.code.to_owned(),
+                    reason: e.message.to_owned(),
+                    detail: e.detail.to_owned(),
+                });
+            }
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
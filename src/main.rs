@@ -3,19 +3,50 @@ extern crate rocket;
 
 use std::error::Error;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use clap::Parser;
 use rocket::data::{Limits, ToByteUnit};
 use tokio::sync::Mutex;
 
+use runtime::SharedRuntime;
+
 mod api;
+mod api_error;
+mod api_keys;
+mod audit;
+mod authorization;
+mod bearer;
+#[cfg(feature = "bollard")]
+mod bollard_runtime;
+mod capabilities;
+mod checksum;
 mod config_file;
 mod docker_client;
+mod docker_context;
+mod docker_error;
 mod docker_structs;
+mod download;
+mod engine;
+mod envelope;
+mod history;
+mod identity;
+mod jobs;
 mod manager;
+mod metrics;
+#[cfg(windows)]
+mod named_pipe;
+mod openapi;
+mod run_args;
+mod runtime;
+mod self_stats;
+mod uploads;
+mod webhooks;
+mod websocket;
 
 /// Exit Codes
 ///  1 - Bad Config File
+///  2 - Docker engine does not meet the requirements
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -24,6 +55,10 @@ struct Cli {
     #[arg(short, long, value_name = "FILE")]
     config: Option<PathBuf>,
 
+    /// Reject all requests which would change container state
+    #[arg(long)]
+    read_only: bool,
+
     /// Turn debugging information on
     #[arg(short, long, action = clap::ArgAction::Count)]
     debug: u8,
@@ -36,7 +71,7 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     // Determine the config file we are going to use and import config
     // "config" contains the final config for the system
     let config_path = cli.config.unwrap_or(PathBuf::from("/etc/edd/config.json"));
-    let config = match config_file::process_config_file(config_path) {
+    let mut config = match config_file::process_config_file(config_path) {
         Ok(c) => c,
         Err(e) => {
             println!("{}", e);
@@ -44,39 +79,244 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         }
     };
 
+    if cli.read_only {
+        config.read_only = true;
+    }
+
     // Client to communcate with the selected docker socket
-    let mut docker = docker_client::DockerClient::new(&config.docker_socket);
+    #[cfg(feature = "bollard")]
+    let docker = match bollard_runtime(&config)? {
+        Some(docker) => docker,
+        None => hyper_runtime(&config).await?,
+    };
+    #[cfg(not(feature = "bollard"))]
+    let docker = hyper_runtime(&config).await?;
+
+    // Check the engine before anything else asks docker for something it may not support
+    let engine = engine::check(&config, docker.as_ref()).await?;
+    for problem in &engine.problems {
+        println!("Warning: {}", problem);
+    }
+    if engine.problems.is_empty() == false && config.engine_check {
+        println!("Docker engine does not meet the requirements, set engine_check to false to start anyway");
+        std::process::exit(2);
+    }
 
-    let manager = manager::Manager::new(&config, &mut docker).await?;
+    let mut manager = manager::Manager::new(&config, docker.as_ref()).await?;
+    if config.read_only == false {
+        manager.adopt_containers(&config, docker.as_ref()).await?;
+    }
+    manager.stop_disabled(&config, docker.as_ref()).await?;
+    if config.webhooks.is_empty() == false {
+        tokio::spawn(webhooks::webhook_loop(
+            config.webhooks.to_owned(),
+            manager.subscribe(),
+        ));
+    }
 
     docker.get_images().await?;
 
+    let capabilities = capabilities::Capabilities::from_info(&docker.system_info().await?);
+    if capabilities.rootless {
+        println!("Docker is running rootless");
+    }
+    for warning in capabilities.warnings() {
+        println!("Warning: {}", warning);
+    }
+
     let figment = rocket::Config::figment()
         .merge(("port", 8855))
         .merge(("address", "0.0.0.0"))
-        .merge(("limits", Limits::new().limit("file", 2.gibibytes())))
+        .merge((
+            "limits",
+            Limits::new()
+                .limit("file", 2.gibibytes())
+                .limit("json", 64.kibibytes()),
+        ))
         .merge(("tls.certs", config.tls_certs.to_owned()))
         .merge(("tls.key", config.tls_key.to_owned()))
-        .merge(("tls.mutual.ca_certs", config.mutual_tls_ca_certs.to_owned()));
+        .merge(("tls.mutual.ca_certs", config.mutual_tls_ca_certs.to_owned()))
+        .merge(("tls.mutual.mandatory", config.mutual_tls_mandatory));
 
-    let _rocket = rocket::custom(figment)
-        .manage(Mutex::new(docker))
+    let uploads = uploads::Uploads::new(&config.upload_dir, config.upload_expiry);
+    let api_keys = match api_keys::ApiKeys::load(&config.api_keys_file) {
+        Ok(k) => k,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let history = match history::History::load(&config.history_file) {
+        Ok(h) => Arc::new(h),
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let audit_log = match &config.audit_log {
+        Some(path) => match audit::AuditLog::open(path) {
+            Ok(l) => Some(l),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let config = Arc::new(config);
+    let manager = Arc::new(Mutex::new(manager));
+
+    tokio::spawn(manager::event_loop(
+        config.clone(),
+        docker.clone(),
+        manager.clone(),
+    ));
+
+    if let Some(period) = config.refresh_interval {
+        tokio::spawn(manager::refresh_loop(
+            period,
+            config.clone(),
+            docker.clone(),
+            manager.clone(),
+        ));
+    }
+
+    // The same routes are served under /v2/ with their responses wrapped in an envelope
+    let routes = routes![
+        api::abort_upload,
+        api::append_upload,
+        api::build_deployment,
+        api::commit_upload,
+        api::create_api_key,
+        api::create_deployment,
+        api::create_upload,
+        api::delete_api_key,
+        api::delete_container,
+        api::delete_deployment,
+        api::delete_dry_run,
+        api::delete_image,
+        api::download_logs,
+        api::exec_deployment,
+        api::get_api_keys,
+        api::get_deployments,
+        api::get_deployment,
+        api::get_events,
+        api::export_image,
+        api::get_image_info,
+        api::get_images,
+        api::get_group_status,
+        api::get_health,
+        api::get_history,
+        api::get_job,
+        api::get_orphans,
+        api::get_ready,
+        api::get_archive,
+        api::get_audit_log,
+        api::get_auth,
+        api::get_capabilities,
+        api::get_console,
+        api::get_logs,
+        api::get_self_stats,
+        api::get_stats,
+        api::get_upload,
+        api::inspect_deployment,
+        api::kill_deployment,
+        api::load_file,
+        api::load_file_in_background,
+        api::load_dry_run,
+        api::prune_orphans,
+        api::prune_system,
+        api::pull,
+        api::put_archive,
+        api::pull_with_progress,
+        api::pull_in_background,
+        api::pause_deployment,
+        api::recreate_deployment,
+        api::reload_config,
+        api::restart_deployment,
+        api::rollback_deployment,
+        api::start_deployment,
+        api::start_dry_run,
+        api::start_group,
+        api::stop_container,
+        api::stop_deployment,
+        api::stop_dry_run,
+        api::stop_group,
+        api::unpause_deployment,
+        api::update_resources
+    ];
+
+    let swagger_ui = config.swagger_ui;
+    let mut rocket = rocket::custom(figment)
+        .manage(docker)
         .manage(config)
-        .manage(Mutex::new(manager))
-        .mount(
-            "/v1/",
-            routes![
-                api::delete_deployment,
-                api::get_deployments,
-                api::get_deployment,
-                api::load_file,
-                api::pull,
-                api::start_deployment,
-                api::stop_deployment
-            ],
-        )
-        .launch()
-        .await?;
+        .manage(manager)
+        .manage(engine)
+        .manage(Arc::new(jobs::Jobs::default()))
+        .manage(uploads)
+        .manage(api_keys)
+        .manage(audit_log)
+        .manage(history)
+        .attach(metrics::RequestMetrics)
+        .attach(envelope::Envelopes)
+        .attach(audit::Audit)
+        .mount("/v1/", routes.clone())
+        .mount("/v1/", routes![api::get_openapi])
+        .mount("/v2/", routes)
+        .register("/", catchers![api_error::catcher])
+        // At the root, where Prometheus looks for it by default
+        .mount("/", routes![api::get_metrics]);
+
+    // Swagger UI is served from the assets built into the binary, so it works offline
+    if swagger_ui {
+        rocket = rocket.mount(
+            "/",
+            utoipa_swagger_ui::SwaggerUi::new("/v1/docs/<_..>")
+                .config(utoipa_swagger_ui::Config::from("/v1/openapi.json")),
+        );
+    }
+
+    rocket.launch().await?;
 
     Ok(())
 }
+
+/// The built in hyper client, used for all sockets in minimal builds
+async fn hyper_runtime(
+    config: &config_file::Config,
+) -> Result<SharedRuntime, Box<dyn Error + Send + Sync>> {
+    let client = match &config.docker_tls {
+        Some(tls) => {
+            docker_client::DockerClient::new_tls(&config.docker_socket, tls, &config.docker_pool)?
+        }
+        None if config.docker_socket.starts_with("ssh://") => {
+            docker_client::DockerClient::new_ssh(&config.docker_socket, &config.docker_pool).await?
+        }
+        None => docker_client::DockerClient::new(&config.docker_socket, &config.docker_pool)?,
+    };
+
+    Ok(Arc::new(
+        client
+            .with_timeouts(config.docker_timeouts.clone())
+            .with_retry(config.docker_retry.clone()),
+    ))
+}
+
+/// With the bollard feature unix and tcp sockets use the bollard runtime, None for TLS and
+/// ssh sockets which it does not support
+#[cfg(feature = "bollard")]
+fn bollard_runtime(
+    config: &config_file::Config,
+) -> Result<Option<SharedRuntime>, Box<dyn Error + Send + Sync>> {
+    if config.docker_tls.is_some() {
+        return Ok(None);
+    }
+
+    let socket = docker_client::DockerSocket::parse(&config.docker_socket)?;
+    if matches!(socket, docker_client::DockerSocket::Ssh { .. }) {
+        return Ok(None);
+    }
+
+    let runtime = bollard_runtime::BollardRuntime::new(&socket, config.docker_timeouts.clone())?;
+    Ok(Some(Arc::new(runtime)))
+}
@@ -1,11 +1,102 @@
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::MissedTickBehavior;
+use utoipa::ToSchema;
 
 use crate::config_file::Config;
-use crate::docker_client::DockerClient;
-use crate::docker_structs::RunningContainer;
+use crate::docker_structs::{InspectImage, RunningContainer};
+use crate::runtime::{ContainerRuntime, SharedRuntime};
+
+/// Labels applied to the containers edd creates, so they are recognised without relying on
+/// the container name. The prefix label keeps daemons using different prefixes on the same
+/// host from claiming each other's containers. The digest label keeps the digest the image
+/// was pulled by across restarts
+pub const MANAGED_LABEL: &str = "ed.managed";
+pub const DEPLOYMENT_LABEL: &str = "ed.deployment";
+pub const PREFIX_LABEL: &str = "ed.prefix";
+pub const DIGEST_LABEL: &str = "ed.digest";
+
+/// The labels for a deployment's container, as `key=value`
+pub fn container_labels(config: &Config, deployment: &str, digest: Option<&str>) -> Vec<String> {
+    let mut labels = vec![
+        format!("{}=true", MANAGED_LABEL),
+        format!("{}={}", DEPLOYMENT_LABEL, deployment),
+        format!(
+            "{}={}",
+            PREFIX_LABEL,
+            config.container_prefix.trim_start_matches("/")
+        ),
+    ];
+    if let Some(digest) = digest {
+        labels.push(format!("{}={}", DIGEST_LABEL, digest));
+    }
+    labels
+}
 
+/// The deployment a container is labelled as belonging to, None if it was not created by
+/// this daemon (or was created before containers were labelled)
+pub fn labelled_deployment<'a>(
+    config: &Config,
+    container: &'a RunningContainer,
+) -> Option<&'a str> {
+    let labels = container.labels.as_ref()?;
+    let prefix = config.container_prefix.trim_start_matches("/");
+    if labels.get(MANAGED_LABEL).map(|v| v.as_str()) != Some("true")
+        || labels.get(PREFIX_LABEL).map(|v| v.as_str()) != Some(prefix)
+    {
+        return None;
+    }
+
+    labels.get(DEPLOYMENT_LABEL).map(|v| v.as_str())
+}
+
+/// Changes buffered for each subscriber, a subscriber which falls further behind than this
+/// misses the oldest changes
+const CHANGES_CAPACITY: usize = 64;
+
+/// Tracks the state of deployments, all changes to the tracked state go through the methods
+/// here so a request cannot partially overwrite another's update
 pub struct Manager {
-    pub deployments: Vec<Deployment>,
+    deployments: Vec<Deployment>,
+    orphans: Vec<Orphan>,
+    /// Containers found which can be adopted by a deployment without one
+    adoptable: Vec<Adoption>,
+    changes: broadcast::Sender<DeploymentChange>,
+}
+
+/// What happened to a deployment
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Started,
+    Stopped,
+    Paused,
+    Unpaused,
+    /// The health check started failing
+    Unhealthy,
+    /// The health check passed again after failing
+    Healthy,
+    /// A new image was loaded, pulled or built and the deployment started with it
+    Loaded,
+}
+
+/// A change to a deployment, sent to every subscriber of Manager::subscribe
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeploymentChange {
+    pub event: Change,
+    pub deployment: String,
+    /// The deployment after the change
+    pub state: String,
+    pub health: String,
+    pub image: String,
+    /// Unix timestamp
+    pub time: u64,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -15,11 +106,68 @@ pub struct Deployment {
     pub state: State,
     pub image: String,
     pub health: String,
+    /// Digest the deployment image was pulled by, the deployment will not be started if the
+    /// image no longer has this digest
+    pub digest: Option<String>,
+    /// What the deployment's image declares, None until the image has been inspected
+    pub metadata: Option<ImageMetadata>,
+}
+
+/// Runtime settings declared by an image, docker only reports these by inspecting the image
+/// so they are recorded when the deployment is started (or first asked for)
+#[derive(Debug, Default, Clone)]
+pub struct ImageMetadata {
+    /// i.e. "80/tcp"
+    pub exposed_ports: Vec<String>,
+    /// The healthcheck test, None if the image has no healthcheck
+    pub healthcheck: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl ImageMetadata {
+    pub fn from_image(image: InspectImage) -> Self {
+        let config = match image.config {
+            Some(config) => config,
+            None => return ImageMetadata::default(),
+        };
+
+        ImageMetadata {
+            exposed_ports: config
+                .exposed_ports
+                .unwrap_or_default()
+                .into_keys()
+                .collect(),
+            healthcheck: config
+                .healthcheck
+                .map(|h| h.test)
+                .filter(|t| t.is_empty() == false && t[0] != "NONE"),
+            entrypoint: config.entrypoint,
+            labels: config.labels.unwrap_or_default(),
+        }
+    }
+}
+
+/// A container using the configured prefix which does not match any configured deployment
+#[derive(Debug, Clone)]
+pub struct Orphan {
+    pub id: String,
+    pub names: Vec<String>,
+    pub image: String,
+    pub state: String,
+}
+
+/// A prefixed container running a deployment's image under another name
+#[derive(Debug, Clone)]
+struct Adoption {
+    deployment: String,
+    container: String,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum State {
     Running,
+    Paused,
     //Failed,
     Stopped,
 }
@@ -30,10 +178,32 @@ impl Default for State {
     }
 }
 
+impl Deployment {
+    /// A container without health reported is known to have no health check once the image
+    /// has been inspected, "none" rather than "unknown"
+    fn apply_metadata(&mut self, metadata: Option<ImageMetadata>) {
+        let without_healthcheck = metadata
+            .as_ref()
+            .map(|m| m.healthcheck.is_none())
+            .unwrap_or(false);
+        if without_healthcheck && self.id.is_empty() == false && self.health == "unknown" {
+            self.health = "none".into();
+        }
+        self.metadata = metadata;
+    }
+
+    /// Running, and either healthy or without a health check
+    pub fn is_healthy(&self) -> bool {
+        self.state == State::Running
+            && (self.health == "healthy" || self.health == "unknown" || self.health == "none")
+    }
+}
+
 impl State {
     pub fn to_string(&self) -> String {
         match self {
             State::Running => "running",
+            State::Paused => "paused",
             //State::Failed => "failed",
             State::Stopped => "stopped",
         }
@@ -44,13 +214,49 @@ impl State {
 impl Manager {
     pub async fn new(
         config: &Config,
-        docker: &mut DockerClient,
+        docker: &dyn ContainerRuntime,
     ) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        // First check the running contains list for anything we need
-        let running_containers = docker.get_containers().await?;
+        let configured = config.deployments.get();
+
+        // Containers are claimed by their labels, only the containers labelled for this daemon
+        // are listed
+        let prefix = config.container_prefix.trim_start_matches("/");
+        let managed = format!("{}=true", MANAGED_LABEL);
+        let prefixed = format!("{}={}", PREFIX_LABEL, prefix);
+        let labelled_containers = docker
+            .get_containers(&[("label", &managed), ("label", &prefixed)])
+            .await?;
+
+        // Containers created before labels were applied are still recognised by the prefix on
+        // their name, docker matches the name filter as a regex so the prefix is anchored and
+        // escaped (only . in a valid prefix is special)
+        let name_filter = format!("^{}", config.container_prefix.replace('.', "\\."));
+        let unlabelled_containers: Vec<RunningContainer> = docker
+            .get_containers(&[("name", &name_filter)])
+            .await?
+            .into_iter()
+            .filter(|c| labelled_containers.iter().any(|l| l.id == c.id) == false)
+            .collect();
+
+        let mut deployments: Vec<Option<Deployment>> = vec![None; configured.len()];
+
+        // Match labelled containers with deployments, a labelled container for a deployment
+        // which is no longer configured (or a second container for a deployment) is an orphan
+        let mut matched: Vec<(usize, &RunningContainer)> = vec![];
+        let mut labelled_orphans: Vec<&RunningContainer> = vec![];
+        for container in &labelled_containers {
+            let index = labelled_deployment(config, container)
+                .and_then(|name| configured.iter().position(|d| d.name == name));
+            match index {
+                Some(index) if matched.iter().any(|(i, _)| *i == index) == false => {
+                    matched.push((index, container))
+                }
+                _ => labelled_orphans.push(container),
+            }
+        }
 
-        // Get all the running containers which are using names prefixed with the correct prefix
-        let mut prefixed_containers: Vec<(&RunningContainer, Vec<&String>)> = running_containers
+        // Get all the unlabelled containers which are using names prefixed with the correct prefix
+        let mut prefixed_containers: Vec<(&RunningContainer, Vec<&String>)> = unlabelled_containers
             .iter()
             .filter_map(|r| {
                 let matched_names: Vec<&String> = r
@@ -66,10 +272,11 @@ impl Manager {
             })
             .collect();
 
-        let mut deployments: Vec<Option<Deployment>> = vec![None; config.deployments.len()];
-
-        // Match running containers with deployment names
-        for (deployment_index, deployment) in config.deployments.iter().enumerate() {
+        // Match unlabelled containers with deployment names
+        for (deployment_index, deployment) in configured.iter().enumerate() {
+            if matched.iter().any(|(i, _)| *i == deployment_index) {
+                continue;
+            }
             let container_name = format!("{}{}", config.container_prefix, deployment.name);
 
             // Determine if any of the given container names match the name for any of the deployments
@@ -81,29 +288,78 @@ impl Manager {
                 }
             }
 
-            // If they match, then configure the demplyment information
             if let Some(index) = remove_at {
-                let inspection = docker
-                    .inspect_running_container(&prefixed_containers[index].0.id)
-                    .await?;
-
-                deployments[deployment_index] = Some(Deployment {
-                    id: prefixed_containers[index].0.id.to_owned(),
-                    name: deployment.name.to_owned(),
-                    state: match prefixed_containers[index].0.state.as_str() {
-                        "running" => State::Running,
-                        _ => State::Stopped,
-                    },
-                    image: prefixed_containers[index].0.image.to_owned(),
-                    health: match inspection.state.health {
-                        Some(h) => h.status.to_owned(),
-                        None => "unknown".to_owned(),
-                    },
-                });
+                matched.push((deployment_index, prefixed_containers[index].0));
                 prefixed_containers.remove(index);
             }
         }
 
+        // A prefixed container which runs a deployment's image under another name (i.e. renamed
+        // by hand, or left behind by an interrupted load) can be adopted by renaming it, rather
+        // than leaving the deployment without a container. If more than one container could
+        // be adopted none are, as there is no way to tell which is wanted. The rename is left
+        // to Manager::adopt_containers so reading the deployments never changes a container
+        let mut adoptable = vec![];
+        for (deployment_index, deployment) in configured.iter().enumerate() {
+            if matched.iter().any(|(i, _)| *i == deployment_index) {
+                continue;
+            }
+
+            let image = format!("{}{}", prefix, deployment.name);
+            let candidates: Vec<&RunningContainer> = prefixed_containers
+                .iter()
+                .filter(|(c, _)| c.image == image || c.image == format!("{}:latest", image))
+                .map(|(c, _)| *c)
+                .collect();
+            if let [container] = candidates[..] {
+                adoptable.push(Adoption {
+                    deployment: deployment.name.to_owned(),
+                    container: container.id.to_owned(),
+                });
+            }
+        }
+
+        // State and image come from the container list, health is only reported by inspecting
+        // the container. Inspections are limited in the number of requests in flight so a
+        // large number of deployments does not swamp the docker daemon
+        let docker: &dyn ContainerRuntime = docker;
+        let ids: Vec<String> = matched.iter().map(|(_, c)| c.id.to_owned()).collect();
+        let inspections: Vec<_> = futures::stream::iter(ids)
+            .map(|id| async move { docker.inspect_running_container(&id).await })
+            .buffered(config.refresh_concurrency)
+            .collect()
+            .await;
+
+        // Inspection results are in the same order as the matched containers
+        let mut inspections = inspections.into_iter();
+
+        // If they match, then configure the demplyment information
+        for (deployment_index, container) in matched.into_iter() {
+            let inspection = inspections.next().expect("container was inspected");
+            let health = match inspection?.state.health {
+                Some(h) => h.status,
+                None => "unknown".to_owned(),
+            };
+
+            deployments[deployment_index] = Some(Deployment {
+                id: container.id.to_owned(),
+                name: configured[deployment_index].name.to_owned(),
+                state: match container.state.as_str() {
+                    "running" => State::Running,
+                    "paused" => State::Paused,
+                    _ => State::Stopped,
+                },
+                image: container.image.to_owned(),
+                health,
+                digest: container
+                    .labels
+                    .as_ref()
+                    .and_then(|l| l.get(DIGEST_LABEL))
+                    .map(|d| d.to_owned()),
+                metadata: None,
+            });
+        }
+
         // We now have two issues:
         //   1) prefixed_containers contains a list of prefixed containers which did not match a deployment
         //   2) deployments contains None for containers which are not running
@@ -126,47 +382,379 @@ impl Manager {
             }
             *deployment = Some(Deployment {
                 id: "".into(),
-                name: config.deployments[index].name.to_owned(),
+                name: configured[index].name.to_owned(),
                 image: "".into(),
                 state: State::Stopped,
                 health: "unknown".into(),
+                digest: None,
+                metadata: None,
             });
         }
 
+        labelled_orphans.iter().for_each(|container| {
+            println!(
+                "Container '{}' is labelled for deployment '{}', which is not configured or already has a container",
+                container
+                    .names
+                    .iter()
+                    .map(|n| n.strip_prefix("/").unwrap_or(n))
+                    .collect::<Vec<&str>>()
+                    .join("/"),
+                labelled_deployment(config, container).unwrap_or_default()
+            );
+        });
+
+        let orphans = prefixed_containers
+            .iter()
+            .map(|(container, _)| *container)
+            .chain(labelled_orphans)
+            .map(|container| Orphan {
+                id: container.id.to_owned(),
+                names: container.names.to_owned(),
+                image: container.image.to_owned(),
+                state: container.state.to_owned(),
+            })
+            .collect();
+
         Ok(Manager {
             deployments: deployments.into_iter().flatten().collect(),
+            orphans,
+            adoptable,
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
         })
     }
 
+    pub fn deployments(&self) -> &[Deployment] {
+        &self.deployments
+    }
+
+    pub fn orphans(&self) -> &[Orphan] {
+        &self.orphans
+    }
+
+    /// Records that the deployment's container has been stopped
+    pub fn mark_stopped(&mut self, name: &str) {
+        let previous = self.deployments.clone();
+        if let Some(deployment) = self.deployments.iter_mut().find(|d| d.name == name) {
+            deployment.state = State::Stopped;
+        }
+        self.publish_changes(&previous);
+    }
+
+    /// Records that the deployment's container has been paused or unpaused
+    pub fn mark_paused(&mut self, name: &str, paused: bool) {
+        let previous = self.deployments.clone();
+        if let Some(deployment) = self.deployments.iter_mut().find(|d| d.name == name) {
+            deployment.state = match paused {
+                true => State::Paused,
+                false => State::Running,
+            };
+        }
+        self.publish_changes(&previous);
+    }
+
+    /// Records that the deployment's container has been removed, the deployment is still
+    /// tracked but no longer has a container
+    pub fn mark_removed(&mut self, name: &str) {
+        let previous = self.deployments.clone();
+        if let Some(deployment) = self.deployments.iter_mut().find(|d| d.name == name) {
+            *deployment = Deployment {
+                name: name.to_owned(),
+                health: "unknown".into(),
+                digest: deployment.digest.take(),
+                metadata: deployment.metadata.take(),
+                ..Deployment::default()
+            };
+        }
+        self.publish_changes(&previous);
+    }
+
+    /// Records what the deployment image declares
+    pub fn set_metadata(&mut self, name: &str, metadata: Option<ImageMetadata>) {
+        if let Some(deployment) = self.deployments.iter_mut().find(|d| d.name == name) {
+            deployment.apply_metadata(metadata);
+        }
+    }
+
+    /// Records the digest the deployment image was pulled by, None when the image was loaded
+    pub fn set_digest(&mut self, name: &str, digest: Option<String>) {
+        if let Some(deployment) = self.deployments.iter_mut().find(|d| d.name == name) {
+            deployment.digest = digest;
+        }
+    }
+
     /// Updates known deployments
     ///
-    /// A stopped and removed container API call will return 404, need to check
-    /// that a new container has not been created using the same name
+    /// The deployments are rebuilt from the container list rather than inspecting each known
+    /// id, this also catches a new container having been created using the same name. Image
+    /// metadata is only known from inspecting the image so is carried over from the previous
+    /// state, as is the digest which may have been set since the container was labelled
     pub async fn update_deployments(
         &mut self,
         config: &Config,
-        docker: &mut DockerClient,
+        docker: &dyn ContainerRuntime,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut full_update = Manager::new(config, docker).await?;
+        for deployment in &mut full_update.deployments {
+            let previous = self.deployments.iter().find(|d| d.name == deployment.name);
+            if let Some(previous) = previous {
+                deployment.digest = previous.digest.to_owned();
+            }
+            deployment.apply_metadata(previous.and_then(|d| d.metadata.to_owned()));
+        }
+        let previous = std::mem::replace(&mut self.deployments, full_update.deployments);
+        self.orphans = full_update.orphans;
+        self.adoptable = full_update.adoptable;
+        self.publish_changes(&previous);
+
+        Ok(())
+    }
+
+    /// Receives every change to a deployment from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<DeploymentChange> {
+        self.changes.subscribe()
+    }
+
+    /// Records that the deployment was started with a new image
+    pub fn publish_loaded(&self, name: &str) {
+        if let Some(deployment) = self.deployments.iter().find(|d| d.name == name) {
+            self.publish(deployment, Change::Loaded);
+        }
+    }
+
+    /// Publishes how each deployment has changed since the previous deployments
+    fn publish_changes(&self, previous: &[Deployment]) {
+        for deployment in &self.deployments {
+            let before = previous
+                .iter()
+                .find(|d| d.name == deployment.name)
+                .cloned()
+                .unwrap_or_default();
+
+            let state = match (&before.state, &deployment.state) {
+                (State::Paused, State::Running) => Some(Change::Unpaused),
+                (State::Stopped, State::Running) => Some(Change::Started),
+                (State::Running, State::Paused) => Some(Change::Paused),
+                (State::Running | State::Paused, State::Stopped) => Some(Change::Stopped),
+                _ => None,
+            };
+            if let Some(change) = state {
+                self.publish(deployment, change);
+            }
+
+            match (before.health.as_str(), deployment.health.as_str()) {
+                (before, "unhealthy") if before != "unhealthy" => {
+                    self.publish(deployment, Change::Unhealthy)
+                }
+                ("unhealthy", "healthy") => self.publish(deployment, Change::Healthy),
+                _ => {}
+            }
+        }
+    }
+
+    fn publish(&self, deployment: &Deployment, event: Change) {
+        // Sending only fails when nothing is subscribed
+        self.changes
+            .send(DeploymentChange {
+                event,
+                deployment: deployment.name.to_owned(),
+                state: deployment.state.to_string(),
+                health: deployment.health.to_owned(),
+                image: deployment.image.to_owned(),
+                time: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            })
+            .ok();
+    }
+
+    /// Renames the containers which can be adopted by a deployment without a container, then
+    /// updates the deployments to include them. Only called at startup or when the config is
+    /// reloaded, never by a read only daemon
+    pub async fn adopt_containers(
+        &mut self,
+        config: &Config,
+        docker: &dyn ContainerRuntime,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.adoptable.is_empty() {
+            return Ok(());
+        }
+
+        let prefix = config.container_prefix.trim_start_matches("/");
+        for adoption in std::mem::take(&mut self.adoptable) {
+            let container_name = format!("{}{}", prefix, adoption.deployment);
+            if let Err(e) = docker
+                .rename_container(&adoption.container, &container_name)
+                .await
+            {
+                println!(
+                    "Unable to adopt container {} for deployment '{}': {}",
+                    adoption.container, adoption.deployment, e
+                );
+                continue;
+            }
+
+            println!(
+                "Adopted container {} as deployment '{}'",
+                adoption.container, adoption.deployment
+            );
+        }
+
+        self.update_deployments(config, docker).await
+    }
+
+    /// Stops any running deployments which are disabled in the config, a read only daemon
+    /// leaves them running
+    pub async fn stop_disabled(
+        &mut self,
+        config: &Config,
+        docker: &dyn ContainerRuntime,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if config.read_only {
+            return Ok(());
+        }
+
+        let previous = self.deployments.clone();
         for deployment in &mut self.deployments {
-            match docker.inspect_running_container(&deployment.id).await {
-                Ok(i) => {
-                    deployment.health = match i.state.health {
-                        Some(h) => h.status,
-                        None => "unknown".to_string(),
+            if deployment.state == State::Stopped || config.is_disabled(&deployment.name) == false {
+                continue;
+            }
+
+            println!("Stopping disabled deployment '{}'", deployment.name);
+            docker.stop_running_container(&deployment.id).await?;
+            deployment.state = State::Stopped;
+        }
+        self.publish_changes(&previous);
+
+        Ok(())
+    }
+}
+
+/// Delay before subscribing to docker events again after the stream fails, doubled for each
+/// failed attempt in a row up to EVENT_RETRY_MAX_DELAY
+const EVENT_RETRY_DELAY: Duration = Duration::from_secs(1);
+const EVENT_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Refreshes the deployment information whenever docker reports one of the deployment
+/// containers starting, stopping, exiting or changing health, so the state does not wait for
+/// the next request or refresh to be updated
+///
+/// The stream closes if docker restarts (or the connection to it drops), the subscription is
+/// retried with a backoff and once it succeeds the deployments are fully resynchronised, as
+/// events were missed and the containers may have changed while docker was away
+pub async fn event_loop(config: Arc<Config>, docker: SharedRuntime, manager: Arc<Mutex<Manager>>) {
+    let mut configured = config.deployments.subscribe();
+    let mut delay = EVENT_RETRY_DELAY;
+    let mut resync = false;
+    loop {
+        // Events are filtered by container name, so the subscription is renewed whenever
+        // deployments are added or removed
+        let names: Vec<String> = configured
+            .borrow_and_update()
+            .iter()
+            .map(|d| {
+                format!(
+                    "{}{}",
+                    config.container_prefix.trim_start_matches("/"),
+                    d.name
+                )
+            })
+            .collect();
+
+        let events = docker.events(&names).await;
+        match events {
+            Ok(mut events) => {
+                if resync {
+                    println!("Reconnected to docker events, resynchronising deployments");
+                    let mut manager = manager.lock().await;
+                    if let Err(e) = manager.update_deployments(&config, docker.as_ref()).await {
+                        crate::metrics::record_error(e.as_ref());
+                        println!("Error resynchronising deployments: {}", e);
+                    }
+                    if let Err(e) = manager.stop_disabled(&config, docker.as_ref()).await {
+                        println!("Unable to stop disabled deployments: {}", e);
+                    }
+                }
+                delay = EVENT_RETRY_DELAY;
+
+                let mut renew = false;
+                loop {
+                    let event = tokio::select! {
+                        event = events.next() => event,
+                        Ok(()) = configured.changed() => {
+                            renew = true;
+                            break;
+                        }
                     };
+                    let event = match event {
+                        Some(Ok(event)) => event,
+                        None => break,
+                        Some(Err(e)) => {
+                            crate::metrics::record_docker_error(&e);
+                            println!("Error reading docker events: {}", e);
+                            break;
+                        }
+                    };
+                    debug!(
+                        "Container {} ({}) event {:?}",
+                        event.name, event.id, event.kind
+                    );
+
+                    let mut manager = manager.lock().await;
+                    if let Err(e) = manager.update_deployments(&config, docker.as_ref()).await {
+                        crate::metrics::record_error(e.as_ref());
+                        println!("Error updating deployments after event: {}", e);
+                    }
                 }
-                Err(_) => {
-                    deployment.id = "".into();
-                    deployment.state = State::Stopped;
-                    deployment.image = "".into();
+                // Events may be missed while subscribing again, so the deployments are
+                // resynchronised as they are after a reconnect
+                if renew {
+                    resync = true;
                     continue;
                 }
-            };
+                println!("Docker event stream closed");
+            }
+            Err(e) => {
+                crate::metrics::record_docker_error(&e);
+                println!(
+                    "Unable to subscribe to docker events, retrying in {}s: {}",
+                    delay.as_secs(),
+                    e
+                )
+            }
         }
 
-        let full_update = Manager::new(config, docker).await?;
-        self.deployments = full_update.deployments;
+        resync = true;
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(EVENT_RETRY_MAX_DELAY);
+    }
+}
+
+/// Periodically refreshes the deployment information in the background
+///
+/// Only one refresh runs at a time, if a refresh takes longer than the interval the missed
+/// ticks are skipped rather than queued up
+pub async fn refresh_loop(
+    period: Duration,
+    config: Arc<Config>,
+    docker: SharedRuntime,
+    manager: Arc<Mutex<Manager>>,
+) {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-        Ok(())
+    loop {
+        interval.tick().await;
+
+        let mut manager = manager.lock().await;
+        if let Err(e) = manager.update_deployments(&config, docker.as_ref()).await {
+            crate::metrics::record_error(e.as_ref());
+            println!("Background refresh of deployments failed: {}", e);
+            continue;
+        }
+        if let Err(e) = manager.stop_disabled(&config, docker.as_ref()).await {
+            println!("Unable to stop disabled deployments: {}", e);
+        }
     }
 }
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+use crate::docker_error::DockerError;
+
+/// Upper bounds in seconds of the request duration histogram buckets, loads and pulls can
+/// take minutes
+const DURATION_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0, 60.0, 300.0,
+];
+
+/// Docker errors counted by kind, see DockerError::kind
+static DOCKER_ERRORS: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+/// Request durations keyed by method, route and status code
+static REQUESTS: Mutex<BTreeMap<(String, String, u16), Histogram>> = Mutex::new(BTreeMap::new());
+
+#[derive(Default)]
+struct Histogram {
+    /// Count of requests in each of DURATION_BUCKETS, not cumulative
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Count a docker error reported by the API or a background refresh
+pub fn record_docker_error(e: &DockerError) {
+    *DOCKER_ERRORS.lock().unwrap().entry(e.kind()).or_default() += 1;
+}
+
+/// Count the error from a manager update if it came from docker
+pub fn record_error(e: &(dyn Error + Send + Sync + 'static)) {
+    if let Some(e) = e.downcast_ref::<DockerError>() {
+        record_docker_error(e);
+    }
+}
+
+/// When the request arrived, kept in the request's local cache
+struct RequestStart(Instant);
+
+/// Records how long each request takes to respond, by route template so deployment names
+/// do not each create a series. Streamed responses are timed until the response starts
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let elapsed = request
+            .local_cache(|| RequestStart(Instant::now()))
+            .0
+            .elapsed()
+            .as_secs_f64();
+        let route = match request.route() {
+            Some(route) => route.uri.origin.path().to_string(),
+            None => "unmatched".into(),
+        };
+
+        let mut requests = REQUESTS.lock().unwrap();
+        let histogram = requests
+            .entry((
+                request.method().as_str().to_owned(),
+                route,
+                response.status().code,
+            ))
+            .or_default();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|b| elapsed <= *b) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += elapsed;
+    }
+}
+
+/// Metrics in the Prometheus text exposition format
+#[derive(Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// Start a metric, its samples must follow before the next metric is started
+    pub fn metric(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.text, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.text, "# TYPE {} {}", name, kind).unwrap();
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if labels.is_empty() == false {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect();
+            write!(self.text, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(self.text, " {}", value).unwrap();
+    }
+
+    /// Add the request durations and docker error counts recorded since the daemon started
+    pub fn recorded(&mut self) {
+        self.metric(
+            "edd_http_request_duration_seconds",
+            "histogram",
+            "Time taken to respond to API requests",
+        );
+        for ((method, route, status), histogram) in REQUESTS.lock().unwrap().iter() {
+            let status = status.to_string();
+            let labels = [
+                ("method", method.as_str()),
+                ("route", route.as_str()),
+                ("status", status.as_str()),
+            ];
+
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                self.sample(
+                    "edd_http_request_duration_seconds_bucket",
+                    &[&labels[..], &[("le", le.as_str())]].concat(),
+                    cumulative as f64,
+                );
+            }
+            self.sample(
+                "edd_http_request_duration_seconds_bucket",
+                &[&labels[..], &[("le", "+Inf")]].concat(),
+                histogram.count as f64,
+            );
+            self.sample(
+                "edd_http_request_duration_seconds_sum",
+                &labels,
+                histogram.sum,
+            );
+            self.sample(
+                "edd_http_request_duration_seconds_count",
+                &labels,
+                histogram.count as f64,
+            );
+        }
+
+        self.metric(
+            "edd_docker_errors_total",
+            "counter",
+            "Errors from docker reported by the API or background refreshes",
+        );
+        for (kind, count) in DOCKER_ERRORS.lock().unwrap().iter() {
+            self.sample("edd_docker_errors_total", &[("kind", kind)], *count as f64);
+        }
+    }
+
+    pub fn into_string(self) -> String {
+        self.text
+    }
+}
+
+/// Escape a label value, backslash, double quote and newline must be escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,95 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::Uri;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient};
+
+/// Returned when every instance of the pipe is in use, the open is retried until one is free
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// Delay between attempts to open a busy pipe
+const PIPE_BUSY_DELAY: Duration = Duration::from_millis(50);
+
+/// Connects to a docker engine listening on a Windows named pipe, i.e. \\.\pipe\docker_engine.
+/// The uri of each request is ignored, every connection opens the same pipe
+#[derive(Clone)]
+pub struct NamedPipeConnector {
+    path: String,
+}
+
+impl NamedPipeConnector {
+    pub fn new(path: &str) -> Self {
+        NamedPipeConnector {
+            path: path.to_owned(),
+        }
+    }
+}
+
+/// An open connection to the pipe
+pub struct NamedPipeStream {
+    pipe: NamedPipeClient,
+}
+
+impl Service<Uri> for NamedPipeConnector {
+    type Response = NamedPipeStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<NamedPipeStream, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move {
+            loop {
+                match ClientOptions::new().open(&path) {
+                    Ok(pipe) => return Ok(NamedPipeStream { pipe }),
+                    Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {}
+                    Err(e) => return Err(e),
+                }
+                tokio::time::sleep(PIPE_BUSY_DELAY).await;
+            }
+        })
+    }
+}
+
+impl Connection for NamedPipeStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for NamedPipeStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for NamedPipeStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.pipe).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_shutdown(cx)
+    }
+}
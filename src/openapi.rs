@@ -0,0 +1,103 @@
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::api;
+use crate::manager;
+
+/// The routes mounted under /v1/, and /v2/ with their responses wrapped in an envelope
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        api::get_deployments,
+        api::get_deployment,
+        api::get_history,
+        api::get_image_info,
+        api::get_images,
+        api::delete_image,
+        api::export_image,
+        api::inspect_deployment,
+        api::get_stats,
+        api::get_archive,
+        api::get_logs,
+        api::download_logs,
+        api::get_events,
+        api::get_console,
+        api::put_archive,
+        api::get_health,
+        api::get_ready,
+        api::get_auth,
+        api::get_audit_log,
+        api::get_capabilities,
+        api::get_openapi,
+        api::get_self_stats,
+        api::start_deployment,
+        api::stop_deployment,
+        api::start_group,
+        api::stop_group,
+        api::get_group_status,
+        api::restart_deployment,
+        api::exec_deployment,
+        api::kill_deployment,
+        api::pause_deployment,
+        api::unpause_deployment,
+        api::update_resources,
+        api::create_deployment,
+        api::reload_config,
+        api::delete_deployment,
+        api::get_job,
+        api::recreate_deployment,
+        api::rollback_deployment,
+        api::load_file,
+        api::create_upload,
+        api::get_upload,
+        api::append_upload,
+        api::commit_upload,
+        api::abort_upload,
+        api::create_api_key,
+        api::get_api_keys,
+        api::delete_api_key,
+        api::build_deployment,
+        api::pull,
+        api::get_orphans,
+        api::prune_orphans,
+        api::prune_system,
+        api::stop_container,
+        api::delete_container
+    ),
+    // Returned instead of the usual body by ?dry_run=true, and the data of each /events message
+    components(schemas(api::DryRun, manager::DeploymentChange))
+)]
+struct V1;
+
+/// OpenAPI description of the API, generated from the utoipa::path annotations on the routes
+/// so it cannot drift from them
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "ed-daemon",
+        description = "Docker based container deployment for edge devices. Clients authenticate with a TLS client certificate, or a bearer token when token authentication is configured. Every /v1/ path is also served under /v2/ with the response wrapped in an envelope of data, error and request_id, except for streamed responses."
+    ),
+    servers((url = "https://localhost:8855")),
+    nest((path = "/v1", api = V1)),
+    // At the root, where Prometheus looks for it by default
+    paths(api::get_metrics),
+    modifiers(&BearerAuth),
+    security((), ("bearer" = []))
+)]
+pub struct ApiDoc;
+
+/// Bearer tokens are accepted alongside client certificates when token_auth is configured
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let mut scheme = Http::new(HttpAuthScheme::Bearer);
+        scheme.description =
+            Some("A static token or JWT, when token authentication is configured".to_string());
+
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme("bearer", SecurityScheme::Http(scheme));
+    }
+}
@@ -0,0 +1,583 @@
+use crate::docker_structs::*;
+
+/// Flags which do not take a value, given by their long name
+const BOOLEAN_FLAGS: [&str; 8] = [
+    "detach",
+    "interactive",
+    "tty",
+    "publish-all",
+    "privileged",
+    "read-only",
+    "rm",
+    "init",
+];
+
+/// Map short flags onto their long name
+fn long_name(short: char) -> Option<&'static str> {
+    match short {
+        'd' => Some("detach"),
+        'i' => Some("interactive"),
+        't' => Some("tty"),
+        'P' => Some("publish-all"),
+        'p' => Some("publish"),
+        'v' => Some("volume"),
+        'e' => Some("env"),
+        'l' => Some("label"),
+        'w' => Some("workdir"),
+        'u' => Some("user"),
+        'h' => Some("hostname"),
+        'm' => Some("memory"),
+        'c' => Some("cpu-shares"),
+        _ => None,
+    }
+}
+
+/// Translate `docker run` style arguments from the deployment config into a request for
+/// the /containers/create endpoint
+///
+/// Short (`-p 80:80`, `-p80:80`, `-it`) and long (`--publish 80:80`, `--publish=80:80`) forms
+/// are accepted. Only the options listed in the readme are supported, anything else is an
+/// error rather than being silently dropped.
+pub fn parse(args: &[&str], image: &str) -> Result<CreateContainer, String> {
+    // Containers have always been run with -d -it
+    let mut create = CreateContainer {
+        image: image.to_owned(),
+        tty: true,
+        open_stdin: true,
+        ..Default::default()
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flags, inline_value): (Vec<&str>, Option<&str>) = if let Some(long) =
+            arg.strip_prefix("--")
+        {
+            match long.split_once('=') {
+                Some((flag, value)) => (vec![flag], Some(value)),
+                None => (vec![long], None),
+            }
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| s.is_empty() == false) {
+            let mut chars = short.chars();
+            let first = chars.next().unwrap();
+            let flag =
+                long_name(first).ok_or(format!("Unsupported docker run argument ({})", arg))?;
+            if BOOLEAN_FLAGS.contains(&flag) {
+                // Grouped boolean flags, i.e. -dit
+                let mut flags = vec![flag];
+                for c in chars {
+                    match long_name(c).filter(|f| BOOLEAN_FLAGS.contains(f)) {
+                        Some(flag) => flags.push(flag),
+                        None => return Err(format!("Unsupported docker run argument ({})", arg)),
+                    }
+                }
+                (flags, None)
+            } else {
+                let rest = chars.as_str();
+                (vec![flag], Some(rest).filter(|r| r.is_empty() == false))
+            }
+        } else {
+            return Err(format!("Unexpected docker run argument ({})", arg));
+        };
+
+        for flag in flags {
+            if BOOLEAN_FLAGS.contains(&flag) {
+                let enabled = match inline_value {
+                    None | Some("true") => true,
+                    Some("false") => false,
+                    Some(v) => return Err(format!("Invalid value for --{} ({})", flag, v)),
+                };
+                apply_boolean(&mut create, flag, enabled);
+            } else {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .ok_or(format!("Missing value for docker run argument ({})", arg))?,
+                };
+                apply_value(&mut create, flag, value)?;
+            }
+        }
+    }
+
+    Ok(create)
+}
+
+fn apply_boolean(create: &mut CreateContainer, flag: &str, enabled: bool) {
+    match flag {
+        "interactive" => create.open_stdin = enabled,
+        "tty" => create.tty = enabled,
+        "publish-all" => create.host_config.publish_all_ports = enabled,
+        "privileged" => create.host_config.privileged = enabled,
+        "read-only" => create.host_config.readonly_rootfs = enabled,
+        "rm" => create.host_config.auto_remove = enabled,
+        "init" => create.host_config.init = Some(enabled),
+        // Containers are always detached
+        _ => {}
+    }
+}
+
+fn apply_value(create: &mut CreateContainer, flag: &str, value: &str) -> Result<(), String> {
+    let host = &mut create.host_config;
+    match flag {
+        "publish" => {
+            for (binding, port) in parse_port(value)? {
+                create
+                    .exposed_ports
+                    .insert(port.to_owned(), Default::default());
+                host.port_bindings.entry(port).or_default().push(binding);
+            }
+        }
+        "expose" => {
+            let (ports, protocol) = split_protocol(value);
+            let (start, end) =
+                parse_range(ports).ok_or(format!("Invalid exposed port ({})", value))?;
+            for port in start..=end {
+                create
+                    .exposed_ports
+                    .insert(format!("{}/{}", port, protocol), Default::default());
+            }
+        }
+        "volume" => host.binds.push(value.to_owned()),
+        "env" => push_env(&mut create.env, value),
+        "env-file" => {
+            // One variable per line as for --env, blank lines and comments are skipped
+            let contents = std::fs::read_to_string(value)
+                .map_err(|e| format!("Unable to read env file ({}): {}", value, e))?;
+            for line in contents.lines() {
+                let line = line.trim_start();
+                if line.is_empty() == false && line.starts_with('#') == false {
+                    push_env(&mut create.env, line);
+                }
+            }
+        }
+        "label" => {
+            let (key, value) = value.split_once('=').unwrap_or((value, ""));
+            create.labels.insert(key.to_owned(), value.to_owned());
+        }
+        "workdir" => create.working_dir = Some(value.to_owned()),
+        "user" => create.user = Some(value.to_owned()),
+        "hostname" => create.hostname = Some(value.to_owned()),
+        "entrypoint" => create.entrypoint = Some(vec![value.to_owned()]),
+        "restart" => {
+            let (name, retries) = match value.split_once(':') {
+                Some((name, retries)) => (
+                    name,
+                    retries
+                        .parse()
+                        .map_err(|_| format!("Invalid restart policy ({})", value))?,
+                ),
+                None => (value, 0),
+            };
+            if ["no", "always", "unless-stopped", "on-failure"].contains(&name) == false {
+                return Err(format!("Invalid restart policy ({})", value));
+            }
+            host.restart_policy = Some(RestartPolicy {
+                name: name.to_owned(),
+                maximum_retry_count: retries,
+            });
+        }
+        "network" | "net" => host.network_mode = Some(value.to_owned()),
+        "cap-add" => host.cap_add.push(value.to_owned()),
+        "cap-drop" => host.cap_drop.push(value.to_owned()),
+        "device" => {
+            let parts: Vec<&str> = value.split(':').collect();
+            let (path_on_host, path_in_container, permissions) = match parts[..] {
+                [host] => (host, host, "rwm"),
+                [host, container] => (host, container, "rwm"),
+                [host, container, permissions] => (host, container, permissions),
+                _ => return Err(format!("Invalid device ({})", value)),
+            };
+            host.devices.push(DeviceMapping {
+                path_on_host: path_on_host.to_owned(),
+                path_in_container: path_in_container.to_owned(),
+                cgroup_permissions: permissions.to_owned(),
+            });
+        }
+        "add-host" => host.extra_hosts.push(value.to_owned()),
+        "dns" => host.dns.push(value.to_owned()),
+        "log-driver" => {
+            host.log_config
+                .get_or_insert_with(|| LogConfig {
+                    driver: String::new(),
+                    config: Default::default(),
+                })
+                .driver = value.to_owned();
+        }
+        "log-opt" => {
+            let (key, value) = value
+                .split_once('=')
+                .ok_or(format!("Invalid log option ({})", value))?;
+            host.log_config
+                .get_or_insert_with(|| LogConfig {
+                    driver: String::new(),
+                    config: Default::default(),
+                })
+                .config
+                .insert(key.to_owned(), value.to_owned());
+        }
+        "memory" => host.memory = Some(parse_bytes(value)?),
+        "cpus" => {
+            let cpus: f64 = value
+                .parse()
+                .map_err(|_| format!("Invalid cpus ({})", value))?;
+            host.nano_cpus = Some((cpus * 1e9) as i64);
+        }
+        // -1 is unlimited swap
+        "memory-swap" if value == "-1" => host.memory_swap = Some(-1),
+        "memory-swap" => host.memory_swap = Some(parse_bytes(value)?),
+        "memory-reservation" => host.memory_reservation = Some(parse_bytes(value)?),
+        "cpu-shares" => host.cpu_shares = Some(parse_number(flag, value)?),
+        "cpuset-cpus" => host.cpuset_cpus = Some(value.to_owned()),
+        "shm-size" => host.shm_size = Some(parse_bytes(value)?),
+        "pids-limit" => host.pids_limit = Some(parse_number(flag, value)?),
+        "mount" => host.mounts.push(parse_mount(value)?),
+        "tmpfs" => {
+            let (path, options) = value.split_once(':').unwrap_or((value, ""));
+            host.tmpfs.insert(path.to_owned(), options.to_owned());
+        }
+        "ulimit" => {
+            // name=soft[:hard], the hard limit defaults to the soft limit
+            let (name, limits) = value
+                .split_once('=')
+                .ok_or(format!("Invalid ulimit ({})", value))?;
+            let (soft, hard) = limits.split_once(':').unwrap_or((limits, limits));
+            host.ulimits.push(Ulimit {
+                name: name.to_owned(),
+                soft: parse_number(flag, soft)?,
+                hard: parse_number(flag, hard)?,
+            });
+        }
+        "security-opt" => host.security_opt.push(value.to_owned()),
+        "sysctl" => {
+            let (key, value) = value
+                .split_once('=')
+                .ok_or(format!("Invalid sysctl ({})", value))?;
+            host.sysctls.insert(key.to_owned(), value.to_owned());
+        }
+        "gpus" => host.device_requests.push(parse_gpus(value)?),
+        "ipc" => host.ipc_mode = Some(value.to_owned()),
+        "pid" => host.pid_mode = Some(value.to_owned()),
+        "group-add" => host.group_add.push(value.to_owned()),
+        "dns-search" => host.dns_search.push(value.to_owned()),
+        "stop-signal" => create.stop_signal = Some(value.to_owned()),
+        "stop-timeout" => create.stop_timeout = Some(parse_number(flag, value)?),
+        _ => return Err(format!("Unsupported docker run argument (--{})", flag)),
+    }
+
+    Ok(())
+}
+
+/// As with the docker cli a name without a value is taken from our environment
+fn push_env(env: &mut Vec<String>, value: &str) {
+    if value.contains('=') {
+        env.push(value.to_owned());
+    } else if let Ok(v) = std::env::var(value) {
+        env.push(format!("{}={}", value, v));
+    }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<i64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for --{} ({})", flag, value))
+}
+
+/// Parse a publish argument, `[ip:][host_port:]container_port[/protocol]`. Either port can
+/// be a range (i.e. `8000-8010`), a container range is published to a host range of the same
+/// size, or to ports docker chooses if no host ports are given
+fn parse_port(value: &str) -> Result<Vec<(PortBinding, String)>, String> {
+    let invalid = || format!("Invalid port mapping ({})", value);
+
+    // IPv6 host addresses are wrapped in [], split them off first so the : inside is ignored
+    let (host_ip, rest) = match value.strip_prefix('[') {
+        Some(v) => v.split_once("]:").ok_or_else(invalid)?,
+        None => ("", value),
+    };
+
+    let parts: Vec<&str> = rest.split(':').collect();
+    let (host_ip, host_port, container_port) = match parts[..] {
+        [container] if host_ip.is_empty() => ("", "", container),
+        [host, container] if host_ip.is_empty() => ("", host, container),
+        [host, container] => (host_ip, host, container),
+        [ip, host, container] if host_ip.is_empty() => (ip, host, container),
+        _ => return Err(invalid()),
+    };
+
+    let (container_ports, protocol) = split_protocol(container_port);
+    let (start, end) = parse_range(container_ports).ok_or_else(invalid)?;
+    let host_range = match host_port {
+        "" => None,
+        host => Some(parse_range(host).ok_or_else(invalid)?),
+    };
+
+    let binding = |host_port: String| PortBinding {
+        host_ip: host_ip.to_owned(),
+        host_port,
+    };
+    match host_range {
+        // A single container port can be published on any free port in a host range
+        Some(_) if start == end => Ok(vec![(
+            binding(host_port.to_owned()),
+            format!("{}/{}", start, protocol),
+        )]),
+        Some((host_start, host_end)) if host_end - host_start == end - start => Ok((start..=end)
+            .zip(host_start..=host_end)
+            .map(|(port, host)| (binding(host.to_string()), format!("{}/{}", port, protocol)))
+            .collect()),
+        Some(_) => Err(invalid()),
+        None => Ok((start..=end)
+            .map(|port| (binding(String::new()), format!("{}/{}", port, protocol)))
+            .collect()),
+    }
+}
+
+/// A port or range of ports, i.e. `80` or `8000-8010`
+fn parse_range(value: &str) -> Option<(u16, u16)> {
+    let (start, end) = value.split_once('-').unwrap_or((value, value));
+    let (start, end) = (start.parse::<u16>().ok()?, end.parse::<u16>().ok()?);
+    (start <= end).then_some((start, end))
+}
+
+/// Split `port[/protocol]`, the protocol defaulting to tcp
+fn split_protocol(value: &str) -> (&str, &str) {
+    value.split_once('/').unwrap_or((value, "tcp"))
+}
+
+/// Parse a mount argument, comma separated `key=value` options as for `docker run --mount`
+fn parse_mount(value: &str) -> Result<Mount, String> {
+    let invalid = || format!("Invalid mount ({})", value);
+
+    let mut mount = Mount {
+        kind: "volume".to_owned(),
+        ..Default::default()
+    };
+    for option in value.split(',') {
+        let (key, option_value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+        match (key, option_value) {
+            ("type", Some(kind @ ("bind" | "volume" | "tmpfs"))) => mount.kind = kind.to_owned(),
+            ("source" | "src", Some(source)) => mount.source = Some(source.to_owned()),
+            ("target" | "destination" | "dst", Some(target)) => mount.target = target.to_owned(),
+            ("readonly" | "ro", None | Some("true" | "1")) => mount.read_only = true,
+            ("readonly" | "ro", Some("false" | "0")) => mount.read_only = false,
+            ("bind-propagation", Some(propagation)) => {
+                mount.bind_options = Some(MountBindOptions {
+                    propagation: propagation.to_owned(),
+                })
+            }
+            ("tmpfs-size", Some(size)) => {
+                mount
+                    .tmpfs_options
+                    .get_or_insert_with(Default::default)
+                    .size_bytes = Some(parse_bytes(size)?)
+            }
+            ("tmpfs-mode", Some(mode)) => {
+                mount
+                    .tmpfs_options
+                    .get_or_insert_with(Default::default)
+                    .mode = Some(u32::from_str_radix(mode, 8).map_err(|_| invalid())?)
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    if mount.target.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(mount)
+}
+
+/// Parse a gpus argument, `all`, a number of GPUs or `device=` followed by GPU ids
+fn parse_gpus(value: &str) -> Result<DeviceRequest, String> {
+    let value = value.trim_matches('"');
+    let (count, device_ids) = match value {
+        "all" => (-1, vec![]),
+        _ => match value.strip_prefix("device=") {
+            Some(ids) => (0, ids.split(',').map(|id| id.to_owned()).collect()),
+            None => (
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid gpus ({})", value))?,
+                vec![],
+            ),
+        },
+    };
+
+    Ok(DeviceRequest {
+        driver: String::new(),
+        count,
+        device_ids,
+        capabilities: vec![vec!["gpu".to_owned()]],
+    })
+}
+
+/// Parse a memory size, a number optionally followed by b, k, m or g
+pub fn parse_bytes(value: &str) -> Result<i64, String> {
+    let lower = value.to_lowercase();
+    let (number, multiplier) = match lower.chars().last() {
+        Some('b') => (&lower[..lower.len() - 1], 1),
+        Some('k') => (&lower[..lower.len() - 1], 1024),
+        Some('m') => (&lower[..lower.len() - 1], 1024 * 1024),
+        Some('g') => (&lower[..lower.len() - 1], 1024 * 1024 * 1024),
+        _ => (lower.as_str(), 1),
+    };
+
+    number
+        .parse::<i64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or(format!("Invalid memory size ({})", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> CreateContainer {
+        parse(args, "image").unwrap()
+    }
+
+    #[test]
+    fn short_and_long_forms() {
+        for args in [
+            &["-p", "80:8000"][..],
+            &["-p80:8000"],
+            &["--publish", "80:8000"],
+            &["--publish=80:8000"],
+        ] {
+            let create = parse_args(args);
+            let bindings = &create.host_config.port_bindings["8000/tcp"];
+            assert_eq!(bindings.len(), 1);
+            assert_eq!(bindings[0].host_port, "80");
+            assert!(create.exposed_ports.contains_key("8000/tcp"));
+        }
+    }
+
+    #[test]
+    fn grouped_boolean_flags() {
+        let create = parse_args(&["-dit", "--read-only", "--init=false"]);
+        assert!(create.tty);
+        assert!(create.open_stdin);
+        assert!(create.host_config.readonly_rootfs);
+        assert_eq!(create.host_config.init, Some(false));
+
+        let create = parse_args(&["-d", "--tty=false"]);
+        assert!(create.tty == false);
+
+        // A flag taking a value cannot be grouped
+        assert!(parse(&["-dp", "80"], "image").is_err());
+    }
+
+    #[test]
+    fn unsupported_and_missing_values() {
+        assert!(parse(&["--no-such-flag", "1"], "image").is_err());
+        assert!(parse(&["-Z"], "image").is_err());
+        assert!(parse(&["image"], "image").is_err());
+        assert!(parse(&["--memory"], "image").is_err());
+        assert!(parse(&["--rm=maybe"], "image").is_err());
+    }
+
+    #[test]
+    fn port_mappings() {
+        let create = parse_args(&["-p", "127.0.0.1:53:53/udp", "-p", "[::1]:8080:80"]);
+        let bindings = &create.host_config.port_bindings;
+        assert_eq!(bindings["53/udp"][0].host_ip, "127.0.0.1");
+        assert_eq!(bindings["80/tcp"][0].host_ip, "::1");
+        assert_eq!(bindings["80/tcp"][0].host_port, "8080");
+
+        let create = parse_args(&["-p", "8000-8002:9000-9002"]);
+        let bindings = &create.host_config.port_bindings;
+        assert_eq!(bindings.len(), 3);
+        assert_eq!(bindings["9001/tcp"][0].host_port, "8001");
+
+        let create = parse_args(&["-p", "8000-8010:80", "-p", "7000-7001"]);
+        let bindings = &create.host_config.port_bindings;
+        assert_eq!(bindings["80/tcp"][0].host_port, "8000-8010");
+        assert_eq!(bindings["7001/tcp"][0].host_port, "");
+
+        assert!(parse(&["-p", "8000-8001:9000-9002"], "image").is_err());
+        assert!(parse(&["-p", "8010-8000"], "image").is_err());
+        assert!(parse(&["-p", "80:70000"], "image").is_err());
+    }
+
+    #[test]
+    fn mounts_and_limits() {
+        let create = parse_args(&[
+            "--mount",
+            "type=bind,source=/data,target=/srv,readonly",
+            "--mount=type=tmpfs,dst=/cache,tmpfs-size=64m,tmpfs-mode=1770",
+            "--tmpfs",
+            "/run:rw,size=1m",
+            "--ulimit",
+            "nofile=1024:2048",
+            "--ulimit=core=-1",
+            "--shm-size=256m",
+            "--pids-limit",
+            "100",
+            "--sysctl",
+            "net.core.somaxconn=1024",
+            "--security-opt",
+            "no-new-privileges",
+            "--gpus",
+            "all",
+            "--ipc=host",
+            "--pid",
+            "host",
+        ]);
+        let host = &create.host_config;
+
+        assert_eq!(host.mounts[0].kind, "bind");
+        assert_eq!(host.mounts[0].source.as_deref(), Some("/data"));
+        assert!(host.mounts[0].read_only);
+        let tmpfs = host.mounts[1].tmpfs_options.as_ref().unwrap();
+        assert_eq!(tmpfs.size_bytes, Some(64 * 1024 * 1024));
+        assert_eq!(tmpfs.mode, Some(0o1770));
+        assert_eq!(host.tmpfs["/run"], "rw,size=1m");
+        assert_eq!((host.ulimits[0].soft, host.ulimits[0].hard), (1024, 2048));
+        assert_eq!((host.ulimits[1].soft, host.ulimits[1].hard), (-1, -1));
+        assert_eq!(host.shm_size, Some(256 * 1024 * 1024));
+        assert_eq!(host.pids_limit, Some(100));
+        assert_eq!(host.sysctls["net.core.somaxconn"], "1024");
+        assert_eq!(host.security_opt, vec!["no-new-privileges"]);
+        assert_eq!(host.device_requests[0].count, -1);
+        assert_eq!(host.ipc_mode.as_deref(), Some("host"));
+        assert_eq!(host.pid_mode.as_deref(), Some("host"));
+
+        assert!(parse(&["--mount", "type=bind,source=/data"], "image").is_err());
+        assert!(parse(&["--mount", "type=npipe,target=/x"], "image").is_err());
+        assert!(parse(&["--ulimit", "nofile"], "image").is_err());
+    }
+
+    #[test]
+    fn gpus() {
+        let request = parse_gpus("2").unwrap();
+        assert_eq!(request.count, 2);
+        let request = parse_gpus("\"device=0,2\"").unwrap();
+        assert_eq!(request.device_ids, vec!["0", "2"]);
+        assert!(parse_gpus("some").is_err());
+    }
+
+    #[test]
+    fn env_file() {
+        let path = std::env::temp_dir().join(format!("edd-env-test-{}", std::process::id()));
+        std::fs::write(&path, "# comment\n\nA=1\n  B=two words\n").unwrap();
+        let create = parse_args(&["--env-file", path.to_str().unwrap(), "-e", "C=3"]);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(create.env, vec!["A=1", "B=two words", "C=3"]);
+        assert!(parse(&["--env-file", "/no/such/file"], "image").is_err());
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(parse_bytes("512"), Ok(512));
+        assert_eq!(parse_bytes("2k"), Ok(2048));
+        assert_eq!(parse_bytes("1G"), Ok(1024 * 1024 * 1024));
+        assert!(parse_bytes("").is_err());
+        assert!(parse_bytes("1x").is_err());
+        assert!(parse_bytes("9223372036854775807g").is_err());
+    }
+}
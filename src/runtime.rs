@@ -0,0 +1,314 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::stream::BoxStream;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::docker_error::DockerError;
+use crate::docker_structs::*;
+
+/// Shared handle to the container runtime, managed by Rocket and used by the refresher
+pub type SharedRuntime = Arc<dyn ContainerRuntime>;
+
+/// Callback receiving progress messages while an image is loaded or pulled
+pub type Progress<'a> = dyn FnMut(&ProgressMessage) + Send + 'a;
+
+/// Stream of container log output, see ContainerRuntime::get_logs
+pub type LogChunks = BoxStream<'static, Result<LogChunk, DockerError>>;
+
+/// An attached container's output, and its stdin, see ContainerRuntime::attach
+pub struct Attached {
+    pub output: LogChunks,
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+/// Stream of container events, see ContainerRuntime::events
+pub type ContainerEvents = BoxStream<'static, Result<ContainerEvent, DockerError>>;
+
+/// Operations the daemon needs from a container runtime
+///
+/// The Manager and the API only use this trait, so an alternative backend (podman,
+/// containerd, a mock for testing) only needs to implement these to be used in place of
+/// docker. Results use the structs in ./src/docker_structs.rs, other runtimes should map
+/// their responses onto them.
+#[rocket::async_trait]
+pub trait ContainerRuntime: Send + Sync {
+    /// List images available to the runtime
+    async fn get_images(&self) -> Result<Vec<ImageList>, DockerError>;
+
+    /// List containers, including stopped containers, matching all of the given docker list
+    /// filters (i.e. `("label", "ed.managed=true")`) - all containers if there are none
+    async fn get_containers(
+        &self,
+        filters: &[(&str, &str)],
+    ) -> Result<Vec<RunningContainer>, DockerError>;
+
+    /// Get the detailed state of a container
+    async fn inspect_running_container(&self, id: &str) -> Result<InspectContainer, DockerError>;
+
+    /// Get a container's inspect response as docker returns it, for diagnostics
+    async fn inspect_container_json(&self, id: &str) -> Result<serde_json::Value, DockerError>;
+
+    /// Load an image tarball and tag it as new_name, returns the name the image was loaded
+    /// as. If overwrite is false and new_name already points at a different image an
+    /// AlreadyExists error is returned. Progress messages are passed to progress as they arrive
+    ///
+    /// A tarball can hold several images, select names the one to tag and is required when
+    /// there is more than one, see select_loaded_image
+    async fn load_container_image(
+        &self,
+        filename: &str,
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError>;
+
+    /// Load an image tarball as it is read from image (i.e. a request body as it is
+    /// uploaded), follows the same naming, overwrite and progress rules as load_container_image
+    async fn load_container_image_stream(
+        &self,
+        image: &mut (dyn AsyncRead + Send + Unpin),
+        select: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError>;
+
+    /// Pull an image from a registry and tag it as new_name, follows the same overwrite rules
+    /// and progress reporting as load_container_image, auth is sent to the registry if given
+    async fn pull_container_image(
+        &self,
+        image: &str,
+        auth: Option<&AuthConfig>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError>;
+
+    /// Build an image from a tar build context as it is read, and tag it as new_name following
+    /// the same overwrite rules and progress reporting as load_container_image. dockerfile is
+    /// the path of the Dockerfile within the context. Returns the id of the built image
+    async fn build_image(
+        &self,
+        context: &mut (dyn AsyncRead + Send + Unpin),
+        dockerfile: Option<&str>,
+        new_name: &str,
+        overwrite: bool,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, DockerError>;
+
+    /// Version of the runtime and the newest API version it supports
+    async fn version(&self) -> Result<EngineVersion, DockerError>;
+
+    /// Information on the runtime, i.e. whether it is rootless and which limits it supports
+    async fn system_info(&self) -> Result<SystemInfo, DockerError>;
+
+    /// Free space in bytes on the filesystem the runtime stores images on, or None if it
+    /// cannot be determined (i.e. the runtime is on another host)
+    async fn free_space(&self) -> Result<Option<u64>, DockerError>;
+
+    /// Remove an image, fails with a Conflict if it is still tagged elsewhere or in use unless
+    /// forced
+    async fn remove_image(&self, name: &str, force: bool) -> Result<(), DockerError>;
+
+    /// Tag an image as new_name (repo:tag), moving the tag if another image has it
+    async fn tag_image(&self, name: &str, new_name: &str) -> Result<(), DockerError>;
+
+    /// Get information on an image, or None if there is no image with the name
+    async fn inspect_image(&self, name: &str) -> Result<Option<InspectImage>, DockerError>;
+
+    /// Get the layer history of an image, or None if there is no image with the name
+    async fn image_history(&self, name: &str) -> Result<Option<Vec<ImageHistory>>, DockerError>;
+
+    /// Export an image as a tarball to the given file
+    async fn save_image(&self, name: &str, filename: &str) -> Result<(), DockerError>;
+
+    /// Subscribe to container start, stop, die and health status events for the named
+    /// containers, or all containers if names is empty
+    async fn events(&self, names: &[String]) -> Result<ContainerEvents, DockerError>;
+
+    /// Get a single sample of a container's resource usage
+    async fn stats(&self, id: &str) -> Result<ContainerStats, DockerError>;
+
+    /// List all volumes
+    async fn list_volumes(&self) -> Result<Vec<Volume>, DockerError>;
+
+    /// Create a named volume, creating a volume which already exists is not an error
+    async fn create_volume(&self, volume: &CreateVolume) -> Result<Volume, DockerError>;
+
+    /// Remove a volume, force removes it even if it is in use
+    async fn remove_volume(&self, name: &str, force: bool) -> Result<(), DockerError>;
+
+    /// List all networks
+    async fn list_networks(&self) -> Result<Vec<Network>, DockerError>;
+
+    /// Create a network, returning its id
+    async fn create_network(&self, network: &CreateNetwork) -> Result<String, DockerError>;
+
+    /// Attach a container (by id or name) to a network, aliases are extra names the container
+    /// can be reached by from other containers on the network
+    async fn connect_network(
+        &self,
+        network: &str,
+        container: &str,
+        aliases: &[String],
+    ) -> Result<(), DockerError>;
+
+    /// Run a command in a running container, returning its output and exit code. Output past
+    /// EXEC_OUTPUT_LIMIT is discarded, see ExecOutput
+    async fn exec(&self, id: &str, command: &[String]) -> Result<ExecResult, DockerError>;
+
+    /// Get the logs of a container, optionally only the last tail lines and lines since a
+    /// unix timestamp. With follow the stream continues until the container stops
+    async fn get_logs(
+        &self,
+        id: &str,
+        tail: Option<usize>,
+        since: Option<i64>,
+        follow: bool,
+    ) -> Result<LogChunks, DockerError>;
+
+    /// Attach to the stdin, stdout and stderr of a running container, output continues until
+    /// the container stops or both halves are dropped
+    async fn attach(&self, id: &str) -> Result<Attached, DockerError>;
+
+    /// Create and start a named container from image, args are given in docker run format
+    async fn run_container(
+        &self,
+        name: &str,
+        image: &str,
+        args: Vec<&str>,
+    ) -> Result<(), DockerError>;
+
+    /// Start an existing, stopped, container
+    async fn start(&self, id: &str) -> Result<(), DockerError>;
+
+    /// Stop a running container
+    async fn stop_running_container(&self, id: &str) -> Result<(), DockerError>;
+
+    /// Restart a container in place, timeout is the number of seconds to wait for it to stop
+    /// before it is killed
+    async fn restart_container(&self, id: &str, timeout: Option<u64>) -> Result<(), DockerError>;
+
+    /// Wait for a container to stop running, returns the exit code
+    async fn wait_container(&self, id: &str) -> Result<i64, DockerError>;
+
+    /// Send a signal to the main process of a container
+    async fn kill_container(&self, id: &str, signal: &str) -> Result<(), DockerError>;
+
+    /// Rename a container
+    async fn rename_container(&self, id: &str, name: &str) -> Result<(), DockerError>;
+
+    /// Pause or unpause a running container
+    async fn set_paused(&self, id: &str, paused: bool) -> Result<(), DockerError>;
+
+    /// Write a tar archive of a file or directory in a container to the given file
+    async fn get_archive(&self, id: &str, path: &str, filename: &str) -> Result<(), DockerError>;
+
+    /// Extract a tar archive, as it is read, into a directory of a container
+    async fn put_archive(
+        &self,
+        id: &str,
+        path: &str,
+        archive: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), DockerError>;
+
+    /// Change the cpu and memory limits of a container without recreating it
+    async fn update_resources(&self, id: &str, update: &UpdateContainer)
+        -> Result<(), DockerError>;
+
+    /// Remove a stopped container
+    async fn remove_stopped_container(&self, id: &str) -> Result<(), DockerError>;
+}
+
+/// Bytes of each of stdout and stderr kept from a command run with exec
+pub const EXEC_OUTPUT_LIMIT: usize = 1024 * 1024;
+
+/// Collects a command's output as it arrives, keeping the first EXEC_OUTPUT_LIMIT bytes of
+/// each of stdout and stderr. The rest is read and discarded so the command still runs to
+/// completion, a chatty command cannot run the device out of memory
+#[derive(Default)]
+pub struct ExecOutput {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    truncated: bool,
+}
+
+impl ExecOutput {
+    pub fn push(&mut self, source: LogSource, data: &[u8]) {
+        let output = match source {
+            LogSource::Stdout => &mut self.stdout,
+            LogSource::Stderr => &mut self.stderr,
+        };
+        let kept = data.len().min(EXEC_OUTPUT_LIMIT - output.len());
+        output.extend_from_slice(&data[..kept]);
+        self.truncated |= kept < data.len();
+    }
+
+    pub fn finish(self, exit_code: Option<i64>) -> ExecResult {
+        ExecResult {
+            stdout: String::from_utf8_lossy(&self.stdout).into(),
+            stderr: String::from_utf8_lossy(&self.stderr).into(),
+            exit_code,
+            truncated: self.truncated,
+        }
+    }
+}
+
+/// Pick the image to deploy from those named in a load response
+///
+/// With select the image must have been loaded, a name without a tag matches the image tagged
+/// latest. Images without tags are reported by id and can be selected by id, with or without
+/// the sha256: prefix, or by a short id of at least 12 characters. Without select the tarball
+/// must hold exactly one image
+pub fn select_loaded_image(loaded: &[String], select: Option<&str>) -> Result<String, DockerError> {
+    let mut images: Vec<&String> = vec![];
+    for image in loaded {
+        if images.contains(&image) == false {
+            images.push(image);
+        }
+    }
+
+    let list = images
+        .iter()
+        .map(|i| i.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+
+    match select {
+        Some(select) => images
+            .iter()
+            .find(|i| loaded_image_matches(i, select))
+            .map(|i| i.to_string())
+            .ok_or(DockerError::ImageSelection(format!(
+                "Image {} is not in the tarball, it contains: {}",
+                select, list
+            ))),
+        None => match images.len() {
+            0 => Err(DockerError::Other(
+                "Unable to determine loaded image from the load response".into(),
+            )),
+            1 => Ok(images[0].to_owned()),
+            _ => Err(DockerError::ImageSelection(format!(
+                "The tarball contains more than one image ({}), the image to deploy must be chosen",
+                list
+            ))),
+        },
+    }
+}
+
+fn loaded_image_matches(image: &str, select: &str) -> bool {
+    if image == select || image == format!("{}:latest", select) {
+        return true;
+    }
+
+    // An image loaded without tags, "sha256:<64 hex characters>"
+    match image.strip_prefix("sha256:") {
+        Some(id) => {
+            let select = select.strip_prefix("sha256:").unwrap_or(select);
+            select.len() >= 12 && id.starts_with(select)
+        }
+        None => false,
+    }
+}
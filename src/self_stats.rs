@@ -0,0 +1,52 @@
+use std::error::Error;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Resource usage of the daemon process itself
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SelfStats {
+    pub rss_bytes: u64,
+    pub cpu_user_seconds: f64,
+    pub cpu_system_seconds: f64,
+}
+
+/// Clock ticks per second used by /proc/<pid>/stat, fixed at 100 for userspace on Linux
+#[cfg(target_os = "linux")]
+const USER_HZ: f64 = 100.0;
+
+/// Read the memory and CPU usage of this process from procfs
+#[cfg(target_os = "linux")]
+pub fn read() -> Result<SelfStats, Box<dyn Error + Send + Sync>> {
+    // VmRSS is reported in kB
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    let rss_kb: u64 = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+        .ok_or("Unable to find VmRSS in /proc/self/status")?;
+
+    // The process name (2nd field) may contain spaces, so split after the closing bracket,
+    // utime and stime are then the 12th and 13th fields
+    let stat = std::fs::read_to_string("/proc/self/stat")?;
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .ok_or("Unable to parse /proc/self/stat")?;
+    if fields.len() < 13 {
+        return Err("Unable to parse /proc/self/stat".into());
+    }
+    let utime: u64 = fields[11].parse()?;
+    let stime: u64 = fields[12].parse()?;
+
+    Ok(SelfStats {
+        rss_bytes: rss_kb * 1024,
+        cpu_user_seconds: utime as f64 / USER_HZ,
+        cpu_system_seconds: stime as f64 / USER_HZ,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read() -> Result<SelfStats, Box<dyn Error + Send + Sync>> {
+    Err("Process statistics are only supported on Linux".into())
+}
@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use utoipa::ToSchema;
+
+/// Length of upload ids in hex characters
+const ID_LENGTH: usize = 32;
+
+/// A partial upload of an image tarball, kept on disk so an interrupted transfer can resume
+/// from `offset`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Upload {
+    pub id: String,
+    pub deployment: String,
+    /// Bytes received so far, the next chunk must start here
+    #[serde(default)]
+    pub offset: u64,
+    /// Unix timestamp
+    pub created: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("No such upload")]
+    NotFound,
+    #[error("Chunk starts at {given} but the upload is at {offset}")]
+    Offset { given: u64, offset: u64 },
+    #[error("A chunk is being appended to the upload, or it is being committed")]
+    Busy,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Uploads in progress, each is a `<id>.tar` holding the data received so far and a
+/// `<id>.json` describing it
+pub struct Uploads {
+    dir: PathBuf,
+    expiry: Duration,
+    /// Uploads with a chunk being appended, or being committed
+    busy: Mutex<HashSet<String>>,
+}
+
+impl Uploads {
+    pub fn new(dir: &Path, expiry: Duration) -> Self {
+        Uploads {
+            dir: dir.to_owned(),
+            expiry,
+            busy: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Start a new upload for a deployment, uploads which have expired are removed first
+    pub async fn create(&self, deployment: &str) -> io::Result<Upload> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        self.expire().await;
+
+        let mut id = [0u8; ID_LENGTH / 2];
+        rand::thread_rng().fill_bytes(&mut id);
+        let upload = Upload {
+            id: id.iter().map(|b| format!("{:02x}", b)).collect(),
+            deployment: deployment.to_owned(),
+            offset: 0,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+
+        tokio::fs::File::create(self.data_path(&upload.id)).await?;
+        tokio::fs::write(
+            self.dir.join(format!("{}.json", upload.id)),
+            serde_json::to_vec(&upload)?,
+        )
+        .await?;
+        Ok(upload)
+    }
+
+    /// The upload with its current offset, None if it does not exist
+    pub async fn get(&self, id: &str) -> io::Result<Option<Upload>> {
+        if valid_id(id) == false {
+            return Ok(None);
+        }
+
+        let mut upload: Upload = match tokio::fs::read(self.dir.join(format!("{}.json", id))).await
+        {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        upload.offset = tokio::fs::metadata(self.data_path(id)).await?.len();
+        Ok(Some(upload))
+    }
+
+    /// Append a chunk which must start at the upload's current offset. Whatever part of the
+    /// chunk arrives is kept, so a chunk cut short can be resumed from the new offset
+    pub async fn append(
+        &self,
+        id: &str,
+        offset: u64,
+        chunk: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<Upload, UploadError> {
+        let _busy = self.mark_busy(id)?;
+        self.append_chunk(id, offset, chunk).await
+    }
+
+    /// Mark the upload as busy while it is loaded, so no chunk can be appended to the data
+    /// docker is reading. Busy if a chunk is being appended or it is already being committed
+    pub fn begin_commit<'a>(&'a self, id: &'a str) -> Result<Busy<'a>, UploadError> {
+        self.mark_busy(id)
+    }
+
+    fn mark_busy<'a>(&'a self, id: &'a str) -> Result<Busy<'a>, UploadError> {
+        if self.busy.lock().unwrap().insert(id.to_owned()) == false {
+            return Err(UploadError::Busy);
+        }
+        Ok(Busy {
+            uploads: &self.busy,
+            id,
+        })
+    }
+
+    async fn append_chunk(
+        &self,
+        id: &str,
+        offset: u64,
+        chunk: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<Upload, UploadError> {
+        let mut upload = self.get(id).await?.ok_or(UploadError::NotFound)?;
+        if offset != upload.offset {
+            return Err(UploadError::Offset {
+                given: offset,
+                offset: upload.offset,
+            });
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(self.data_path(id))
+            .await?;
+        let copied = tokio::io::copy(chunk, &mut file).await;
+        file.flush().await?;
+
+        upload.offset = file.metadata().await?.len();
+        copied?;
+        Ok(upload)
+    }
+
+    /// Where the data of an upload is kept
+    pub fn data_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.tar", id))
+    }
+
+    pub async fn remove(&self, id: &str) {
+        if valid_id(id) {
+            tokio::fs::remove_file(self.data_path(id)).await.ok();
+            tokio::fs::remove_file(self.dir.join(format!("{}.json", id)))
+                .await
+                .ok();
+        }
+    }
+
+    /// Remove uploads which have not been added to within the expiry time, returning the data
+    /// files removed and the bytes freed
+    async fn expire(&self) -> (Vec<PathBuf>, u64) {
+        let mut removed = vec![];
+        let mut freed = 0;
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(_) => return (removed, freed),
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let id = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(id) if path.extension().map(|e| e == "tar") == Some(true) => id.to_owned(),
+                _ => continue,
+            };
+            let metadata = match entry.metadata().await {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let idle = metadata.modified().ok().and_then(|m| m.elapsed().ok());
+            if idle.map(|i| i > self.expiry) == Some(true) {
+                println!("Removing expired upload {}", id);
+                self.remove(&id).await;
+                removed.push(path);
+                freed += metadata.len();
+            }
+        }
+
+        (removed, freed)
+    }
+
+    /// Remove expired uploads and the halves of uploads missing the other, i.e. the data of an
+    /// upload whose description was lost in a crash. Files not named by an upload id are left
+    /// alone, the directory may be shared. Returns the files removed and the bytes freed
+    pub async fn prune(&self) -> (Vec<PathBuf>, u64) {
+        let (mut removed, mut freed) = self.expire().await;
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(_) => return (removed, freed),
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let id = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(id) if valid_id(id) => id.to_owned(),
+                _ => continue,
+            };
+            let complete = match path.extension().and_then(|e| e.to_str()) {
+                Some("tar") => self.dir.join(format!("{}.json", id)).exists(),
+                Some("json") => self.data_path(&id).exists(),
+                _ => false,
+            };
+            if complete || self.busy.lock().unwrap().contains(&id) {
+                continue;
+            }
+
+            let size = entry.metadata().await.map(|m| m.len()).unwrap_or_default();
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {
+                    freed += size;
+                    removed.push(path);
+                }
+                Err(e) => println!("Unable to remove {}: {}", path.display(), e),
+            }
+        }
+
+        (removed, freed)
+    }
+}
+
+/// Marks an upload as no longer busy when dropped, including when the request is abandoned
+pub struct Busy<'a> {
+    uploads: &'a Mutex<HashSet<String>>,
+    id: &'a str,
+}
+
+impl Drop for Busy<'_> {
+    fn drop(&mut self) {
+        self.uploads.lock().unwrap().remove(self.id);
+    }
+}
+
+/// Ids are only ever hex, anything else could escape the upload directory
+fn valid_id(id: &str) -> bool {
+    id.len() == ID_LENGTH && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rocket::async_test]
+    async fn commit_excludes_chunks() {
+        let dir = std::env::temp_dir().join(format!("edd-uploads-commit-{}", std::process::id()));
+        let uploads = Uploads::new(&dir, Duration::from_secs(60 * 60));
+        let upload = uploads.create("website").await.unwrap();
+
+        let committing = uploads.begin_commit(&upload.id).unwrap();
+        let appended = uploads.append(&upload.id, 0, &mut &b"data"[..]).await;
+        assert!(matches!(appended, Err(UploadError::Busy)));
+        assert!(matches!(
+            uploads.begin_commit(&upload.id),
+            Err(UploadError::Busy)
+        ));
+
+        // Released once the commit is over
+        drop(committing);
+        let appended = uploads.append(&upload.id, 0, &mut &b"data"[..]).await;
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(appended.unwrap().offset, 4);
+    }
+
+    #[rocket::async_test]
+    async fn prune_keeps_other_files() {
+        let dir = std::env::temp_dir().join(format!("edd-uploads-test-{}", std::process::id()));
+        let uploads = Uploads::new(&dir, Duration::from_secs(60 * 60));
+        let upload = uploads.create("website").await.unwrap();
+
+        // Shared with the daemon's other files, and the data of an upload which lost its json
+        std::fs::write(dir.join("deployments.json"), "[]").unwrap();
+        std::fs::write(dir.join("history.json"), "{}").unwrap();
+        std::fs::write(dir.join("notes.tar"), "").unwrap();
+        let orphan = dir.join(format!("{}.tar", "0".repeat(ID_LENGTH)));
+        std::fs::write(&orphan, "data").unwrap();
+
+        let (removed, freed) = uploads.prune().await;
+        let remaining: HashSet<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(removed, vec![orphan]);
+        assert_eq!(freed, 4);
+        assert_eq!(
+            remaining,
+            HashSet::from([
+                "deployments.json".to_owned(),
+                "history.json".to_owned(),
+                "notes.tar".to_owned(),
+                format!("{}.tar", upload.id),
+                format!("{}.json", upload.id),
+            ])
+        );
+    }
+}
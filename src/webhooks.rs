@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Body, Client, Method, Request, Uri};
+use ring::hmac;
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::manager::{Change, DeploymentChange};
+
+/// Attempts to deliver each change to a webhook, the delay between attempts starts at
+/// RETRY_DELAY and doubles after each failure
+const ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Time allowed for a webhook to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header holding the hex HMAC-SHA256 of the body, when the webhook has a secret
+const SIGNATURE_HEADER: &str = "X-Edd-Signature";
+
+/// A url changes to deployments are POSTed to
+#[derive(Debug, Clone, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Key the body is signed with, the receiver checks the signature to trust the change
+    pub secret: Option<String>,
+    /// The changes sent, every change if not set
+    pub events: Option<Vec<Change>>,
+}
+
+impl Webhook {
+    pub fn validate(&self) -> Result<(), String> {
+        let uri: Uri = self
+            .url
+            .parse()
+            .map_err(|e| format!("Invalid webhook url ({}): {}", self.url, e))?;
+        match uri.scheme_str() {
+            Some("http" | "https") if uri.host().is_some() => Ok(()),
+            _ => Err(format!(
+                "webhook url ({}) must be a http:// or https:// url",
+                self.url
+            )),
+        }
+    }
+
+    fn wants(&self, change: &DeploymentChange) -> bool {
+        self.events
+            .as_ref()
+            .map(|e| e.contains(&change.event))
+            .unwrap_or(true)
+    }
+}
+
+/// Sends every deployment change to the webhooks interested in it. Each delivery runs on its
+/// own, so a slow or unreachable webhook does not hold up the others
+pub async fn webhook_loop(
+    webhooks: Vec<Webhook>,
+    mut changes: broadcast::Receiver<DeploymentChange>,
+) {
+    let webhooks: Vec<Arc<Webhook>> = webhooks.into_iter().map(Arc::new).collect();
+    loop {
+        let change = match changes.recv().await {
+            Ok(change) => change,
+            Err(RecvError::Lagged(missed)) => {
+                println!(
+                    "Webhooks fell behind, {} deployment changes not sent",
+                    missed
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let body = match serde_json::to_vec(&change) {
+            Ok(body) => Arc::new(body),
+            Err(_) => continue,
+        };
+        for webhook in webhooks.iter().filter(|w| w.wants(&change)) {
+            tokio::spawn(deliver(webhook.clone(), body.clone()));
+        }
+    }
+}
+
+async fn deliver(webhook: Arc<Webhook>, body: Arc<Vec<u8>>) {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client: Client<_, Body> = Client::builder().build(connector);
+    let signature = webhook.secret.as_ref().map(|secret| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let tag = hmac::sign(&key, &body);
+        let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("sha256={}", hex)
+    });
+
+    let mut delay = RETRY_DELAY;
+    for attempt in 1..=ATTEMPTS {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(&webhook.url)
+            .header(hyper::header::CONTENT_TYPE, "application/json");
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let request = match request.body(Body::from(body.as_ref().clone())) {
+            Ok(request) => request,
+            Err(e) => {
+                println!("Unable to send webhook to {}: {}", webhook.url, e);
+                return;
+            }
+        };
+
+        let failure = match tokio::time::timeout(TIMEOUT, client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => return,
+            Ok(Ok(response)) => format!("status was {}", response.status()),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".into(),
+        };
+
+        if attempt == ATTEMPTS {
+            println!(
+                "Unable to send webhook to {}, giving up after {} attempts: {}",
+                webhook.url, ATTEMPTS, failure
+            );
+            return;
+        }
+        warn!(
+            "Unable to send webhook to {}, retrying in {}s: {}",
+            webhook.url,
+            delay.as_secs(),
+            failure
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
@@ -0,0 +1,48 @@
+use rocket::data::IoStream;
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+use tokio_tungstenite::WebSocketStream;
+
+/// Largest message accepted from a client, console input arrives a few bytes at a time
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Request guard for WebSocket upgrade requests, holding the Sec-WebSocket-Accept value for
+/// the response. Requests which are not an upgrade are rejected with 426 Upgrade Required
+pub struct WebSocketKey {
+    pub accept: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocketKey {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let upgrade = request
+            .headers()
+            .get("upgrade")
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim().eq_ignore_ascii_case("websocket"));
+
+        match request.headers().get_one("sec-websocket-key") {
+            Some(key) if upgrade => Outcome::Success(WebSocketKey {
+                accept: derive_accept_key(key.trim().as_bytes()),
+            }),
+            _ => Outcome::Error((Status::UpgradeRequired, ())),
+        }
+    }
+}
+
+/// The WebSocket on a connection upgraded by a response with the WebSocketKey's accept value.
+/// Pings from the client are answered as messages are read
+pub async fn accept(io: IoStream) -> WebSocketStream<IoStream> {
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..Default::default()
+    };
+
+    WebSocketStream::from_raw_socket(io, Role::Server, Some(config)).await
+}